@@ -1,4 +1,6 @@
 use std::env;
+use std::fs;
+use std::path::PathBuf;
 
 macro_rules! set_rustc_env_var {
 	($name: expr, $value: expr) => {{
@@ -17,7 +19,40 @@ fn main() {
 		}
 		set_rustc_env_var!("ACMED_TLS_LIB_NAME", "OpenSSL");
 	}
+	if env::var("CARGO_FEATURE_PQC").is_ok() && env::var("CARGO_FEATURE_CRYPTO_OPENSSL").is_ok() {
+		println!(
+			"cargo:warning=post-quantum key types are only available with the RustCrypto backend"
+		);
+	}
 	if env::var("DEP_OPENSSL_LIBRESSL_VERSION_NUMBER").is_ok() {
 		set_rustc_env_var!("ACMED_TLS_LIB_NAME", "LibreSSL");
 	}
+	if env::var("CARGO_FEATURE_CRYPTO_RUSTCRYPTO").is_ok()
+		&& env::var("CARGO_FEATURE_CRYPTO_OPENSSL").is_err()
+	{
+		println!("cargo:rustc-cfg=feature=\"ed25519\"");
+		if env::var("CARGO_FEATURE_PQC").is_ok() {
+			println!("cargo:rustc-cfg=feature=\"ml_dsa\"");
+		}
+		set_rustc_env_var!("ACMED_TLS_LIB_NAME", "RustCrypto");
+		set_rustc_env_var!("ACMED_TLS_LIB_VERSION", get_lock_version("x509-cert"));
+	}
+}
+
+/// Return the version of a package as recorded in the workspace's lock file.
+fn get_lock_version(package: &str) -> String {
+	let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+	let lock_file = manifest_dir.join("..").join("Cargo.lock");
+	let name_line = format!("name = \"{package}\"");
+	if let Ok(lock) = fs::read_to_string(lock_file) {
+		let mut lines = lock.lines();
+		while let Some(line) = lines.next() {
+			if line == name_line {
+				if let Some(version) = lines.next().and_then(|l| l.strip_prefix("version = ")) {
+					return version.trim_matches('"').to_string();
+				}
+			}
+		}
+	}
+	"unknown".to_string()
 }
@@ -2,11 +2,15 @@ use crate::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
+mod encrypted_data;
 mod jws_signature_algorithm;
 mod key_type;
+mod key_usage;
 #[cfg(feature = "crypto_openssl")]
 mod openssl_certificate;
 #[cfg(feature = "crypto_openssl")]
+mod openssl_cipher;
+#[cfg(feature = "crypto_openssl")]
 mod openssl_hash;
 #[cfg(feature = "crypto_openssl")]
 mod openssl_keys;
@@ -14,12 +18,38 @@ mod openssl_keys;
 mod openssl_subject_attribute;
 #[cfg(feature = "crypto_openssl")]
 mod openssl_version;
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_certificate;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_cipher;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_hash;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_keys;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_subject_attribute;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustcrypto_version;
+mod traits;
+#[cfg(any(feature = "crypto_openssl", feature = "crypto_rustcrypto"))]
+mod x509_extensions;
+#[cfg(any(
+	all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")),
+	feature = "pkcs11"
+))]
+mod x509_request;
 
 const APP_ORG: &str = "ACMEd";
 const APP_NAME: &str = "ACMEd";
+#[cfg(feature = "crypto_openssl")]
 const X509_VERSION: i32 = 0x02;
 const CRT_SERIAL_NB_BITS: i32 = 32;
 const INVALID_EXT_MSG: &str = "invalid acmeIdentifier extension";
+const AES_256_KEY_LEN: usize = 32;
+pub(crate) const PBKDF2_ITERATIONS: u32 = 600_000;
+const PBKDF2_SALT_LEN: usize = 16;
 pub const CRT_NB_DAYS_VALIDITY: u32 = 7;
 
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
@@ -79,8 +109,28 @@ impl fmt::Display for BaseHashFunction {
 	}
 }
 
+/// Remove the `pin-value` and `pin-source` query attributes from a PKCS #11
+/// URI, so that it can be stored or displayed without disclosing the PIN.
+pub fn pkcs11_uri_without_pin(uri: &str) -> String {
+	let (path, query) = match uri.split_once('?') {
+		Some(parts) => parts,
+		None => return uri.to_string(),
+	};
+	let query: Vec<&str> = query
+		.split('&')
+		.filter(|a| !a.is_empty() && !a.starts_with("pin-value=") && !a.starts_with("pin-source="))
+		.collect();
+	if query.is_empty() {
+		path.to_string()
+	} else {
+		format!("{path}?{}", query.join("&"))
+	}
+}
+
+pub use encrypted_data::{decrypt_data, encrypt_data, is_encrypted_data};
 pub use jws_signature_algorithm::JwsSignatureAlgorithm;
 pub use key_type::KeyType;
+pub use key_usage::{ExtendedKeyUsage, KeyUsage};
 #[cfg(feature = "crypto_openssl")]
 pub use openssl_certificate::{Csr, X509Certificate};
 #[cfg(feature = "crypto_openssl")]
@@ -91,3 +141,20 @@ pub use openssl_keys::{gen_keypair, KeyPair};
 pub use openssl_subject_attribute::SubjectAttribute;
 #[cfg(feature = "crypto_openssl")]
 pub use openssl_version::{get_lib_name, get_lib_version};
+#[cfg(feature = "pkcs11")]
+pub use pkcs11::Pkcs11Uri;
+#[cfg(all(test, feature = "pkcs11"))]
+pub(crate) use pkcs11::{ecdsa_signature_to_der, Pkcs11PublicKey};
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+pub use rustcrypto_certificate::{Csr, X509Certificate};
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+pub use rustcrypto_hash::HashFunction;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+pub use rustcrypto_keys::{gen_keypair, KeyPair};
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+pub use rustcrypto_subject_attribute::SubjectAttribute;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+pub use rustcrypto_version::{get_lib_name, get_lib_version};
+pub use traits::{CertificateInspector, CsrBuilder, CsrInspector, KeyGenerator, Signer};
+#[cfg(all(test, feature = "pkcs11", feature = "crypto_openssl"))]
+pub(crate) use x509_request::build_cert_req;
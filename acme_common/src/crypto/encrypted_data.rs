@@ -0,0 +1,78 @@
+//! Passphrase-based encryption of arbitrary data.
+//!
+//! The encrypted data is made of a header followed by the AES-256-GCM
+//! ciphertext and its authentication tag. The encryption key is derived from
+//! the passphrase using PBKDF2-HMAC-SHA256. The header is authenticated as
+//! additional data.
+//!
+//! | field      | size (bytes) |
+//! |------------|--------------|
+//! | magic      | 8            |
+//! | version    | 1            |
+//! | iterations | 4 (BE)       |
+//! | salt       | 16           |
+//! | nonce      | 12           |
+
+#[cfg(feature = "crypto_openssl")]
+use super::openssl_cipher::{aes_256_gcm_decrypt, aes_256_gcm_encrypt, derive_key, random_bytes};
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+use super::rustcrypto_cipher::{
+	aes_256_gcm_decrypt, aes_256_gcm_encrypt, derive_key, random_bytes,
+};
+use super::{PBKDF2_ITERATIONS, PBKDF2_SALT_LEN};
+use crate::error::Error;
+
+const MAGIC: &[u8; 8] = b"ACMEDENC";
+const VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 + PBKDF2_SALT_LEN + NONCE_LEN;
+// The iteration count is read from the data, which should not be able to
+// stall the key derivation.
+const MAX_ITERATIONS: u32 = 10 * PBKDF2_ITERATIONS;
+
+/// Check whether the data has been produced by `encrypt_data`.
+pub fn is_encrypted_data(data: &[u8]) -> bool {
+	data.starts_with(MAGIC)
+}
+
+pub fn encrypt_data(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+	let mut salt = [0u8; PBKDF2_SALT_LEN];
+	let mut nonce = [0u8; NONCE_LEN];
+	random_bytes(&mut salt)?;
+	random_bytes(&mut nonce)?;
+	let mut res = Vec::with_capacity(HEADER_LEN + data.len() + 16);
+	res.extend_from_slice(MAGIC);
+	res.push(VERSION);
+	res.extend_from_slice(&PBKDF2_ITERATIONS.to_be_bytes());
+	res.extend_from_slice(&salt);
+	res.extend_from_slice(&nonce);
+	let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS)?;
+	let ciphertext = aes_256_gcm_encrypt(&key, &nonce, &res, data)?;
+	res.extend_from_slice(&ciphertext);
+	Ok(res)
+}
+
+pub fn decrypt_data(passphrase: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+	if !is_encrypted_data(data) || data.len() < HEADER_LEN {
+		return Err("invalid encrypted data".into());
+	}
+	let (header, ciphertext) = data.split_at(HEADER_LEN);
+	let version = header[MAGIC.len()];
+	if version != VERSION {
+		let msg = format!("{version}: unsupported encrypted data version");
+		return Err(msg.into());
+	}
+	let pos = MAGIC.len() + 1;
+	let mut iterations = [0u8; 4];
+	iterations.copy_from_slice(&header[pos..pos + 4]);
+	let iterations = u32::from_be_bytes(iterations);
+	if iterations == 0 || iterations > MAX_ITERATIONS {
+		let msg = format!("invalid encrypted data: {iterations}: invalid iteration count");
+		return Err(msg.into());
+	}
+	let pos = pos + 4;
+	let salt = &header[pos..pos + PBKDF2_SALT_LEN];
+	let nonce = &header[pos + PBKDF2_SALT_LEN..];
+	let key = derive_key(passphrase, salt, iterations)?;
+	aes_256_gcm_decrypt(&key, nonce, header, ciphertext)
+}
@@ -2,12 +2,17 @@ use crate::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub enum JwsSignatureAlgorithm {
 	Hs256,
 	Hs384,
 	Hs512,
 	Rs256,
+	Rs384,
+	Rs512,
+	Ps256,
+	Ps384,
+	Ps512,
 	Es256,
 	Es384,
 	Es512,
@@ -15,6 +20,12 @@ pub enum JwsSignatureAlgorithm {
 	Ed25519,
 	#[cfg(feature = "ed448")]
 	Ed448,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa44,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa65,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa87,
 }
 
 impl FromStr for JwsSignatureAlgorithm {
@@ -26,6 +37,11 @@ impl FromStr for JwsSignatureAlgorithm {
 			"hs384" => Ok(JwsSignatureAlgorithm::Hs384),
 			"hs512" => Ok(JwsSignatureAlgorithm::Hs512),
 			"rs256" => Ok(JwsSignatureAlgorithm::Rs256),
+			"rs384" => Ok(JwsSignatureAlgorithm::Rs384),
+			"rs512" => Ok(JwsSignatureAlgorithm::Rs512),
+			"ps256" => Ok(JwsSignatureAlgorithm::Ps256),
+			"ps384" => Ok(JwsSignatureAlgorithm::Ps384),
+			"ps512" => Ok(JwsSignatureAlgorithm::Ps512),
 			"es256" => Ok(JwsSignatureAlgorithm::Es256),
 			"es384" => Ok(JwsSignatureAlgorithm::Es384),
 			"es512" => Ok(JwsSignatureAlgorithm::Es512),
@@ -33,6 +49,12 @@ impl FromStr for JwsSignatureAlgorithm {
 			"ed25519" => Ok(JwsSignatureAlgorithm::Ed25519),
 			#[cfg(feature = "ed448")]
 			"ed448" => Ok(JwsSignatureAlgorithm::Ed448),
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-44" => Ok(JwsSignatureAlgorithm::MlDsa44),
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-65" => Ok(JwsSignatureAlgorithm::MlDsa65),
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-87" => Ok(JwsSignatureAlgorithm::MlDsa87),
 			_ => Err(format!("{s}: unknown algorithm.").into()),
 		}
 	}
@@ -45,6 +67,11 @@ impl fmt::Display for JwsSignatureAlgorithm {
 			JwsSignatureAlgorithm::Hs384 => "HS384",
 			JwsSignatureAlgorithm::Hs512 => "HS512",
 			JwsSignatureAlgorithm::Rs256 => "RS256",
+			JwsSignatureAlgorithm::Rs384 => "RS384",
+			JwsSignatureAlgorithm::Rs512 => "RS512",
+			JwsSignatureAlgorithm::Ps256 => "PS256",
+			JwsSignatureAlgorithm::Ps384 => "PS384",
+			JwsSignatureAlgorithm::Ps512 => "PS512",
 			JwsSignatureAlgorithm::Es256 => "ES256",
 			JwsSignatureAlgorithm::Es384 => "ES384",
 			JwsSignatureAlgorithm::Es512 => "ES512",
@@ -52,6 +79,12 @@ impl fmt::Display for JwsSignatureAlgorithm {
 			JwsSignatureAlgorithm::Ed25519 => "Ed25519",
 			#[cfg(feature = "ed448")]
 			JwsSignatureAlgorithm::Ed448 => "Ed448",
+			#[cfg(feature = "ml_dsa")]
+			JwsSignatureAlgorithm::MlDsa44 => "ML-DSA-44",
+			#[cfg(feature = "ml_dsa")]
+			JwsSignatureAlgorithm::MlDsa65 => "ML-DSA-65",
+			#[cfg(feature = "ml_dsa")]
+			JwsSignatureAlgorithm::MlDsa87 => "ML-DSA-87",
 		};
 		write!(f, "{s}")
 	}
@@ -78,4 +111,20 @@ mod tests {
 		let a = JwsSignatureAlgorithm::Es256;
 		assert_eq!(a.to_string().as_str(), "ES256");
 	}
+
+	#[test]
+	fn test_rsa_from_str() {
+		let variants = [
+			("RS384", JwsSignatureAlgorithm::Rs384),
+			("rs512", JwsSignatureAlgorithm::Rs512),
+			("PS256", JwsSignatureAlgorithm::Ps256),
+			("Ps384", JwsSignatureAlgorithm::Ps384),
+			("ps512", JwsSignatureAlgorithm::Ps512),
+		];
+		for (s, alg) in variants.iter() {
+			let a = JwsSignatureAlgorithm::from_str(s).unwrap();
+			assert_eq!(a, *alg);
+			assert_eq!(a.to_string(), s.to_uppercase());
+		}
+	}
 }
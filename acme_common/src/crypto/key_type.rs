@@ -3,10 +3,17 @@ use crate::error::Error;
 use std::fmt;
 use std::str::FromStr;
 
+const RSA_MIN_SIZE: u32 = 2048;
+const RSA_MAX_SIZE: u32 = 8192;
+const RSA_SIZE_STEP: u32 = 1024;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum KeyType {
 	Rsa2048,
+	Rsa3072,
 	Rsa4096,
+	/// RSA key of any other size, in bits.
+	Rsa(u32),
 	EcdsaP256,
 	EcdsaP384,
 	EcdsaP521,
@@ -14,12 +21,48 @@ pub enum KeyType {
 	Ed25519,
 	#[cfg(feature = "ed448")]
 	Ed448,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa44,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa65,
+	#[cfg(feature = "ml_dsa")]
+	MlDsa87,
 }
 
 impl KeyType {
+	/// Return the RSA key type having the given modulus size, in bits. Only
+	/// multiples of 1024 bits are supported.
+	pub fn from_rsa_size(nb_bits: u32) -> Result<Self, Error> {
+		match nb_bits {
+			2048 => Ok(KeyType::Rsa2048),
+			3072 => Ok(KeyType::Rsa3072),
+			4096 => Ok(KeyType::Rsa4096),
+			n if (RSA_MIN_SIZE..=RSA_MAX_SIZE).contains(&n) && n % RSA_SIZE_STEP == 0 => {
+				Ok(KeyType::Rsa(n))
+			}
+			n => Err(format!(
+				"{n}: unsupported RSA key size, it must be a multiple of {RSA_SIZE_STEP} between {RSA_MIN_SIZE} and {RSA_MAX_SIZE} bits"
+			)
+			.into()),
+		}
+	}
+
+	/// Return the modulus size, in bits, of RSA key types.
+	pub fn rsa_size(&self) -> Option<u32> {
+		match self {
+			KeyType::Rsa2048 => Some(2048),
+			KeyType::Rsa3072 => Some(3072),
+			KeyType::Rsa4096 => Some(4096),
+			KeyType::Rsa(n) => Some(*n),
+			_ => None,
+		}
+	}
+
 	pub fn get_default_signature_alg(&self) -> JwsSignatureAlgorithm {
 		match self {
-			KeyType::Rsa2048 | KeyType::Rsa4096 => JwsSignatureAlgorithm::Rs256,
+			KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 | KeyType::Rsa(_) => {
+				JwsSignatureAlgorithm::Rs256
+			}
 			KeyType::EcdsaP256 => JwsSignatureAlgorithm::Es256,
 			KeyType::EcdsaP384 => JwsSignatureAlgorithm::Es384,
 			KeyType::EcdsaP521 => JwsSignatureAlgorithm::Es512,
@@ -27,12 +70,28 @@ impl KeyType {
 			KeyType::Ed25519 => JwsSignatureAlgorithm::Ed25519,
 			#[cfg(feature = "ed448")]
 			KeyType::Ed448 => JwsSignatureAlgorithm::Ed448,
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa44 => JwsSignatureAlgorithm::MlDsa44,
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa65 => JwsSignatureAlgorithm::MlDsa65,
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa87 => JwsSignatureAlgorithm::MlDsa87,
 		}
 	}
 
 	pub fn check_alg_compatibility(&self, alg: &JwsSignatureAlgorithm) -> Result<(), Error> {
 		let ok = match self {
-			KeyType::Rsa2048 | KeyType::Rsa4096 => *alg == JwsSignatureAlgorithm::Rs256,
+			KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 | KeyType::Rsa(_) => {
+				matches!(
+					alg,
+					JwsSignatureAlgorithm::Rs256
+						| JwsSignatureAlgorithm::Rs384
+						| JwsSignatureAlgorithm::Rs512
+						| JwsSignatureAlgorithm::Ps256
+						| JwsSignatureAlgorithm::Ps384
+						| JwsSignatureAlgorithm::Ps512
+				)
+			}
 			KeyType::EcdsaP256 | KeyType::EcdsaP384 | KeyType::EcdsaP521 => {
 				*alg == self.get_default_signature_alg()
 			}
@@ -40,6 +99,10 @@ impl KeyType {
 			KeyType::Ed25519 => *alg == self.get_default_signature_alg(),
 			#[cfg(feature = "ed448")]
 			KeyType::Ed448 => *alg == self.get_default_signature_alg(),
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa44 | KeyType::MlDsa65 | KeyType::MlDsa87 => {
+				*alg == self.get_default_signature_alg()
+			}
 		};
 		if ok {
 			Ok(())
@@ -54,6 +117,7 @@ impl KeyType {
 	pub fn list_possible_values() -> Vec<&'static str> {
 		vec![
 			"rsa2048",
+			"rsa3072",
 			"rsa4096",
 			"ecdsa-p256",
 			"ecdsa-p384",
@@ -62,6 +126,12 @@ impl KeyType {
 			"ed25519",
 			#[cfg(feature = "ed448")]
 			"ed448",
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-44",
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-65",
+			#[cfg(feature = "ml_dsa")]
+			"ml-dsa-87",
 		]
 	}
 }
@@ -70,9 +140,14 @@ impl FromStr for KeyType {
 	type Err = Error;
 
 	fn from_str(s: &str) -> Result<Self, Error> {
-		match s.to_lowercase().replace('-', "_").as_str() {
-			"rsa2048" => Ok(KeyType::Rsa2048),
-			"rsa4096" => Ok(KeyType::Rsa4096),
+		let s = s.to_lowercase().replace('-', "_");
+		if let Some(nb_bits) = s.strip_prefix("rsa") {
+			return match nb_bits.parse::<u32>() {
+				Ok(nb_bits) => KeyType::from_rsa_size(nb_bits),
+				Err(_) => Err(format!("{s}: unknown algorithm").into()),
+			};
+		}
+		match s.as_str() {
 			"ecdsa_p256" => Ok(KeyType::EcdsaP256),
 			"ecdsa_p384" => Ok(KeyType::EcdsaP384),
 			"ecdsa_p521" => Ok(KeyType::EcdsaP521),
@@ -80,6 +155,12 @@ impl FromStr for KeyType {
 			"ed25519" => Ok(KeyType::Ed25519),
 			#[cfg(feature = "ed448")]
 			"ed448" => Ok(KeyType::Ed448),
+			#[cfg(feature = "ml_dsa")]
+			"ml_dsa_44" => Ok(KeyType::MlDsa44),
+			#[cfg(feature = "ml_dsa")]
+			"ml_dsa_65" => Ok(KeyType::MlDsa65),
+			#[cfg(feature = "ml_dsa")]
+			"ml_dsa_87" => Ok(KeyType::MlDsa87),
 			_ => Err(format!("{s}: unknown algorithm").into()),
 		}
 	}
@@ -89,7 +170,11 @@ impl fmt::Display for KeyType {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
 		let s = match self {
 			KeyType::Rsa2048 => "rsa2048",
+			KeyType::Rsa3072 => "rsa3072",
 			KeyType::Rsa4096 => "rsa4096",
+			KeyType::Rsa(n) => {
+				return write!(f, "rsa{n}");
+			}
 			KeyType::EcdsaP256 => "ecdsa-p256",
 			KeyType::EcdsaP384 => "ecdsa-p384",
 			KeyType::EcdsaP521 => "ecdsa-p521",
@@ -97,6 +182,12 @@ impl fmt::Display for KeyType {
 			KeyType::Ed25519 => "ed25519",
 			#[cfg(feature = "ed448")]
 			KeyType::Ed448 => "ed448",
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa44 => "ml-dsa-44",
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa65 => "ml-dsa-65",
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa87 => "ml-dsa-87",
 		};
 		write!(f, "{s}")
 	}
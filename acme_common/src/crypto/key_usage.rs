@@ -0,0 +1,112 @@
+use std::collections::HashSet;
+use std::fmt;
+
+const OID_SERVER_AUTH: &str = "1.3.6.1.5.5.7.3.1";
+const OID_CLIENT_AUTH: &str = "1.3.6.1.5.5.7.3.2";
+const OID_CODE_SIGNING: &str = "1.3.6.1.5.5.7.3.3";
+const OID_EMAIL_PROTECTION: &str = "1.3.6.1.5.5.7.3.4";
+const OID_TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+const OID_OCSP_SIGNING: &str = "1.3.6.1.5.5.7.3.9";
+
+/// Purpose of the key contained in a certificate, as defined by the key
+/// usage extension (RFC 5280, section 4.2.1.3).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum KeyUsage {
+	DigitalSignature,
+	ContentCommitment,
+	KeyEncipherment,
+	DataEncipherment,
+	KeyAgreement,
+	KeyCertSign,
+	CrlSign,
+	EncipherOnly,
+	DecipherOnly,
+}
+
+impl KeyUsage {
+	/// Return the key usages set in the extension's bit string, the first
+	/// bit being the most significant one of the first byte.
+	pub(crate) fn from_bits(bits: &[u8]) -> HashSet<Self> {
+		// Ordered by bit number.
+		let usages = [
+			KeyUsage::DigitalSignature,
+			KeyUsage::ContentCommitment,
+			KeyUsage::KeyEncipherment,
+			KeyUsage::DataEncipherment,
+			KeyUsage::KeyAgreement,
+			KeyUsage::KeyCertSign,
+			KeyUsage::CrlSign,
+			KeyUsage::EncipherOnly,
+			KeyUsage::DecipherOnly,
+		];
+		usages
+			.iter()
+			.enumerate()
+			.filter(|(i, _)| match bits.get(i / 8) {
+				Some(b) => b & (0x80 >> (i % 8)) != 0,
+				None => false,
+			})
+			.map(|(_, u)| *u)
+			.collect()
+	}
+}
+
+impl fmt::Display for KeyUsage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			KeyUsage::DigitalSignature => "digitalSignature",
+			KeyUsage::ContentCommitment => "contentCommitment",
+			KeyUsage::KeyEncipherment => "keyEncipherment",
+			KeyUsage::DataEncipherment => "dataEncipherment",
+			KeyUsage::KeyAgreement => "keyAgreement",
+			KeyUsage::KeyCertSign => "keyCertSign",
+			KeyUsage::CrlSign => "cRLSign",
+			KeyUsage::EncipherOnly => "encipherOnly",
+			KeyUsage::DecipherOnly => "decipherOnly",
+		};
+		write!(f, "{s}")
+	}
+}
+
+/// Purpose for which a certificate may be used, as defined by the extended
+/// key usage extension (RFC 5280, section 4.2.1.12). Unknown purposes are
+/// kept as their object identifier in dotted notation.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum ExtendedKeyUsage {
+	ServerAuth,
+	ClientAuth,
+	CodeSigning,
+	EmailProtection,
+	TimeStamping,
+	OcspSigning,
+	Other(String),
+}
+
+impl ExtendedKeyUsage {
+	pub(crate) fn from_oid(oid: &str) -> Self {
+		match oid {
+			OID_SERVER_AUTH => ExtendedKeyUsage::ServerAuth,
+			OID_CLIENT_AUTH => ExtendedKeyUsage::ClientAuth,
+			OID_CODE_SIGNING => ExtendedKeyUsage::CodeSigning,
+			OID_EMAIL_PROTECTION => ExtendedKeyUsage::EmailProtection,
+			OID_TIME_STAMPING => ExtendedKeyUsage::TimeStamping,
+			OID_OCSP_SIGNING => ExtendedKeyUsage::OcspSigning,
+			_ => ExtendedKeyUsage::Other(oid.to_string()),
+		}
+	}
+}
+
+impl fmt::Display for ExtendedKeyUsage {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let s = match self {
+			ExtendedKeyUsage::ServerAuth => "serverAuth",
+			ExtendedKeyUsage::ClientAuth => "clientAuth",
+			ExtendedKeyUsage::CodeSigning => "codeSigning",
+			ExtendedKeyUsage::EmailProtection => "emailProtection",
+			ExtendedKeyUsage::TimeStamping => "timeStamping",
+			ExtendedKeyUsage::OcspSigning => "OCSPSigning",
+			ExtendedKeyUsage::Other(oid) => oid,
+		};
+		write!(f, "{s}")
+	}
+}
@@ -1,16 +1,32 @@
-use super::{gen_keypair, KeyPair, KeyType, SubjectAttribute};
+use super::openssl_keys::get_public_key_type;
+#[cfg(feature = "pkcs11")]
+use super::openssl_keys::InnerKey;
+use super::x509_extensions::{get_csr_subject_alt_names, get_extended_key_usage, get_key_usage};
+#[cfg(feature = "pkcs11")]
+use super::x509_request::build_cert_req;
+use super::{
+	gen_keypair, CertificateInspector, CsrBuilder, CsrInspector, ExtendedKeyUsage, KeyPair,
+	KeyType, KeyUsage, SubjectAttribute,
+};
 use crate::b64_encode;
 use crate::crypto::HashFunction;
 use crate::error::Error;
-use openssl::asn1::Asn1Time;
+use der::Decode;
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
 use openssl::bn::{BigNum, MsbOption};
 use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
 use openssl::stack::Stack;
 use openssl::x509::extension::{BasicConstraints, SubjectAlternativeName};
-use openssl::x509::{X509Builder, X509Extension, X509NameBuilder, X509Req, X509ReqBuilder, X509};
+use openssl::x509::{
+	X509Builder, X509Extension, X509NameBuilder, X509NameRef, X509Req, X509ReqBuilder, X509,
+};
 use std::collections::{HashMap, HashSet};
 use std::net::IpAddr;
-use std::time::Duration;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use x509_cert::request::CertReq;
+use x509_cert::Certificate;
 
 fn get_digest(digest: HashFunction, key_pair: &KeyPair) -> MessageDigest {
 	#[cfg(not(any(feature = "ed25519", feature = "ed448")))]
@@ -32,15 +48,54 @@ pub struct Csr {
 }
 
 impl Csr {
-	pub fn new(
+	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
+		Ok(Csr {
+			inner_csr: X509Req::from_pem(pem_data)?,
+		})
+	}
+}
+
+impl CsrInspector for Csr {
+	fn key_type(&self) -> Result<KeyType, Error> {
+		let der = self.inner_csr.public_key()?.public_key_to_der()?;
+		get_public_key_type(&der)
+	}
+
+	fn subject_alt_names(&self) -> Result<HashSet<String>, Error> {
+		// OpenSSL does not give access to the content of the extensions.
+		let csr = CertReq::from_der(&self.inner_csr.to_der()?)?;
+		get_csr_subject_alt_names(&csr)
+	}
+}
+
+impl CsrBuilder<KeyPair> for Csr {
+	fn build(
 		key_pair: &KeyPair,
 		digest: HashFunction,
 		domains: &[String],
 		ips: &[String],
 		subject_attributes: &HashMap<SubjectAttribute, String>,
 	) -> Result<Self, Error> {
+		#[cfg(feature = "pkcs11")]
+		if let InnerKey::Pkcs11(key) = &key_pair.inner_key {
+			// OpenSSL cannot sign using the token, hence the request is
+			// built separately and its data is signed by the token.
+			let public_key = key.public_key();
+			let csr = build_cert_req(
+				public_key.public_key_info()?,
+				public_key.x509_signature_algorithm(&digest),
+				domains,
+				ips,
+				subject_attributes,
+				|cri| key.x509_sign(&digest, cri),
+			)?;
+			return Ok(Csr {
+				inner_csr: X509Req::from_der(&der::Encode::to_der(&csr)?)?,
+			});
+		}
 		let mut builder = X509ReqBuilder::new()?;
-		builder.set_pubkey(&key_pair.inner_key)?;
+		let public_key = key_pair.public_key()?;
+		builder.set_pubkey(&*public_key)?;
 		if !subject_attributes.is_empty() {
 			let mut snb = X509NameBuilder::new()?;
 			for (sattr, val) in subject_attributes.iter() {
@@ -62,33 +117,119 @@ impl Csr {
 		ext_stack.push(san)?;
 		builder.add_extensions(&ext_stack)?;
 		let digest = get_digest(digest, key_pair);
-		builder.sign(&key_pair.inner_key, digest)?;
+		builder.sign(key_pair.private_key()?, digest)?;
 		Ok(Csr {
 			inner_csr: builder.build(),
 		})
 	}
 
-	pub fn to_der_base64(&self) -> Result<String, Error> {
+	fn to_der_base64(&self) -> Result<String, Error> {
 		let csr = self.inner_csr.to_der()?;
 		let csr = b64_encode(&csr);
 		Ok(csr)
 	}
 
-	pub fn to_pem(&self) -> Result<String, Error> {
+	fn to_pem(&self) -> Result<String, Error> {
 		let csr = self.inner_csr.to_pem()?;
 		Ok(String::from_utf8(csr)?)
 	}
 }
 
+fn escape_dn_value(value: &str) -> String {
+	let mut escaped = String::with_capacity(value.len());
+	let mut iter = value.char_indices().peekable();
+	while let Some((i, c)) = iter.next() {
+		match c {
+			'#' if i == 0 => escaped.push_str("\\#"),
+			' ' if i == 0 || iter.peek().is_none() => escaped.push_str("\\ "),
+			'"' | '+' | ',' | ';' | '<' | '>' | '\\' => {
+				escaped.push('\\');
+				escaped.push(c);
+			}
+			'\x00'..='\x1f' | '\x7f' => escaped.push_str(&format!("\\{:02x}", c as u8)),
+			_ => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+fn name_to_string(name: &X509NameRef) -> Result<String, Error> {
+	let mut attributes = Vec::new();
+	for entry in name.entries() {
+		let key = match entry.object().nid().short_name() {
+			Ok(n) => n.to_string(),
+			Err(_) => entry.object().to_string(),
+		};
+		let value = entry.data().as_utf8()?;
+		attributes.push(format!("{key}={}", escape_dn_value(&value)));
+	}
+	// As per RFC 4514, the attributes are listed in reverse order.
+	attributes.reverse();
+	Ok(attributes.join(","))
+}
+
+fn asn1_time_to_system_time(time: &Asn1TimeRef) -> Result<SystemTime, Error> {
+	let diff = Asn1Time::from_unix(0)?.diff(time)?;
+	let nb_secs = i64::from(diff.days) * 24 * 60 * 60 + i64::from(diff.secs);
+	let duration = Duration::from_secs(nb_secs.unsigned_abs());
+	let time = if nb_secs >= 0 {
+		UNIX_EPOCH.checked_add(duration)
+	} else {
+		UNIX_EPOCH.checked_sub(duration)
+	};
+	time.ok_or_else(|| Error::from("invalid time"))
+}
+
 pub struct X509Certificate {
-	pub inner_cert: X509,
+	inner_cert: X509,
+	// OpenSSL does not give access to the content of some extensions, hence
+	// the certificate is also decoded on demand using the x509-cert crate.
+	decoded_cert: OnceLock<Certificate>,
 }
 
 impl X509Certificate {
+	fn new(inner_cert: X509) -> Self {
+		X509Certificate {
+			inner_cert,
+			decoded_cert: OnceLock::new(),
+		}
+	}
+
 	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
-		Ok(X509Certificate {
-			inner_cert: X509::from_pem(pem_data)?,
-		})
+		Ok(X509Certificate::new(X509::from_pem(pem_data)?))
+	}
+
+	/// Load every certificate of a PEM bundle, such as a certificate chain,
+	/// in the order they appear.
+	pub fn from_pem_chain(pem_data: &[u8]) -> Result<Vec<Self>, Error> {
+		let chain: Vec<Self> = X509::stack_from_pem(pem_data)?
+			.into_iter()
+			.map(X509Certificate::new)
+			.collect();
+		if chain.is_empty() {
+			return Err("no certificate found".into());
+		}
+		Ok(chain)
+	}
+
+	fn get_decoded_cert(&self) -> Result<&Certificate, Error> {
+		if let Some(cert) = self.decoded_cert.get() {
+			return Ok(cert);
+		}
+		let cert = Certificate::from_der(&self.inner_cert.to_der()?)?;
+		Ok(self.decoded_cert.get_or_init(|| cert))
+	}
+
+	fn get_access_locations(&self, method: Nid) -> Vec<String> {
+		match self.inner_cert.authority_info() {
+			Some(aia) => aia
+				.iter()
+				.filter(|ad| ad.method().nid() == method)
+				.filter_map(|ad| ad.location().uri())
+				.map(|uri| uri.to_string())
+				.collect(),
+			None => vec![],
+		}
 	}
 
 	pub fn from_pem_native(pem_data: &[u8]) -> Result<native_tls::Certificate, Error> {
@@ -104,11 +245,38 @@ impl X509Certificate {
 		let key_pair = gen_keypair(key_type)?;
 		let digest = get_digest(digest, &key_pair);
 		let inner_cert = gen_certificate(domain, &key_pair, &digest, acme_ext)?;
-		let cert = X509Certificate { inner_cert };
+		let cert = X509Certificate::new(inner_cert);
 		Ok((key_pair, cert))
 	}
+}
+
+impl CertificateInspector for X509Certificate {
+	fn to_der(&self) -> Result<Vec<u8>, Error> {
+		self.inner_cert.to_der().map_err(Error::from)
+	}
+
+	fn serial_number(&self) -> Result<String, Error> {
+		let serial = self.inner_cert.serial_number().to_bn()?.to_hex_str()?;
+		Ok(serial.to_string())
+	}
+
+	fn issuer(&self) -> Result<String, Error> {
+		name_to_string(self.inner_cert.issuer_name())
+	}
 
-	pub fn expires_in(&self) -> Result<Duration, Error> {
+	fn subject(&self) -> Result<String, Error> {
+		name_to_string(self.inner_cert.subject_name())
+	}
+
+	fn not_before(&self) -> Result<SystemTime, Error> {
+		asn1_time_to_system_time(self.inner_cert.not_before())
+	}
+
+	fn not_after(&self) -> Result<SystemTime, Error> {
+		asn1_time_to_system_time(self.inner_cert.not_after())
+	}
+
+	fn expires_in(&self) -> Result<Duration, Error> {
 		let now = Asn1Time::days_from_now(0)?;
 		let not_after = self.inner_cert.not_after();
 		let diff = now.diff(not_after)?;
@@ -117,7 +285,17 @@ impl X509Certificate {
 		Ok(Duration::from_secs(nb_secs))
 	}
 
-	pub fn subject_alt_names(&self) -> HashSet<String> {
+	fn lifetime(&self) -> Result<Duration, Error> {
+		let diff = self
+			.inner_cert
+			.not_before()
+			.diff(self.inner_cert.not_after())?;
+		let nb_secs = diff.days * 24 * 60 * 60 + diff.secs;
+		let nb_secs = if nb_secs > 0 { nb_secs as u64 } else { 0 };
+		Ok(Duration::from_secs(nb_secs))
+	}
+
+	fn subject_alt_names(&self) -> HashSet<String> {
 		match self.inner_cert.subject_alt_names() {
 			Some(s) => s
 				.iter()
@@ -146,6 +324,44 @@ impl X509Certificate {
 			None => HashSet::new(),
 		}
 	}
+
+	fn authority_key_identifier(&self) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self
+			.inner_cert
+			.authority_key_id()
+			.map(|id| id.as_slice().to_vec()))
+	}
+
+	fn subject_key_identifier(&self) -> Result<Option<Vec<u8>>, Error> {
+		Ok(self
+			.inner_cert
+			.subject_key_id()
+			.map(|id| id.as_slice().to_vec()))
+	}
+
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error> {
+		Ok(self.inner_cert.public_key()?.public_key_to_der()?)
+	}
+
+	fn key_type(&self) -> Result<KeyType, Error> {
+		get_public_key_type(&self.public_key_to_der()?)
+	}
+
+	fn key_usage(&self) -> Result<Option<HashSet<KeyUsage>>, Error> {
+		get_key_usage(&self.get_decoded_cert()?.tbs_certificate)
+	}
+
+	fn extended_key_usage(&self) -> Result<Option<Vec<ExtendedKeyUsage>>, Error> {
+		get_extended_key_usage(&self.get_decoded_cert()?.tbs_certificate)
+	}
+
+	fn ocsp_urls(&self) -> Result<Vec<String>, Error> {
+		Ok(self.get_access_locations(Nid::AD_OCSP))
+	}
+
+	fn ca_issuers_urls(&self) -> Result<Vec<String>, Error> {
+		Ok(self.get_access_locations(Nid::AD_CA_ISSUERS))
+	}
 }
 
 fn gen_certificate(
@@ -170,7 +386,7 @@ fn gen_certificate(
 	builder.set_serial_number(&serial_number)?;
 	builder.set_subject_name(&x509_name)?;
 	builder.set_issuer_name(&x509_name)?;
-	builder.set_pubkey(&key_pair.inner_key)?;
+	builder.set_pubkey(key_pair.private_key()?)?;
 	let not_before = Asn1Time::days_from_now(0)?;
 	builder.set_not_before(&not_before)?;
 	let not_after = Asn1Time::days_from_now(super::CRT_NB_DAYS_VALIDITY)?;
@@ -197,7 +413,7 @@ fn gen_certificate(
 			.map_err(|_| Error::from(super::INVALID_EXT_MSG))?;
 	}
 
-	builder.sign(&key_pair.inner_key, *digest)?;
+	builder.sign(key_pair.private_key()?, *digest)?;
 	let cert = builder.build();
 	Ok(cert)
 }
@@ -0,0 +1,62 @@
+use crate::error::Error;
+use openssl::hash::MessageDigest;
+use openssl::pkcs5::pbkdf2_hmac;
+use openssl::rand::rand_bytes;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+
+const TAG_LEN: usize = 16;
+
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<(), Error> {
+	rand_bytes(buf)?;
+	Ok(())
+}
+
+pub(crate) fn derive_key(
+	passphrase: &[u8],
+	salt: &[u8],
+	iterations: u32,
+) -> Result<[u8; super::AES_256_KEY_LEN], Error> {
+	let mut key = [0u8; super::AES_256_KEY_LEN];
+	pbkdf2_hmac(
+		passphrase,
+		salt,
+		iterations as usize,
+		MessageDigest::sha256(),
+		&mut key,
+	)?;
+	Ok(key)
+}
+
+pub(crate) fn aes_256_gcm_encrypt(
+	key: &[u8],
+	nonce: &[u8],
+	aad: &[u8],
+	data: &[u8],
+) -> Result<Vec<u8>, Error> {
+	let mut tag = [0u8; TAG_LEN];
+	let mut ciphertext =
+		encrypt_aead(Cipher::aes_256_gcm(), key, Some(nonce), aad, data, &mut tag)?;
+	ciphertext.extend_from_slice(&tag);
+	Ok(ciphertext)
+}
+
+pub(crate) fn aes_256_gcm_decrypt(
+	key: &[u8],
+	nonce: &[u8],
+	aad: &[u8],
+	data: &[u8],
+) -> Result<Vec<u8>, Error> {
+	if data.len() < TAG_LEN {
+		return Err("encrypted data is too short".into());
+	}
+	let (ciphertext, tag) = data.split_at(data.len() - TAG_LEN);
+	decrypt_aead(
+		Cipher::aes_256_gcm(),
+		key,
+		Some(nonce),
+		aad,
+		ciphertext,
+		tag,
+	)
+	.map_err(|_| Error::from("unable to decrypt data: invalid passphrase or corrupted data"))
+}
@@ -1,27 +1,33 @@
 use crate::b64_encode;
-use crate::crypto::{HashFunction, JwsSignatureAlgorithm, KeyType};
+use crate::crypto::openssl_cipher::{derive_key, random_bytes};
+#[cfg(feature = "pkcs11")]
+use crate::crypto::pkcs11::Pkcs11Key;
+use crate::crypto::{
+	pkcs11_uri_without_pin, HashFunction, JwsSignatureAlgorithm, KeyGenerator, KeyType, Signer,
+};
 use crate::error::Error;
 use openssl::bn::{BigNum, BigNumContext};
 use openssl::ec::{Asn1Flag, EcGroup, EcKey};
 use openssl::ecdsa::EcdsaSig;
 use openssl::hash::MessageDigest;
 use openssl::nid::Nid;
-use openssl::pkey::{Id, PKey, Private};
-use openssl::rsa::Rsa;
-use openssl::sign::Signer;
+use openssl::pkey::{Id, PKey, Private, Public};
+use openssl::rsa::{Padding, Rsa};
+use openssl::sign::{RsaPssSaltlen, Signer as OpenSslSigner};
+use openssl::symm::{encrypt, Cipher};
+use pkcs8::der::EncodePem;
+use pkcs8::pkcs5::pbes2;
+use pkcs8::{EncryptedPrivateKeyInfo, LineEnding};
 use serde_json::json;
 use serde_json::value::Value;
+use std::fmt;
+
+const AES_CBC_IV_LEN: usize = 16;
 
 macro_rules! get_key_type {
 	($key: expr) => {
 		match $key.id() {
-			Id::RSA => match $key.rsa()?.size() {
-				256 => KeyType::Rsa2048,
-				512 => KeyType::Rsa4096,
-				s => {
-					return Err(format!("{}: unsupported RSA key size", s * 8).into());
-				}
-			},
+			Id::RSA => KeyType::from_rsa_size($key.rsa()?.n().num_bits() as u32)?,
 			Id::EC => match $key.ec_key()?.group().curve_name() {
 				Some(Nid::X9_62_PRIME256V1) => KeyType::EcdsaP256,
 				Some(Nid::SECP384R1) => KeyType::EcdsaP384,
@@ -59,10 +65,26 @@ macro_rules! get_ecdsa_sig_part {
 	}};
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
+pub(crate) enum InnerKey {
+	Private(PKey<Private>),
+	#[cfg(feature = "pkcs11")]
+	Pkcs11(Pkcs11Key),
+}
+
+#[derive(Clone)]
 pub struct KeyPair {
 	pub key_type: KeyType,
-	pub inner_key: PKey<Private>,
+	pub(crate) inner_key: InnerKey,
+}
+
+// The private key must never be displayed.
+impl fmt::Debug for KeyPair {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("KeyPair")
+			.field("key_type", &self.key_type)
+			.finish_non_exhaustive()
+	}
 }
 
 impl KeyPair {
@@ -71,56 +93,131 @@ impl KeyPair {
 		let key_type = get_key_type!(inner_key);
 		Ok(KeyPair {
 			key_type,
-			inner_key,
+			inner_key: InnerKey::Private(inner_key),
 		})
 	}
 
 	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
-		let inner_key = PKey::private_key_from_pem(pem_data)?;
+		// Without a callback, OpenSSL would prompt for the passphrase of an
+		// encrypted key on the terminal.
+		let inner_key = PKey::private_key_from_pem_callback(pem_data, |_| {
+			Err(openssl::error::ErrorStack::get())
+		})?;
+		let key_type = get_key_type!(inner_key);
+		Ok(KeyPair {
+			key_type,
+			inner_key: InnerKey::Private(inner_key),
+		})
+	}
+
+	/// Load a private key from a PKCS #8 encrypted PEM. Unencrypted PEM
+	/// data is also accepted, in which case the passphrase is ignored.
+	pub fn from_pem_passphrase(pem_data: &[u8], passphrase: &[u8]) -> Result<Self, Error> {
+		let inner_key = PKey::private_key_from_pem_passphrase(pem_data, passphrase)?;
 		let key_type = get_key_type!(inner_key);
 		Ok(KeyPair {
 			key_type,
-			inner_key,
+			inner_key: InnerKey::Private(inner_key),
 		})
 	}
 
+	/// Open a private key stored in a PKCS #11 token.
+	#[cfg(feature = "pkcs11")]
+	pub fn from_pkcs11_uri(uri: &str) -> Result<Self, Error> {
+		let key = Pkcs11Key::open(uri).map_err(|e| e.prefix(&pkcs11_uri_without_pin(uri)))?;
+		Ok(KeyPair {
+			key_type: key.public_key().key_type()?,
+			inner_key: InnerKey::Pkcs11(key),
+		})
+	}
+
+	/// Open a private key stored in a PKCS #11 token.
+	#[cfg(not(feature = "pkcs11"))]
+	pub fn from_pkcs11_uri(uri: &str) -> Result<Self, Error> {
+		let msg = format!(
+			"{}: PKCS #11 support is disabled, rebuild with the pkcs11 feature",
+			pkcs11_uri_without_pin(uri)
+		);
+		Err(msg.into())
+	}
+
+	/// Return the URI of the key if it is stored in a PKCS #11 token. The
+	/// PIN is not included.
+	pub fn pkcs11_uri(&self) -> Option<&str> {
+		match &self.inner_key {
+			InnerKey::Private(_) => None,
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(key) => Some(key.uri()),
+		}
+	}
+
+	pub(crate) fn private_key(&self) -> Result<&PKey<Private>, Error> {
+		match &self.inner_key {
+			InnerKey::Private(key) => Ok(key),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(key) => Err(key.export_error()),
+		}
+	}
+
+	pub(crate) fn public_key(&self) -> Result<PKey<Public>, Error> {
+		let der = self.public_key_to_der()?;
+		PKey::public_key_from_der(&der).map_err(Error::from)
+	}
+
 	pub fn private_key_to_der(&self) -> Result<Vec<u8>, Error> {
-		self.inner_key.private_key_to_der().map_err(Error::from)
+		self.private_key()?
+			.private_key_to_der()
+			.map_err(Error::from)
 	}
 
 	pub fn private_key_to_pem(&self) -> Result<Vec<u8>, Error> {
-		self.inner_key
+		self.private_key()?
 			.private_key_to_pem_pkcs8()
 			.map_err(Error::from)
 	}
 
-	pub fn public_key_to_pem(&self) -> Result<Vec<u8>, Error> {
-		self.inner_key.public_key_to_pem().map_err(Error::from)
+	/// Export the private key as a PKCS #8 PEM encrypted using PBES2 with
+	/// PBKDF2-HMAC-SHA256 and AES-256-CBC. OpenSSL's default iteration count
+	/// being much lower, the same one as the RustCrypto backend is used.
+	pub fn private_key_to_pem_passphrase(&self, passphrase: &[u8]) -> Result<Vec<u8>, Error> {
+		let mut salt = [0u8; super::PBKDF2_SALT_LEN];
+		let mut iv = [0u8; AES_CBC_IV_LEN];
+		random_bytes(&mut salt)?;
+		random_bytes(&mut iv)?;
+		let key = derive_key(passphrase, &salt, super::PBKDF2_ITERATIONS)?;
+		let der = self.private_key()?.private_key_to_pkcs8()?;
+		let encrypted_data = encrypt(Cipher::aes_256_cbc(), &key, Some(&iv), &der)?;
+		let params =
+			pbes2::Parameters::pbkdf2_sha256_aes256cbc(super::PBKDF2_ITERATIONS, &salt, &iv)
+				.map_err(pkcs8::Error::from)?;
+		let info = EncryptedPrivateKeyInfo {
+			encryption_algorithm: params.into(),
+			encrypted_data: &encrypted_data,
+		};
+		let pem = info.to_pem(LineEnding::LF)?;
+		Ok(pem.into_bytes())
 	}
 
-	pub fn sign(&self, alg: &JwsSignatureAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
-		self.key_type.check_alg_compatibility(alg)?;
-		match alg {
-			JwsSignatureAlgorithm::Hs256
-			| JwsSignatureAlgorithm::Hs384
-			| JwsSignatureAlgorithm::Hs512 => Err(format!(
-				"{} key pair cannot be used for the {alg} signature algorithm",
-				self.key_type
-			)
-			.into()),
-			JwsSignatureAlgorithm::Rs256 => self.sign_rsa(&MessageDigest::sha256(), data),
-			JwsSignatureAlgorithm::Es256 => self.sign_ecdsa(&HashFunction::Sha256, data),
-			JwsSignatureAlgorithm::Es384 => self.sign_ecdsa(&HashFunction::Sha384, data),
-			JwsSignatureAlgorithm::Es512 => self.sign_ecdsa(&HashFunction::Sha512, data),
-			#[cfg(feature = "ed25519")]
-			JwsSignatureAlgorithm::Ed25519 => self.sign_eddsa(data),
-			#[cfg(feature = "ed448")]
-			JwsSignatureAlgorithm::Ed448 => self.sign_eddsa(data),
+	pub fn public_key_to_pem(&self) -> Result<Vec<u8>, Error> {
+		match &self.inner_key {
+			InnerKey::Private(key) => key.public_key_to_pem().map_err(Error::from),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(key) => key.public_key().to_pem(),
 		}
 	}
 
 	fn sign_rsa(&self, hash_func: &MessageDigest, data: &[u8]) -> Result<Vec<u8>, Error> {
-		let mut signer = Signer::new(*hash_func, &self.inner_key)?;
+		let mut signer = OpenSslSigner::new(*hash_func, self.private_key()?)?;
+		signer.update(data)?;
+		let signature = signer.sign_to_vec()?;
+		Ok(signature)
+	}
+
+	fn sign_rsa_pss(&self, hash_func: &MessageDigest, data: &[u8]) -> Result<Vec<u8>, Error> {
+		let mut signer = OpenSslSigner::new(*hash_func, self.private_key()?)?;
+		signer.set_rsa_padding(Padding::PKCS1_PSS)?;
+		signer.set_rsa_mgf1_md(*hash_func)?;
+		signer.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)?;
 		signer.update(data)?;
 		let signature = signer.sign_to_vec()?;
 		Ok(signature)
@@ -128,7 +225,7 @@ impl KeyPair {
 
 	fn sign_ecdsa(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
 		let fingerprint = hash_func.hash(data);
-		let signature = EcdsaSig::sign(&fingerprint, self.inner_key.ec_key()?.as_ref())?;
+		let signature = EcdsaSig::sign(&fingerprint, self.private_key()?.ec_key()?.as_ref())?;
 		let sig_size = match self.key_type {
 			KeyType::EcdsaP256 => 32,
 			KeyType::EcdsaP384 => 48,
@@ -146,22 +243,20 @@ impl KeyPair {
 
 	#[cfg(any(feature = "ed25519", feature = "ed448"))]
 	fn sign_eddsa(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
-		let mut signer = Signer::new_without_digest(&self.inner_key)?;
+		let mut signer = OpenSslSigner::new_without_digest(self.private_key()?)?;
 		let signature = signer.sign_oneshot_to_vec(data)?;
 		Ok(signature)
 	}
 
-	pub fn jwk_public_key(&self) -> Result<Value, Error> {
-		self.get_jwk_public_key(false)
-	}
-
-	pub fn jwk_public_key_thumbprint(&self) -> Result<Value, Error> {
-		self.get_jwk_public_key(true)
-	}
-
 	fn get_jwk_public_key(&self, thumbprint: bool) -> Result<Value, Error> {
+		#[cfg(feature = "pkcs11")]
+		if let InnerKey::Pkcs11(key) = &self.inner_key {
+			return key.public_key().jwk(thumbprint);
+		}
 		match self.key_type {
-			KeyType::Rsa2048 | KeyType::Rsa4096 => self.get_rsa_jwk(thumbprint),
+			KeyType::Rsa2048 | KeyType::Rsa3072 | KeyType::Rsa4096 | KeyType::Rsa(_) => {
+				self.get_rsa_jwk(thumbprint)
+			}
 			KeyType::EcdsaP256 | KeyType::EcdsaP384 | KeyType::EcdsaP521 => {
 				self.get_ecdsa_jwk(thumbprint)
 			}
@@ -169,11 +264,13 @@ impl KeyPair {
 			KeyType::Ed25519 => self.get_eddsa_jwk(thumbprint),
 			#[cfg(feature = "ed448")]
 			KeyType::Ed448 => self.get_eddsa_jwk(thumbprint),
+			#[cfg(feature = "ml_dsa")]
+			KeyType::MlDsa44 | KeyType::MlDsa65 | KeyType::MlDsa87 => Err(unsupported_pqc()),
 		}
 	}
 
 	fn get_rsa_jwk(&self, thumbprint: bool) -> Result<Value, Error> {
-		let rsa = self.inner_key.rsa().unwrap();
+		let rsa = self.private_key()?.rsa()?;
 		let e = rsa.e();
 		let n = rsa.n();
 		let e = b64_encode(&e.to_vec());
@@ -209,9 +306,8 @@ impl KeyPair {
 		let mut ctx = BigNumContext::new().unwrap();
 		let mut x = BigNum::new().unwrap();
 		let mut y = BigNum::new().unwrap();
-		self.inner_key
-			.ec_key()
-			.unwrap()
+		self.private_key()?
+			.ec_key()?
 			.public_key()
 			.affine_coordinates_gfp(&group, &mut x, &mut y, &mut ctx)?;
 		let x = b64_encode(&x.to_vec_padded(size)?);
@@ -293,6 +389,62 @@ impl KeyPair {
 	}
 }
 
+impl Signer for KeyPair {
+	fn key_type(&self) -> KeyType {
+		self.key_type
+	}
+
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error> {
+		match &self.inner_key {
+			InnerKey::Private(key) => key.public_key_to_der().map_err(Error::from),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(key) => key.public_key().to_der(),
+		}
+	}
+
+	fn sign(&self, alg: &JwsSignatureAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+		self.key_type.check_alg_compatibility(alg)?;
+		#[cfg(feature = "pkcs11")]
+		if let InnerKey::Pkcs11(key) = &self.inner_key {
+			return key.sign(alg, data);
+		}
+		match alg {
+			JwsSignatureAlgorithm::Hs256
+			| JwsSignatureAlgorithm::Hs384
+			| JwsSignatureAlgorithm::Hs512 => Err(format!(
+				"{} key pair cannot be used for the {alg} signature algorithm",
+				self.key_type
+			)
+			.into()),
+			JwsSignatureAlgorithm::Rs256 => self.sign_rsa(&MessageDigest::sha256(), data),
+			JwsSignatureAlgorithm::Rs384 => self.sign_rsa(&MessageDigest::sha384(), data),
+			JwsSignatureAlgorithm::Rs512 => self.sign_rsa(&MessageDigest::sha512(), data),
+			JwsSignatureAlgorithm::Ps256 => self.sign_rsa_pss(&MessageDigest::sha256(), data),
+			JwsSignatureAlgorithm::Ps384 => self.sign_rsa_pss(&MessageDigest::sha384(), data),
+			JwsSignatureAlgorithm::Ps512 => self.sign_rsa_pss(&MessageDigest::sha512(), data),
+			JwsSignatureAlgorithm::Es256 => self.sign_ecdsa(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Es384 => self.sign_ecdsa(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Es512 => self.sign_ecdsa(&HashFunction::Sha512, data),
+			#[cfg(feature = "ed25519")]
+			JwsSignatureAlgorithm::Ed25519 => self.sign_eddsa(data),
+			#[cfg(feature = "ed448")]
+			JwsSignatureAlgorithm::Ed448 => self.sign_eddsa(data),
+			#[cfg(feature = "ml_dsa")]
+			JwsSignatureAlgorithm::MlDsa44
+			| JwsSignatureAlgorithm::MlDsa65
+			| JwsSignatureAlgorithm::MlDsa87 => Err(unsupported_pqc()),
+		}
+	}
+
+	fn jwk_public_key(&self) -> Result<Value, Error> {
+		self.get_jwk_public_key(false)
+	}
+
+	fn jwk_public_key_thumbprint(&self) -> Result<Value, Error> {
+		self.get_jwk_public_key(true)
+	}
+}
+
 fn gen_rsa_pair(nb_bits: u32) -> Result<PKey<Private>, Error> {
 	let priv_key = Rsa::generate(nb_bits)?;
 	let pk = PKey::from_rsa(priv_key).map_err(|_| Error::from(""))?;
@@ -322,10 +474,31 @@ fn gen_ed448_pair() -> Result<PKey<Private>, Error> {
 	Ok(pk)
 }
 
+/// Return the type of a DER-encoded public key (SubjectPublicKeyInfo).
+pub(crate) fn get_public_key_type(der_data: &[u8]) -> Result<KeyType, Error> {
+	let key = PKey::public_key_from_der(der_data)?;
+	Ok(get_key_type!(key))
+}
+
+impl KeyGenerator for KeyPair {
+	fn generate(key_type: KeyType) -> Result<Self, Error> {
+		gen_keypair(key_type)
+	}
+}
+
+// The post-quantum key types only exist in this backend if the `ml_dsa`
+// feature has been explicitly enabled, OpenSSL does not support them yet.
+#[cfg(feature = "ml_dsa")]
+fn unsupported_pqc() -> Error {
+	"post-quantum key types are only available with the RustCrypto backend".into()
+}
+
 pub fn gen_keypair(key_type: KeyType) -> Result<KeyPair, Error> {
 	let priv_key = match key_type {
 		KeyType::Rsa2048 => gen_rsa_pair(2048),
+		KeyType::Rsa3072 => gen_rsa_pair(3072),
 		KeyType::Rsa4096 => gen_rsa_pair(4096),
+		KeyType::Rsa(nb_bits) => gen_rsa_pair(nb_bits),
 		KeyType::EcdsaP256 => gen_ec_pair(Nid::X9_62_PRIME256V1),
 		KeyType::EcdsaP384 => gen_ec_pair(Nid::SECP384R1),
 		KeyType::EcdsaP521 => gen_ec_pair(Nid::SECP521R1),
@@ -333,11 +506,15 @@ pub fn gen_keypair(key_type: KeyType) -> Result<KeyPair, Error> {
 		KeyType::Ed25519 => gen_ed25519_pair(),
 		#[cfg(feature = "ed448")]
 		KeyType::Ed448 => gen_ed448_pair(),
+		#[cfg(feature = "ml_dsa")]
+		KeyType::MlDsa44 | KeyType::MlDsa65 | KeyType::MlDsa87 => {
+			return Err(unsupported_pqc());
+		}
 	}
 	.map_err(|_| Error::from(format!("unable to generate a {key_type} key pair")))?;
 	let key_pair = KeyPair {
 		key_type,
-		inner_key: priv_key,
+		inner_key: InnerKey::Private(priv_key),
 	};
 	Ok(key_pair)
 }
@@ -0,0 +1,623 @@
+use crate::b64_encode;
+use crate::crypto::{pkcs11_uri_without_pin, HashFunction, JwsSignatureAlgorithm, KeyType};
+use crate::error::Error;
+use cryptoki::context::{CInitializeArgs, CInitializeFlags, Pkcs11};
+use cryptoki::error::{Error as Pkcs11Error, RvError};
+use cryptoki::mechanism::rsa::{PkcsMgfType, PkcsPssParams};
+use cryptoki::mechanism::{Mechanism, MechanismType};
+use cryptoki::object::{
+	Attribute, AttributeType, KeyType as Pkcs11KeyType, ObjectClass, ObjectHandle,
+};
+use cryptoki::session::{Session, UserType};
+use cryptoki::types::AuthPin;
+use der::asn1::{BitString, ObjectIdentifier, OctetStringRef, UintRef};
+use der::pem::LineEnding;
+use der::{Any, Decode, Encode, EncodePem, Sequence};
+use serde_json::json;
+use serde_json::value::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, OnceLock};
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+
+const URI_SCHEME: &str = "pkcs11:";
+const OID_SECP256R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_SECP384R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const OID_SECP521R1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.35");
+const OID_RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const OID_RSA_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+const OID_RSA_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12");
+const OID_RSA_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13");
+const OID_EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const OID_ECDSA_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const OID_ECDSA_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const OID_ECDSA_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.4");
+
+/// RSAPublicKey structure (RFC 8017).
+#[derive(Sequence)]
+struct RsaPublicKey<'a> {
+	modulus: UintRef<'a>,
+	public_exponent: UintRef<'a>,
+}
+
+/// Ecdsa-Sig-Value structure (RFC 3279).
+#[derive(Sequence)]
+struct EcdsaSigValue<'a> {
+	r: UintRef<'a>,
+	s: UintRef<'a>,
+}
+
+/// PKCS #11 URI (RFC 7512) designating a private key.
+///
+/// Only the attributes useful to find a private key are supported. The
+/// `module-path` query attribute is required.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pkcs11Uri {
+	pub token: Option<String>,
+	pub manufacturer: Option<String>,
+	pub model: Option<String>,
+	pub serial: Option<String>,
+	pub slot_id: Option<u64>,
+	pub object: Option<String>,
+	pub id: Option<Vec<u8>>,
+	pub module_path: String,
+	pub pin_value: Option<String>,
+	pub pin_source: Option<String>,
+}
+
+impl Pkcs11Uri {
+	fn get_pin(&self) -> Result<Option<String>, Error> {
+		if let Some(pin) = &self.pin_value {
+			return Ok(Some(pin.to_owned()));
+		}
+		match &self.pin_source {
+			Some(src) => {
+				let path = src.strip_prefix("file:").unwrap_or(src);
+				let pin = std::fs::read_to_string(path).map_err(|e| Error::from(e).prefix(path))?;
+				Ok(Some(pin.trim_end_matches(['\r', '\n']).to_string()))
+			}
+			None => Ok(None),
+		}
+	}
+}
+
+fn percent_decode(value: &str) -> Result<Vec<u8>, Error> {
+	let mut res = Vec::with_capacity(value.len());
+	let mut bytes = value.bytes();
+	while let Some(b) = bytes.next() {
+		if b == b'%' {
+			let hex = [
+				bytes.next().unwrap_or_default(),
+				bytes.next().unwrap_or_default(),
+			];
+			let hex =
+				std::str::from_utf8(&hex).map_err(|_| Error::from("invalid percent-encoding"))?;
+			let b = u8::from_str_radix(hex, 16)
+				.map_err(|_| Error::from(format!("%{hex}: invalid percent-encoding")))?;
+			res.push(b);
+		} else {
+			res.push(b);
+		}
+	}
+	Ok(res)
+}
+
+fn percent_decode_str(value: &str) -> Result<String, Error> {
+	Ok(String::from_utf8(percent_decode(value)?)?)
+}
+
+impl FromStr for Pkcs11Uri {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Error> {
+		let uri = s
+			.strip_prefix(URI_SCHEME)
+			.ok_or_else(|| Error::from("not a PKCS #11 URI"))?;
+		let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+		let mut res = Pkcs11Uri::default();
+		let mut module_path = None;
+		for attr in path.split(';').filter(|a| !a.is_empty()) {
+			let (name, value) = attr
+				.split_once('=')
+				.ok_or_else(|| Error::from(format!("{attr}: invalid PKCS #11 URI attribute")))?;
+			match name {
+				"token" => res.token = Some(percent_decode_str(value)?),
+				"manufacturer" => res.manufacturer = Some(percent_decode_str(value)?),
+				"model" => res.model = Some(percent_decode_str(value)?),
+				"serial" => res.serial = Some(percent_decode_str(value)?),
+				"slot-id" => {
+					let id = value
+						.parse::<u64>()
+						.map_err(|_| Error::from(format!("{value}: invalid slot id")))?;
+					res.slot_id = Some(id);
+				}
+				"object" => res.object = Some(percent_decode_str(value)?),
+				"id" => res.id = Some(percent_decode(value)?),
+				"type" => {
+					if value != "private" {
+						let msg = format!(
+							"{value}: unsupported object type, only private keys can be used"
+						);
+						return Err(msg.into());
+					}
+				}
+				_ => {
+					let msg = format!("{name}: unsupported PKCS #11 URI attribute");
+					return Err(msg.into());
+				}
+			}
+		}
+		for attr in query.split('&').filter(|a| !a.is_empty()) {
+			let (name, value) = attr
+				.split_once('=')
+				.ok_or_else(|| Error::from(format!("{attr}: invalid PKCS #11 URI attribute")))?;
+			match name {
+				"module-path" => module_path = Some(percent_decode_str(value)?),
+				"pin-value" => res.pin_value = Some(percent_decode_str(value)?),
+				"pin-source" => res.pin_source = Some(percent_decode_str(value)?),
+				_ => {
+					let msg = format!("{name}: unsupported PKCS #11 URI query attribute");
+					return Err(msg.into());
+				}
+			}
+		}
+		res.module_path =
+			module_path.ok_or_else(|| Error::from("the module-path attribute is required"))?;
+		if res.object.is_none() && res.id.is_none() {
+			return Err("the PKCS #11 URI must specify either an object or an id".into());
+		}
+		Ok(res)
+	}
+}
+
+/// Public part of a key stored in a PKCS #11 token, built from the key's
+/// attributes.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Pkcs11PublicKey {
+	Rsa { n: Vec<u8>, e: Vec<u8> },
+	Ec { key_type: KeyType, point: Vec<u8> },
+}
+
+impl Pkcs11PublicKey {
+	pub(crate) fn from_rsa_components(n: &[u8], e: &[u8]) -> Self {
+		Pkcs11PublicKey::Rsa {
+			n: strip_leading_zeros(n).to_vec(),
+			e: strip_leading_zeros(e).to_vec(),
+		}
+	}
+
+	/// Build an EC public key from the `CKA_EC_PARAMS` and `CKA_EC_POINT`
+	/// attributes. The point may either be the raw uncompressed point or
+	/// be wrapped in a DER octet string, as mandated by the specification.
+	pub(crate) fn from_ec_components(params: &[u8], point: &[u8]) -> Result<Self, Error> {
+		let curve =
+			ObjectIdentifier::from_der(params).map_err(|_| Error::from("unsupported EC curve"))?;
+		let (key_type, size) = match curve {
+			OID_SECP256R1 => (KeyType::EcdsaP256, 32),
+			OID_SECP384R1 => (KeyType::EcdsaP384, 48),
+			OID_SECP521R1 => (KeyType::EcdsaP521, 66),
+			_ => {
+				return Err("unsupported EC curve".into());
+			}
+		};
+		let point_len = size * 2 + 1;
+		let point = if point.len() == point_len {
+			point
+		} else {
+			OctetStringRef::from_der(point)
+				.map_err(|_| Error::from("invalid EC point"))?
+				.as_bytes()
+		};
+		if point.len() != point_len || point[0] != 0x04 {
+			return Err("invalid or compressed EC point".into());
+		}
+		Ok(Pkcs11PublicKey::Ec {
+			key_type,
+			point: point.to_vec(),
+		})
+	}
+
+	pub(crate) fn key_type(&self) -> Result<KeyType, Error> {
+		match self {
+			Pkcs11PublicKey::Rsa { n, .. } => {
+				let nb_bits = match n.first() {
+					Some(b) => (n.len() * 8) - b.leading_zeros() as usize,
+					None => 0,
+				};
+				KeyType::from_rsa_size(nb_bits as u32)
+			}
+			Pkcs11PublicKey::Ec { key_type, .. } => Ok(*key_type),
+		}
+	}
+
+	pub(crate) fn public_key_info(&self) -> Result<SubjectPublicKeyInfoOwned, Error> {
+		let (algorithm, public_key) = match self {
+			Pkcs11PublicKey::Rsa { n, e } => {
+				let algorithm = AlgorithmIdentifierOwned {
+					oid: OID_RSA_ENCRYPTION,
+					parameters: Some(Any::null()),
+				};
+				let key = RsaPublicKey {
+					modulus: UintRef::new(n)?,
+					public_exponent: UintRef::new(e)?,
+				};
+				(algorithm, key.to_der()?)
+			}
+			Pkcs11PublicKey::Ec { key_type, point } => {
+				let curve = match key_type {
+					KeyType::EcdsaP384 => OID_SECP384R1,
+					KeyType::EcdsaP521 => OID_SECP521R1,
+					_ => OID_SECP256R1,
+				};
+				let algorithm = AlgorithmIdentifierOwned {
+					oid: OID_EC_PUBLIC_KEY,
+					parameters: Some(Any::encode_from(&curve)?),
+				};
+				(algorithm, point.to_owned())
+			}
+		};
+		Ok(SubjectPublicKeyInfoOwned {
+			algorithm,
+			subject_public_key: BitString::from_bytes(&public_key)?,
+		})
+	}
+
+	/// Return the public key as a DER-encoded SubjectPublicKeyInfo.
+	pub(crate) fn to_der(&self) -> Result<Vec<u8>, Error> {
+		Ok(self.public_key_info()?.to_der()?)
+	}
+
+	pub(crate) fn to_pem(&self) -> Result<Vec<u8>, Error> {
+		let pem = self.public_key_info()?.to_pem(LineEnding::LF)?;
+		Ok(pem.into_bytes())
+	}
+
+	pub(crate) fn jwk(&self, thumbprint: bool) -> Result<Value, Error> {
+		let jwk = match self {
+			Pkcs11PublicKey::Rsa { n, e } => {
+				let e = b64_encode(e);
+				let n = b64_encode(n);
+				if thumbprint {
+					json!({
+						"kty": "RSA",
+						"e": e,
+						"n": n,
+					})
+				} else {
+					json!({
+						"alg": "RS256",
+						"kty": "RSA",
+						"use": "sig",
+						"e": e,
+						"n": n,
+					})
+				}
+			}
+			Pkcs11PublicKey::Ec { key_type, point } => {
+				let (crv, alg) = match key_type {
+					KeyType::EcdsaP256 => ("P-256", "ES256"),
+					KeyType::EcdsaP384 => ("P-384", "ES384"),
+					KeyType::EcdsaP521 => ("P-521", "ES512"),
+					_ => {
+						return Err("not an ECDSA elliptic curve".into());
+					}
+				};
+				let (x, y) = point[1..].split_at((point.len() - 1) / 2);
+				let x = b64_encode(x);
+				let y = b64_encode(y);
+				if thumbprint {
+					json!({
+						"crv": crv,
+						"kty": "EC",
+						"x": x,
+						"y": y,
+					})
+				} else {
+					json!({
+						"alg": alg,
+						"crv": crv,
+						"kty": "EC",
+						"use": "sig",
+						"x": x,
+						"y": y,
+					})
+				}
+			}
+		};
+		Ok(jwk)
+	}
+
+	/// Return the identifier of the algorithm used to sign X.509 structures
+	/// using this key and the given digest.
+	pub(crate) fn x509_signature_algorithm(
+		&self,
+		digest: &HashFunction,
+	) -> AlgorithmIdentifierOwned {
+		let (oid, parameters) = match (self, digest) {
+			(Pkcs11PublicKey::Rsa { .. }, HashFunction::Sha256) => {
+				(OID_RSA_SHA256, Some(Any::null()))
+			}
+			(Pkcs11PublicKey::Rsa { .. }, HashFunction::Sha384) => {
+				(OID_RSA_SHA384, Some(Any::null()))
+			}
+			(Pkcs11PublicKey::Rsa { .. }, HashFunction::Sha512) => {
+				(OID_RSA_SHA512, Some(Any::null()))
+			}
+			(Pkcs11PublicKey::Ec { .. }, HashFunction::Sha256) => (OID_ECDSA_SHA256, None),
+			(Pkcs11PublicKey::Ec { .. }, HashFunction::Sha384) => (OID_ECDSA_SHA384, None),
+			(Pkcs11PublicKey::Ec { .. }, HashFunction::Sha512) => (OID_ECDSA_SHA512, None),
+		};
+		AlgorithmIdentifierOwned { oid, parameters }
+	}
+}
+
+/// Private key stored in a PKCS #11 token. The key never leaves the token,
+/// which is only used to sign data.
+#[derive(Clone)]
+pub(crate) struct Pkcs11Key {
+	uri: String,
+	public_key: Pkcs11PublicKey,
+	session: Arc<Mutex<Session>>,
+	handle: ObjectHandle,
+}
+
+impl fmt::Debug for Pkcs11Key {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("Pkcs11Key")
+			.field("public_key", &self.public_key)
+			.finish_non_exhaustive()
+	}
+}
+
+impl Pkcs11Key {
+	pub(crate) fn open(uri: &str) -> Result<Self, Error> {
+		let parsed_uri: Pkcs11Uri = uri.parse()?;
+		let context = get_context(&parsed_uri.module_path)?;
+		let slot = context
+			.get_slots_with_token()?
+			.into_iter()
+			.find(|slot| slot_matches(&context, *slot, &parsed_uri))
+			.ok_or_else(|| Error::from("no matching PKCS #11 token found"))?;
+		let session = context.open_ro_session(slot)?;
+		if let Some(pin) = parsed_uri.get_pin()? {
+			match session.login(UserType::User, Some(&AuthPin::from(pin))) {
+				Ok(_) | Err(Pkcs11Error::Pkcs11(RvError::UserAlreadyLoggedIn, _)) => {}
+				Err(e) => {
+					return Err(Error::from(e).prefix("unable to log into the PKCS #11 token"));
+				}
+			};
+		}
+		let handle = find_object(&session, &parsed_uri, ObjectClass::PRIVATE_KEY)?
+			.ok_or_else(|| Error::from("private key not found in the PKCS #11 token"))?;
+		let public_key = get_public_key(&session, &parsed_uri, handle)?;
+		Ok(Pkcs11Key {
+			uri: pkcs11_uri_without_pin(uri),
+			public_key,
+			session: Arc::new(Mutex::new(session)),
+			handle,
+		})
+	}
+
+	pub(crate) fn uri(&self) -> &str {
+		&self.uri
+	}
+
+	pub(crate) fn public_key(&self) -> &Pkcs11PublicKey {
+		&self.public_key
+	}
+
+	pub(crate) fn export_error(&self) -> Error {
+		format!(
+			"{}: keys stored in a PKCS #11 token cannot be exported",
+			self.uri
+		)
+		.into()
+	}
+
+	fn sign_raw(&self, mechanism: &Mechanism, data: &[u8]) -> Result<Vec<u8>, Error> {
+		let session = self
+			.session
+			.lock()
+			.map_err(|_| Error::from("PKCS #11 session unavailable"))?;
+		let signature = session.sign(mechanism, self.handle, data)?;
+		Ok(signature)
+	}
+
+	fn sign_rsa(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		let mechanism = match hash_func {
+			HashFunction::Sha256 => Mechanism::Sha256RsaPkcs,
+			HashFunction::Sha384 => Mechanism::Sha384RsaPkcs,
+			HashFunction::Sha512 => Mechanism::Sha512RsaPkcs,
+		};
+		self.sign_raw(&mechanism, data)
+	}
+
+	fn sign_rsa_pss(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		// The salt length is equal to the digest length.
+		let (hash_alg, mgf, s_len) = match hash_func {
+			HashFunction::Sha256 => (MechanismType::SHA256, PkcsMgfType::MGF1_SHA256, 32),
+			HashFunction::Sha384 => (MechanismType::SHA384, PkcsMgfType::MGF1_SHA384, 48),
+			HashFunction::Sha512 => (MechanismType::SHA512, PkcsMgfType::MGF1_SHA512, 64),
+		};
+		let params = PkcsPssParams {
+			hash_alg,
+			mgf,
+			s_len: (s_len as u64).into(),
+		};
+		let mechanism = match hash_func {
+			HashFunction::Sha256 => Mechanism::Sha256RsaPkcsPss(params),
+			HashFunction::Sha384 => Mechanism::Sha384RsaPkcsPss(params),
+			HashFunction::Sha512 => Mechanism::Sha512RsaPkcsPss(params),
+		};
+		self.sign_raw(&mechanism, data)
+	}
+
+	/// The signature is the concatenation of `r` and `s`, as expected by
+	/// JWS.
+	fn sign_ecdsa(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		self.sign_raw(&Mechanism::Ecdsa, &hash_func.hash(data))
+	}
+
+	pub(crate) fn sign(&self, alg: &JwsSignatureAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+		match alg {
+			JwsSignatureAlgorithm::Rs256 => self.sign_rsa(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Rs384 => self.sign_rsa(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Rs512 => self.sign_rsa(&HashFunction::Sha512, data),
+			JwsSignatureAlgorithm::Ps256 => self.sign_rsa_pss(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Ps384 => self.sign_rsa_pss(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Ps512 => self.sign_rsa_pss(&HashFunction::Sha512, data),
+			JwsSignatureAlgorithm::Es256 => self.sign_ecdsa(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Es384 => self.sign_ecdsa(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Es512 => self.sign_ecdsa(&HashFunction::Sha512, data),
+			_ => Err(format!("{alg}: signature algorithm not supported with PKCS #11 keys").into()),
+		}
+	}
+
+	/// Sign an X.509 structure, the signature being encoded as required by
+	/// the algorithm returned by `Pkcs11PublicKey::x509_signature_algorithm`.
+	pub(crate) fn x509_sign(&self, digest: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		match self.public_key {
+			Pkcs11PublicKey::Rsa { .. } => self.sign_rsa(digest, data),
+			Pkcs11PublicKey::Ec { .. } => {
+				let signature = self.sign_ecdsa(digest, data)?;
+				ecdsa_signature_to_der(&signature)
+			}
+		}
+	}
+}
+
+// A PKCS #11 library can only be initialized once per process, hence the
+// contexts are shared between all keys.
+fn get_context(module_path: &str) -> Result<Pkcs11, Error> {
+	static CONTEXTS: OnceLock<Mutex<HashMap<String, Pkcs11>>> = OnceLock::new();
+	let mut contexts = CONTEXTS
+		.get_or_init(|| Mutex::new(HashMap::new()))
+		.lock()
+		.map_err(|_| Error::from("PKCS #11 contexts unavailable"))?;
+	if let Some(ctx) = contexts.get(module_path) {
+		return Ok(ctx.clone());
+	}
+	let ctx = Pkcs11::new(module_path).map_err(|e| Error::from(e).prefix(module_path))?;
+	ctx.initialize(CInitializeArgs::new(CInitializeFlags::OS_LOCKING_OK))
+		.map_err(|e| Error::from(e).prefix(module_path))?;
+	contexts.insert(module_path.to_string(), ctx.clone());
+	Ok(ctx)
+}
+
+fn slot_matches(context: &Pkcs11, slot: cryptoki::slot::Slot, uri: &Pkcs11Uri) -> bool {
+	if let Some(id) = uri.slot_id {
+		if slot.id() != id {
+			return false;
+		}
+	}
+	let info = match context.get_token_info(slot) {
+		Ok(i) => i,
+		Err(_) => {
+			return false;
+		}
+	};
+	let matches = |expected: &Option<String>, value: &str| match expected {
+		Some(e) => e.trim_end() == value.trim_end(),
+		None => true,
+	};
+	matches(&uri.token, info.label())
+		&& matches(&uri.manufacturer, info.manufacturer_id())
+		&& matches(&uri.model, info.model())
+		&& matches(&uri.serial, info.serial_number())
+}
+
+fn find_object(
+	session: &Session,
+	uri: &Pkcs11Uri,
+	class: ObjectClass,
+) -> Result<Option<ObjectHandle>, Error> {
+	let mut template = vec![Attribute::Class(class)];
+	if let Some(label) = &uri.object {
+		template.push(Attribute::Label(label.as_bytes().to_vec()));
+	}
+	if let Some(id) = &uri.id {
+		template.push(Attribute::Id(id.to_owned()));
+	}
+	let objects = session.find_objects(&template)?;
+	match objects.len() {
+		0 => Ok(None),
+		1 => Ok(Some(objects[0])),
+		_ => Err("several keys match the PKCS #11 URI".into()),
+	}
+}
+
+fn get_attribute(
+	session: &Session,
+	handle: ObjectHandle,
+	attr_type: AttributeType,
+) -> Result<Attribute, Error> {
+	session
+		.get_attributes(handle, &[attr_type])?
+		.into_iter()
+		.next()
+		.ok_or_else(|| Error::from(format!("{attr_type}: attribute not found")))
+}
+
+fn get_bytes_attribute(
+	session: &Session,
+	handle: ObjectHandle,
+	attr_type: AttributeType,
+) -> Result<Vec<u8>, Error> {
+	match get_attribute(session, handle, attr_type)? {
+		Attribute::Modulus(v)
+		| Attribute::PublicExponent(v)
+		| Attribute::EcParams(v)
+		| Attribute::EcPoint(v) => Ok(v),
+		_ => Err(format!("{attr_type}: unexpected attribute").into()),
+	}
+}
+
+fn get_public_key(
+	session: &Session,
+	uri: &Pkcs11Uri,
+	private_key: ObjectHandle,
+) -> Result<Pkcs11PublicKey, Error> {
+	let key_type = match get_attribute(session, private_key, AttributeType::KeyType)? {
+		Attribute::KeyType(kt) => kt,
+		_ => {
+			return Err("unable to retrieve the key type".into());
+		}
+	};
+	if key_type == Pkcs11KeyType::RSA {
+		let n = get_bytes_attribute(session, private_key, AttributeType::Modulus)?;
+		let e = get_bytes_attribute(session, private_key, AttributeType::PublicExponent)?;
+		Ok(Pkcs11PublicKey::from_rsa_components(&n, &e))
+	} else if key_type == Pkcs11KeyType::EC {
+		// The EC point is only available on the public key object.
+		let public_key = find_object(session, uri, ObjectClass::PUBLIC_KEY)?
+			.ok_or_else(|| Error::from("public key not found in the PKCS #11 token"))?;
+		let params = get_bytes_attribute(session, public_key, AttributeType::EcParams)?;
+		let point = get_bytes_attribute(session, public_key, AttributeType::EcPoint)?;
+		Pkcs11PublicKey::from_ec_components(&params, &point)
+	} else {
+		Err(format!("{key_type}: unsupported PKCS #11 key type").into())
+	}
+}
+
+fn strip_leading_zeros(data: &[u8]) -> &[u8] {
+	let start = data
+		.iter()
+		.position(|b| *b != 0)
+		.unwrap_or(data.len().saturating_sub(1));
+	&data[start..]
+}
+
+/// Convert an ECDSA signature from the concatenation of `r` and `s` to the
+/// DER-encoded Ecdsa-Sig-Value structure (RFC 3279) used in X.509.
+pub(crate) fn ecdsa_signature_to_der(signature: &[u8]) -> Result<Vec<u8>, Error> {
+	if signature.is_empty() || signature.len() % 2 != 0 {
+		return Err("invalid ECDSA signature".into());
+	}
+	let (r, s) = signature.split_at(signature.len() / 2);
+	let signature = EcdsaSigValue {
+		r: UintRef::new(r)?,
+		s: UintRef::new(s)?,
+	};
+	Ok(signature.to_der()?)
+}
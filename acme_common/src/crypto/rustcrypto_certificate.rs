@@ -0,0 +1,375 @@
+use super::rustcrypto_keys::get_public_key_type;
+use super::x509_extensions::{
+	get_csr_subject_alt_names, get_extended_key_usage, get_general_names, get_key_usage,
+};
+use super::x509_request::{build_cert_req, get_extension, get_san_extension};
+use super::{
+	gen_keypair, CertificateInspector, CsrBuilder, CsrInspector, ExtendedKeyUsage, KeyPair,
+	KeyType, KeyUsage, Signer, SubjectAttribute,
+};
+use crate::b64_encode;
+use crate::crypto::HashFunction;
+use crate::error::Error;
+use der::asn1::{BitString, ObjectIdentifier, OctetString, SetOfVec, Utf8StringRef};
+use der::{Any, Decode, DecodePem, Encode, EncodePem};
+use pkcs8::LineEnding;
+use rand_core::{OsRng, RngCore};
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+use x509_cert::attr::AttributeTypeAndValue;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{
+	AuthorityInfoAccessSyntax, AuthorityKeyIdentifier, BasicConstraints, SubjectAltName,
+	SubjectKeyIdentifier,
+};
+use x509_cert::ext::Extension;
+use x509_cert::name::{RdnSequence, RelativeDistinguishedName};
+use x509_cert::request::CertReq;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::spki::SubjectPublicKeyInfoOwned;
+use x509_cert::time::Validity;
+use x509_cert::{Certificate, TbsCertificate};
+
+const OID_ORGANIZATION_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.10");
+const OID_COMMON_NAME: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.4.3");
+const OID_ACCESS_OCSP: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.1");
+const OID_ACCESS_CA_ISSUERS: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.48.2");
+
+fn get_public_key_info(key_pair: &KeyPair) -> Result<SubjectPublicKeyInfoOwned, Error> {
+	let der = key_pair.public_key_to_der()?;
+	Ok(SubjectPublicKeyInfoOwned::from_der(&der)?)
+}
+
+pub struct Csr {
+	inner_csr: CertReq,
+}
+
+impl Csr {
+	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
+		Ok(Csr {
+			inner_csr: CertReq::from_pem(pem_data)?,
+		})
+	}
+}
+
+impl CsrInspector for Csr {
+	fn key_type(&self) -> Result<KeyType, Error> {
+		let der = self.inner_csr.info.public_key.to_der()?;
+		get_public_key_type(&der)
+	}
+
+	fn subject_alt_names(&self) -> Result<HashSet<String>, Error> {
+		get_csr_subject_alt_names(&self.inner_csr)
+	}
+}
+
+impl CsrBuilder<KeyPair> for Csr {
+	fn build(
+		key_pair: &KeyPair,
+		digest: HashFunction,
+		domains: &[String],
+		ips: &[String],
+		subject_attributes: &HashMap<SubjectAttribute, String>,
+	) -> Result<Self, Error> {
+		let inner_csr = build_cert_req(
+			get_public_key_info(key_pair)?,
+			key_pair.x509_signature_algorithm(&digest)?,
+			domains,
+			ips,
+			subject_attributes,
+			|cri| key_pair.x509_sign(&digest, cri),
+		)?;
+		Ok(Csr { inner_csr })
+	}
+
+	fn to_der_base64(&self) -> Result<String, Error> {
+		let csr = self.inner_csr.to_der()?;
+		let csr = b64_encode(&csr);
+		Ok(csr)
+	}
+
+	fn to_pem(&self) -> Result<String, Error> {
+		Ok(self.inner_csr.to_pem(LineEnding::LF)?)
+	}
+}
+
+fn load_pem_chain(pem_data: &[u8]) -> Result<Vec<Certificate>, Error> {
+	// Certificate::load_pem_chain panics on empty input.
+	if pem_data.iter().all(|c| c.is_ascii_whitespace()) {
+		return Err("no certificate found".into());
+	}
+	Ok(Certificate::load_pem_chain(pem_data)?)
+}
+
+pub struct X509Certificate {
+	inner_cert: Certificate,
+}
+
+impl X509Certificate {
+	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
+		// The PEM data may contain the whole certificate chain.
+		let inner_cert = load_pem_chain(pem_data)?
+			.into_iter()
+			.next()
+			.ok_or_else(|| Error::from("no certificate found"))?;
+		Ok(X509Certificate { inner_cert })
+	}
+
+	/// Load every certificate of a PEM bundle, such as a certificate chain,
+	/// in the order they appear.
+	pub fn from_pem_chain(pem_data: &[u8]) -> Result<Vec<Self>, Error> {
+		let chain: Vec<Self> = load_pem_chain(pem_data)?
+			.into_iter()
+			.map(|inner_cert| X509Certificate { inner_cert })
+			.collect();
+		if chain.is_empty() {
+			return Err("no certificate found".into());
+		}
+		Ok(chain)
+	}
+
+	fn get_access_locations(&self, method: ObjectIdentifier) -> Result<Vec<String>, Error> {
+		let aia = match self
+			.inner_cert
+			.tbs_certificate
+			.get::<AuthorityInfoAccessSyntax>()?
+		{
+			Some((_, aia)) => aia,
+			None => return Ok(vec![]),
+		};
+		let locations = aia
+			.0
+			.iter()
+			.filter(|ad| ad.access_method == method)
+			.filter_map(|ad| match &ad.access_location {
+				GeneralName::UniformResourceIdentifier(uri) => Some(uri.to_string()),
+				_ => None,
+			})
+			.collect();
+		Ok(locations)
+	}
+
+	pub fn from_acme_ext(
+		domain: &str,
+		acme_ext: &str,
+		key_type: KeyType,
+		digest: HashFunction,
+	) -> Result<(KeyPair, Self), Error> {
+		let key_pair = gen_keypair(key_type)?;
+		let inner_cert = gen_certificate(domain, &key_pair, &digest, acme_ext)?;
+		let cert = X509Certificate { inner_cert };
+		Ok((key_pair, cert))
+	}
+}
+
+impl CertificateInspector for X509Certificate {
+	fn to_der(&self) -> Result<Vec<u8>, Error> {
+		Ok(self.inner_cert.to_der()?)
+	}
+
+	fn serial_number(&self) -> Result<String, Error> {
+		// The DER encoding may start with a zero byte which only prevents the
+		// number from being negative and is not part of its value.
+		let serial = self
+			.inner_cert
+			.tbs_certificate
+			.serial_number
+			.as_bytes()
+			.iter()
+			.skip_while(|b| **b == 0)
+			.map(|b| format!("{b:02X}"))
+			.collect::<String>();
+		if serial.is_empty() {
+			return Ok("0".to_string());
+		}
+		Ok(serial)
+	}
+
+	fn issuer(&self) -> Result<String, Error> {
+		Ok(self.inner_cert.tbs_certificate.issuer.to_string())
+	}
+
+	fn subject(&self) -> Result<String, Error> {
+		Ok(self.inner_cert.tbs_certificate.subject.to_string())
+	}
+
+	fn not_before(&self) -> Result<SystemTime, Error> {
+		Ok(self
+			.inner_cert
+			.tbs_certificate
+			.validity
+			.not_before
+			.to_system_time())
+	}
+
+	fn not_after(&self) -> Result<SystemTime, Error> {
+		Ok(self
+			.inner_cert
+			.tbs_certificate
+			.validity
+			.not_after
+			.to_system_time())
+	}
+
+	fn expires_in(&self) -> Result<Duration, Error> {
+		let not_after = self
+			.inner_cert
+			.tbs_certificate
+			.validity
+			.not_after
+			.to_system_time();
+		Ok(not_after
+			.duration_since(SystemTime::now())
+			.unwrap_or(Duration::ZERO))
+	}
+
+	fn lifetime(&self) -> Result<Duration, Error> {
+		let validity = &self.inner_cert.tbs_certificate.validity;
+		Ok(validity
+			.not_after
+			.to_system_time()
+			.duration_since(validity.not_before.to_system_time())
+			.unwrap_or(Duration::ZERO))
+	}
+
+	fn subject_alt_names(&self) -> HashSet<String> {
+		match self.inner_cert.tbs_certificate.get::<SubjectAltName>() {
+			Ok(Some((_, san))) => get_general_names(&san.0),
+			_ => HashSet::new(),
+		}
+	}
+
+	fn authority_key_identifier(&self) -> Result<Option<Vec<u8>>, Error> {
+		let aki = self
+			.inner_cert
+			.tbs_certificate
+			.get::<AuthorityKeyIdentifier>()?;
+		Ok(aki.and_then(|(_, aki)| aki.key_identifier.map(|id| id.as_bytes().to_vec())))
+	}
+
+	fn subject_key_identifier(&self) -> Result<Option<Vec<u8>>, Error> {
+		let ski = self
+			.inner_cert
+			.tbs_certificate
+			.get::<SubjectKeyIdentifier>()?;
+		Ok(ski.map(|(_, ski)| ski.0.as_bytes().to_vec()))
+	}
+
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error> {
+		Ok(self
+			.inner_cert
+			.tbs_certificate
+			.subject_public_key_info
+			.to_der()?)
+	}
+
+	fn key_type(&self) -> Result<KeyType, Error> {
+		get_public_key_type(&self.public_key_to_der()?)
+	}
+
+	fn key_usage(&self) -> Result<Option<HashSet<KeyUsage>>, Error> {
+		get_key_usage(&self.inner_cert.tbs_certificate)
+	}
+
+	fn extended_key_usage(&self) -> Result<Option<Vec<ExtendedKeyUsage>>, Error> {
+		get_extended_key_usage(&self.inner_cert.tbs_certificate)
+	}
+
+	fn ocsp_urls(&self) -> Result<Vec<String>, Error> {
+		self.get_access_locations(OID_ACCESS_OCSP)
+	}
+
+	fn ca_issuers_urls(&self) -> Result<Vec<String>, Error> {
+		self.get_access_locations(OID_ACCESS_CA_ISSUERS)
+	}
+}
+
+fn get_acme_extension(acme_ext: &str) -> Result<Extension, Error> {
+	let mut v: Vec<&str> = acme_ext.split('=').collect();
+	let value = v.pop().ok_or_else(|| Error::from(super::INVALID_EXT_MSG))?;
+	let acme_ext_name = v.pop().ok_or_else(|| Error::from(super::INVALID_EXT_MSG))?;
+	if !v.is_empty() {
+		return Err(Error::from(super::INVALID_EXT_MSG));
+	}
+	let extn_id =
+		ObjectIdentifier::new(acme_ext_name).map_err(|_| Error::from(super::INVALID_EXT_MSG))?;
+	let (critical, value) = match value.strip_prefix("critical,") {
+		Some(v) => (true, v),
+		None => (false, value),
+	};
+	let value = value
+		.strip_prefix("DER:")
+		.ok_or_else(|| Error::from(super::INVALID_EXT_MSG))?
+		.split(':')
+		.map(|b| u8::from_str_radix(b, 16))
+		.collect::<Result<Vec<u8>, _>>()
+		.map_err(|_| Error::from(super::INVALID_EXT_MSG))?;
+	Ok(Extension {
+		extn_id,
+		critical,
+		extn_value: OctetString::new(value)?,
+	})
+}
+
+fn gen_certificate(
+	domain: &str,
+	key_pair: &KeyPair,
+	digest: &HashFunction,
+	acme_ext: &str,
+) -> Result<Certificate, Error> {
+	let ca_name = format!("{} TLS-ALPN-01 Authority", super::APP_NAME);
+	let x509_name = RdnSequence(vec![
+		RelativeDistinguishedName(SetOfVec::try_from(vec![AttributeTypeAndValue {
+			oid: OID_ORGANIZATION_NAME,
+			value: Any::encode_from(&Utf8StringRef::new(super::APP_ORG)?)?,
+		}])?),
+		RelativeDistinguishedName(SetOfVec::try_from(vec![AttributeTypeAndValue {
+			oid: OID_COMMON_NAME,
+			value: Any::encode_from(&Utf8StringRef::new(&ca_name)?)?,
+		}])?),
+	]);
+	let serial_number = {
+		let mut serial = [0u8; (super::CRT_SERIAL_NB_BITS / 8) as usize];
+		OsRng.fill_bytes(&mut serial);
+		serial[0] &= 0x7f;
+		SerialNumber::new(&serial)?
+	};
+	let validity = Validity::from_now(Duration::from_secs(
+		u64::from(super::CRT_NB_DAYS_VALIDITY) * 24 * 60 * 60,
+	))?;
+
+	let mut extensions = vec![
+		get_extension(
+			&BasicConstraints {
+				ca: false,
+				path_len_constraint: None,
+			},
+			false,
+		)?,
+		get_san_extension(&[domain.to_string()], &[])?,
+	];
+	if !acme_ext.is_empty() {
+		extensions.push(get_acme_extension(acme_ext)?);
+	}
+
+	let signature_algorithm = key_pair.x509_signature_algorithm(digest)?;
+	let tbs_certificate = TbsCertificate {
+		version: x509_cert::Version::V3,
+		serial_number,
+		signature: signature_algorithm.clone(),
+		issuer: x509_name.clone(),
+		validity,
+		subject: x509_name,
+		subject_public_key_info: get_public_key_info(key_pair)?,
+		issuer_unique_id: None,
+		subject_unique_id: None,
+		extensions: Some(extensions),
+	};
+	let signature = key_pair.x509_sign(digest, &tbs_certificate.to_der()?)?;
+	Ok(Certificate {
+		tbs_certificate,
+		signature_algorithm,
+		signature: BitString::from_bytes(&signature)?,
+	})
+}
@@ -0,0 +1,57 @@
+use crate::error::Error;
+use aes_gcm::aead::consts::U12;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use pbkdf2::pbkdf2_hmac;
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+use std::convert::TryInto;
+
+pub(crate) fn random_bytes(buf: &mut [u8]) -> Result<(), Error> {
+	OsRng
+		.try_fill_bytes(buf)
+		.map_err(|e| Error::from(e.to_string()))
+}
+
+pub(crate) fn derive_key(
+	passphrase: &[u8],
+	salt: &[u8],
+	iterations: u32,
+) -> Result<[u8; super::AES_256_KEY_LEN], Error> {
+	let mut key = [0u8; super::AES_256_KEY_LEN];
+	pbkdf2_hmac::<Sha256>(passphrase, salt, iterations, &mut key);
+	Ok(key)
+}
+
+fn get_cipher(key: &[u8]) -> Result<Aes256Gcm, Error> {
+	Aes256Gcm::new_from_slice(key).map_err(|_| Error::from("invalid AES-256 key length"))
+}
+
+fn get_nonce(nonce: &[u8]) -> Result<Nonce<U12>, Error> {
+	let nonce: [u8; 12] = nonce
+		.try_into()
+		.map_err(|_| Error::from("invalid AES-GCM nonce length"))?;
+	Ok(nonce.into())
+}
+
+pub(crate) fn aes_256_gcm_encrypt(
+	key: &[u8],
+	nonce: &[u8],
+	aad: &[u8],
+	data: &[u8],
+) -> Result<Vec<u8>, Error> {
+	get_cipher(key)?
+		.encrypt(&get_nonce(nonce)?, Payload { msg: data, aad })
+		.map_err(|_| Error::from("unable to encrypt data"))
+}
+
+pub(crate) fn aes_256_gcm_decrypt(
+	key: &[u8],
+	nonce: &[u8],
+	aad: &[u8],
+	data: &[u8],
+) -> Result<Vec<u8>, Error> {
+	get_cipher(key)?
+		.decrypt(&get_nonce(nonce)?, Payload { msg: data, aad })
+		.map_err(|_| Error::from("unable to decrypt data: invalid passphrase or corrupted data"))
+}
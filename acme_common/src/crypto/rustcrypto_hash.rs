@@ -0,0 +1,33 @@
+use crate::error::Error;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+
+pub type HashFunction = super::BaseHashFunction;
+
+macro_rules! get_hmac {
+	($hash: ty, $key: expr, $data: expr) => {{
+		let mut mac = Hmac::<$hash>::new_from_slice($key)
+			.map_err(|_| Error::from("invalid HMAC key length"))?;
+		mac.update($data);
+		mac.finalize().into_bytes().to_vec()
+	}};
+}
+
+impl HashFunction {
+	pub fn hash(&self, data: &[u8]) -> Vec<u8> {
+		match self {
+			HashFunction::Sha256 => Sha256::digest(data).to_vec(),
+			HashFunction::Sha384 => Sha384::digest(data).to_vec(),
+			HashFunction::Sha512 => Sha512::digest(data).to_vec(),
+		}
+	}
+
+	pub fn hmac(&self, key: &[u8], data: &[u8]) -> Result<Vec<u8>, Error> {
+		let res = match self {
+			HashFunction::Sha256 => get_hmac!(Sha256, key, data),
+			HashFunction::Sha384 => get_hmac!(Sha384, key, data),
+			HashFunction::Sha512 => get_hmac!(Sha512, key, data),
+		};
+		Ok(res)
+	}
+}
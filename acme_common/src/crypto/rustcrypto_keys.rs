@@ -0,0 +1,773 @@
+use crate::b64_encode;
+#[cfg(feature = "pkcs11")]
+use crate::crypto::pkcs11::Pkcs11Key;
+use crate::crypto::{
+	pkcs11_uri_without_pin, HashFunction, JwsSignatureAlgorithm, KeyGenerator, KeyType, Signer,
+};
+use crate::error::Error;
+use der::asn1::ObjectIdentifier;
+use der::{Any, Decode};
+#[cfg(feature = "ml_dsa")]
+use ml_dsa::{Keypair as _, MlDsa44, MlDsa65, MlDsa87};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::elliptic_curve::ALGORITHM_OID as EC_ALGORITHM_OID;
+use pkcs8::pkcs5::pbes2;
+use pkcs8::{
+	DecodePrivateKey, DecodePublicKey, EncodePrivateKey, EncodePublicKey, EncryptedPrivateKeyInfo,
+	LineEnding, PrivateKeyInfo,
+};
+use rand_core::{OsRng, RngCore};
+use rsa::pkcs1::{DecodeRsaPrivateKey, ALGORITHM_OID as RSA_ALGORITHM_OID};
+use rsa::traits::PublicKeyParts;
+use rsa::{Pkcs1v15Sign, Pss, RsaPrivateKey, RsaPublicKey};
+use serde_json::json;
+use serde_json::value::Value;
+use sha2::{Sha256, Sha384, Sha512};
+use std::fmt;
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoRef};
+
+const OID_P256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const OID_P384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const OID_P521: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.35");
+const OID_ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+const OID_RSA_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+const OID_RSA_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12");
+const OID_RSA_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13");
+const OID_ECDSA_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const OID_ECDSA_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const OID_ECDSA_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.4");
+const ENCRYPTED_PEM_LABEL: &str = "ENCRYPTED PRIVATE KEY";
+#[cfg(feature = "ml_dsa")]
+const OID_ML_DSA_44: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.17");
+#[cfg(feature = "ml_dsa")]
+const OID_ML_DSA_65: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.18");
+#[cfg(feature = "ml_dsa")]
+const OID_ML_DSA_87: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.3.19");
+
+macro_rules! sign_ecdsa {
+	($signature: ty, $signature_expr: expr, $der: expr) => {{
+		let signature: $signature = $signature_expr?;
+		if $der {
+			signature.to_der().as_bytes().to_vec()
+		} else {
+			signature.to_bytes().to_vec()
+		}
+	}};
+}
+
+// The ml-dsa crate relies on newer versions of the pkcs8 and spki crates, so
+// its keys are exchanged with the rest of the backend using their DER form.
+#[cfg(feature = "ml_dsa")]
+macro_rules! ml_dsa_der {
+	($e: expr) => {
+		$e.map(|doc| doc.as_bytes().to_vec())
+			.map_err(|e| Error::from(e.to_string()))
+	};
+}
+
+#[derive(Clone)]
+enum InnerKey {
+	Rsa(RsaPrivateKey),
+	EcdsaP256(p256::SecretKey),
+	EcdsaP384(p384::SecretKey),
+	EcdsaP521(p521::SecretKey),
+	#[cfg(feature = "ed25519")]
+	Ed25519(ed25519_dalek::SigningKey),
+	#[cfg(feature = "ml_dsa")]
+	MlDsa44(Box<ml_dsa::SigningKey<MlDsa44>>),
+	#[cfg(feature = "ml_dsa")]
+	MlDsa65(Box<ml_dsa::SigningKey<MlDsa65>>),
+	#[cfg(feature = "ml_dsa")]
+	MlDsa87(Box<ml_dsa::SigningKey<MlDsa87>>),
+	#[cfg(feature = "pkcs11")]
+	Pkcs11(Pkcs11Key),
+}
+
+#[derive(Clone)]
+pub struct KeyPair {
+	pub key_type: KeyType,
+	inner_key: InnerKey,
+}
+
+// The private key must never be displayed.
+impl fmt::Debug for KeyPair {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_struct("KeyPair")
+			.field("key_type", &self.key_type)
+			.finish_non_exhaustive()
+	}
+}
+
+fn get_rsa_key_type(key: &RsaPrivateKey) -> Result<KeyType, Error> {
+	KeyType::from_rsa_size(key.n().bits() as u32)
+}
+
+fn get_ec_key(curve: ObjectIdentifier, der_data: &[u8], is_pkcs8: bool) -> Result<KeyPair, Error> {
+	macro_rules! load_ec_key {
+		($key_type: expr, $variant: ident, $secret_key: ty) => {{
+			let key = if is_pkcs8 {
+				<$secret_key>::from_pkcs8_der(der_data)?
+			} else {
+				<$secret_key>::from_sec1_der(der_data)?
+			};
+			KeyPair {
+				key_type: $key_type,
+				inner_key: InnerKey::$variant(key),
+			}
+		}};
+	}
+
+	let key_pair = match curve {
+		OID_P256 => load_ec_key!(KeyType::EcdsaP256, EcdsaP256, p256::SecretKey),
+		OID_P384 => load_ec_key!(KeyType::EcdsaP384, EcdsaP384, p384::SecretKey),
+		OID_P521 => load_ec_key!(KeyType::EcdsaP521, EcdsaP521, p521::SecretKey),
+		oid => {
+			return Err(format!("{oid}: unsupported EC key").into());
+		}
+	};
+	Ok(key_pair)
+}
+
+#[cfg(feature = "ml_dsa")]
+fn get_ml_dsa_key(oid: ObjectIdentifier, der_data: &[u8]) -> Result<KeyPair, Error> {
+	use ml_dsa::pkcs8::DecodePrivateKey as _;
+
+	macro_rules! load_ml_dsa_key {
+		($key_type: expr, $variant: ident, $params: ty) => {{
+			let key = ml_dsa::SigningKey::<$params>::from_pkcs8_der(der_data)
+				.map_err(|e| Error::from(e.to_string()))?;
+			KeyPair {
+				key_type: $key_type,
+				inner_key: InnerKey::$variant(Box::new(key)),
+			}
+		}};
+	}
+
+	let key_pair = match oid {
+		OID_ML_DSA_44 => load_ml_dsa_key!(KeyType::MlDsa44, MlDsa44, MlDsa44),
+		OID_ML_DSA_65 => load_ml_dsa_key!(KeyType::MlDsa65, MlDsa65, MlDsa65),
+		OID_ML_DSA_87 => load_ml_dsa_key!(KeyType::MlDsa87, MlDsa87, MlDsa87),
+		oid => {
+			return Err(format!("{oid}: unsupported ML-DSA key").into());
+		}
+	};
+	Ok(key_pair)
+}
+
+#[cfg(feature = "ml_dsa")]
+fn gen_ml_dsa_seed() -> ml_dsa::Seed {
+	let mut seed = ml_dsa::Seed::default();
+	OsRng.fill_bytes(&mut seed);
+	seed
+}
+
+fn pad_hash(hash: Vec<u8>, size: usize) -> Vec<u8> {
+	if hash.len() >= size {
+		return hash;
+	}
+	let mut padded = vec![0; size - hash.len()];
+	padded.extend_from_slice(&hash);
+	padded
+}
+
+impl KeyPair {
+	pub fn from_der(der_data: &[u8]) -> Result<Self, Error> {
+		let pki = match PrivateKeyInfo::from_der(der_data) {
+			Ok(pki) => pki,
+			Err(_) => return Self::from_traditional_der(der_data),
+		};
+		match pki.algorithm.oid {
+			RSA_ALGORITHM_OID => {
+				let key = RsaPrivateKey::from_pkcs8_der(der_data)?;
+				Ok(KeyPair {
+					key_type: get_rsa_key_type(&key)?,
+					inner_key: InnerKey::Rsa(key),
+				})
+			}
+			EC_ALGORITHM_OID => get_ec_key(pki.algorithm.parameters_oid()?, der_data, true),
+			#[cfg(feature = "ed25519")]
+			OID_ED25519 => Ok(KeyPair {
+				key_type: KeyType::Ed25519,
+				inner_key: InnerKey::Ed25519(ed25519_dalek::SigningKey::from_pkcs8_der(der_data)?),
+			}),
+			#[cfg(feature = "ml_dsa")]
+			oid @ (OID_ML_DSA_44 | OID_ML_DSA_65 | OID_ML_DSA_87) => get_ml_dsa_key(oid, der_data),
+			_ => Err("unsupported key type".into()),
+		}
+	}
+
+	// PKCS #1 and SEC1 keys, as exported by other backends.
+	fn from_traditional_der(der_data: &[u8]) -> Result<Self, Error> {
+		if let Ok(key) = RsaPrivateKey::from_pkcs1_der(der_data) {
+			return Ok(KeyPair {
+				key_type: get_rsa_key_type(&key)?,
+				inner_key: InnerKey::Rsa(key),
+			});
+		}
+		let ec_key = sec1::EcPrivateKey::from_der(der_data)?;
+		let curve = ec_key
+			.parameters
+			.and_then(|p| p.named_curve())
+			.ok_or_else(|| Error::from("unsupported EC key"))?;
+		get_ec_key(curve, der_data, false)
+	}
+
+	pub fn from_pem(pem_data: &[u8]) -> Result<Self, Error> {
+		let (_, der_data) = der::pem::decode_vec(pem_data)?;
+		Self::from_der(&der_data)
+	}
+
+	/// Load a private key from a PKCS #8 encrypted PEM. Unencrypted PEM
+	/// data is also accepted, in which case the passphrase is ignored.
+	pub fn from_pem_passphrase(pem_data: &[u8], passphrase: &[u8]) -> Result<Self, Error> {
+		let (label, der_data) = der::pem::decode_vec(pem_data)?;
+		if label != ENCRYPTED_PEM_LABEL {
+			return Self::from_der(&der_data);
+		}
+		let doc = EncryptedPrivateKeyInfo::from_der(&der_data)?.decrypt(passphrase)?;
+		Self::from_der(doc.as_bytes())
+	}
+
+	/// Open a private key stored in a PKCS #11 token.
+	#[cfg(feature = "pkcs11")]
+	pub fn from_pkcs11_uri(uri: &str) -> Result<Self, Error> {
+		let key = Pkcs11Key::open(uri).map_err(|e| e.prefix(&pkcs11_uri_without_pin(uri)))?;
+		Ok(KeyPair {
+			key_type: key.public_key().key_type()?,
+			inner_key: InnerKey::Pkcs11(key),
+		})
+	}
+
+	/// Open a private key stored in a PKCS #11 token.
+	#[cfg(not(feature = "pkcs11"))]
+	pub fn from_pkcs11_uri(uri: &str) -> Result<Self, Error> {
+		let msg = format!(
+			"{}: PKCS #11 support is disabled, rebuild with the pkcs11 feature",
+			pkcs11_uri_without_pin(uri)
+		);
+		Err(msg.into())
+	}
+
+	/// Return the URI of the key if it is stored in a PKCS #11 token. The
+	/// PIN is not included.
+	pub fn pkcs11_uri(&self) -> Option<&str> {
+		match &self.inner_key {
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(key) => Some(key.uri()),
+			_ => None,
+		}
+	}
+
+	pub fn private_key_to_der(&self) -> Result<Vec<u8>, Error> {
+		let doc = match &self.inner_key {
+			InnerKey::Rsa(k) => k.to_pkcs8_der()?,
+			InnerKey::EcdsaP256(k) => k.to_pkcs8_der()?,
+			InnerKey::EcdsaP384(k) => k.to_pkcs8_der()?,
+			InnerKey::EcdsaP521(k) => k.to_pkcs8_der()?,
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => k.to_pkcs8_der()?,
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(k) => return ml_dsa_der!(ml_dsa::pkcs8::EncodePrivateKey::to_pkcs8_der(&**k)),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa65(k) => return ml_dsa_der!(ml_dsa::pkcs8::EncodePrivateKey::to_pkcs8_der(&**k)),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa87(k) => return ml_dsa_der!(ml_dsa::pkcs8::EncodePrivateKey::to_pkcs8_der(&**k)),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => {
+				return Err(k.export_error());
+			}
+		};
+		Ok(doc.as_bytes().to_vec())
+	}
+
+	pub fn private_key_to_pem(&self) -> Result<Vec<u8>, Error> {
+		let pem = match &self.inner_key {
+			InnerKey::Rsa(k) => k.to_pkcs8_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP256(k) => k.to_pkcs8_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP384(k) => k.to_pkcs8_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP521(k) => k.to_pkcs8_pem(LineEnding::LF)?,
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => k.to_pkcs8_pem(LineEnding::LF)?,
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(_) | InnerKey::MlDsa65(_) | InnerKey::MlDsa87(_) => {
+				let der = self.private_key_to_der()?;
+				let pem = der::pem::encode_string("PRIVATE KEY", LineEnding::LF, &der)?;
+				return Ok(pem.into_bytes());
+			}
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => {
+				return Err(k.export_error());
+			}
+		};
+		Ok(pem.as_bytes().to_vec())
+	}
+
+	/// Export the private key as a PKCS #8 PEM encrypted using PBES2 with
+	/// PBKDF2-HMAC-SHA256 and AES-256-CBC.
+	pub fn private_key_to_pem_passphrase(&self, passphrase: &[u8]) -> Result<Vec<u8>, Error> {
+		let mut salt = [0u8; super::PBKDF2_SALT_LEN];
+		let mut iv = [0u8; 16];
+		OsRng.fill_bytes(&mut salt);
+		OsRng.fill_bytes(&mut iv);
+		let params =
+			pbes2::Parameters::pbkdf2_sha256_aes256cbc(super::PBKDF2_ITERATIONS, &salt, &iv)
+				.map_err(pkcs8::Error::from)?;
+		let der = self.private_key_to_der()?;
+		let doc = PrivateKeyInfo::from_der(&der)?.encrypt_with_params(params, passphrase)?;
+		let pem = doc.to_pem(ENCRYPTED_PEM_LABEL, LineEnding::LF)?;
+		Ok(pem.as_bytes().to_vec())
+	}
+
+	pub fn public_key_to_pem(&self) -> Result<Vec<u8>, Error> {
+		let pem = match &self.inner_key {
+			InnerKey::Rsa(k) => k.to_public_key().to_public_key_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP256(k) => k.public_key().to_public_key_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP384(k) => k.public_key().to_public_key_pem(LineEnding::LF)?,
+			InnerKey::EcdsaP521(k) => k.public_key().to_public_key_pem(LineEnding::LF)?,
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => k.verifying_key().to_public_key_pem(LineEnding::LF)?,
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(_) | InnerKey::MlDsa65(_) | InnerKey::MlDsa87(_) => {
+				let der = self.public_key_to_der()?;
+				der::pem::encode_string("PUBLIC KEY", LineEnding::LF, &der)?
+			}
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => {
+				return k.public_key().to_pem();
+			}
+		};
+		Ok(pem.into_bytes())
+	}
+
+	/// Return the identifier of the algorithm used to sign X.509 structures
+	/// using this key pair and the given digest.
+	pub(crate) fn x509_signature_algorithm(
+		&self,
+		digest: &HashFunction,
+	) -> Result<AlgorithmIdentifierOwned, Error> {
+		let (oid, parameters) = match (&self.inner_key, digest) {
+			#[cfg(feature = "pkcs11")]
+			(InnerKey::Pkcs11(k), _) => {
+				return Ok(k.public_key().x509_signature_algorithm(digest));
+			}
+			(InnerKey::Rsa(_), HashFunction::Sha256) => (OID_RSA_SHA256, Some(Any::null())),
+			(InnerKey::Rsa(_), HashFunction::Sha384) => (OID_RSA_SHA384, Some(Any::null())),
+			(InnerKey::Rsa(_), HashFunction::Sha512) => (OID_RSA_SHA512, Some(Any::null())),
+			#[cfg(feature = "ed25519")]
+			(InnerKey::Ed25519(_), _) => (OID_ED25519, None),
+			#[cfg(feature = "ml_dsa")]
+			(InnerKey::MlDsa44(_), _) => (OID_ML_DSA_44, None),
+			#[cfg(feature = "ml_dsa")]
+			(InnerKey::MlDsa65(_), _) => (OID_ML_DSA_65, None),
+			#[cfg(feature = "ml_dsa")]
+			(InnerKey::MlDsa87(_), _) => (OID_ML_DSA_87, None),
+			(_, HashFunction::Sha256) => (OID_ECDSA_SHA256, None),
+			(_, HashFunction::Sha384) => (OID_ECDSA_SHA384, None),
+			(_, HashFunction::Sha512) => (OID_ECDSA_SHA512, None),
+		};
+		Ok(AlgorithmIdentifierOwned { oid, parameters })
+	}
+
+	/// Sign an X.509 structure, the signature being encoded as required by
+	/// the algorithm returned by `x509_signature_algorithm`.
+	pub(crate) fn x509_sign(&self, digest: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		match &self.inner_key {
+			InnerKey::Rsa(_) => self.sign_rsa(digest, data),
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(_) => self.sign_eddsa(data),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(_) | InnerKey::MlDsa65(_) | InnerKey::MlDsa87(_) => self.sign_ml_dsa(data),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => k.x509_sign(digest, data),
+			_ => self.sign_ecdsa(digest, data, true),
+		}
+	}
+
+	fn get_rsa_key(&self) -> Result<&RsaPrivateKey, Error> {
+		match &self.inner_key {
+			InnerKey::Rsa(k) => Ok(k),
+			_ => Err("not a RSA key".into()),
+		}
+	}
+
+	fn sign_rsa(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		let key = self.get_rsa_key()?;
+		let padding = match hash_func {
+			HashFunction::Sha256 => Pkcs1v15Sign::new::<Sha256>(),
+			HashFunction::Sha384 => Pkcs1v15Sign::new::<Sha384>(),
+			HashFunction::Sha512 => Pkcs1v15Sign::new::<Sha512>(),
+		};
+		let signature = key.sign_with_rng(&mut OsRng, padding, &hash_func.hash(data))?;
+		Ok(signature)
+	}
+
+	fn sign_rsa_pss(&self, hash_func: &HashFunction, data: &[u8]) -> Result<Vec<u8>, Error> {
+		let key = self.get_rsa_key()?;
+		// The salt length is equal to the digest length.
+		let padding = match hash_func {
+			HashFunction::Sha256 => Pss::new::<Sha256>(),
+			HashFunction::Sha384 => Pss::new::<Sha384>(),
+			HashFunction::Sha512 => Pss::new::<Sha512>(),
+		};
+		let signature = key.sign_with_rng(&mut OsRng, padding, &hash_func.hash(data))?;
+		Ok(signature)
+	}
+
+	fn sign_ecdsa(
+		&self,
+		hash_func: &HashFunction,
+		data: &[u8],
+		der: bool,
+	) -> Result<Vec<u8>, Error> {
+		use p256::ecdsa::signature::hazmat::{PrehashSigner, RandomizedPrehashSigner};
+
+		let fingerprint = hash_func.hash(data);
+		let signature = match &self.inner_key {
+			InnerKey::EcdsaP256(k) => {
+				let signing_key = p256::ecdsa::SigningKey::from(k);
+				sign_ecdsa!(
+					p256::ecdsa::Signature,
+					signing_key.sign_prehash(&pad_hash(fingerprint, 32)),
+					der
+				)
+			}
+			InnerKey::EcdsaP384(k) => {
+				let signing_key = p384::ecdsa::SigningKey::from(k);
+				sign_ecdsa!(
+					p384::ecdsa::Signature,
+					signing_key.sign_prehash(&pad_hash(fingerprint, 48)),
+					der
+				)
+			}
+			InnerKey::EcdsaP521(k) => {
+				let signing_key = p521::ecdsa::SigningKey::from_bytes(&k.to_bytes())?;
+				sign_ecdsa!(
+					p521::ecdsa::Signature,
+					signing_key.sign_prehash_with_rng(&mut OsRng, &pad_hash(fingerprint, 66)),
+					der
+				)
+			}
+			_ => {
+				return Err("not an ecdsa key".into());
+			}
+		};
+		Ok(signature)
+	}
+
+	#[cfg(any(feature = "ed25519", feature = "ed448"))]
+	fn sign_eddsa(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+		use ed25519_dalek::Signer as _;
+
+		match &self.inner_key {
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => Ok(k.sign(data).to_bytes().to_vec()),
+			_ => Err(format!("{}: unsupported EdDSA key", self.key_type).into()),
+		}
+	}
+
+	#[cfg(feature = "ml_dsa")]
+	fn sign_ml_dsa(&self, data: &[u8]) -> Result<Vec<u8>, Error> {
+		use ml_dsa::signature::Signer as _;
+
+		let signature = match &self.inner_key {
+			InnerKey::MlDsa44(k) => k.try_sign(data).map(|s| s.encode().to_vec()),
+			InnerKey::MlDsa65(k) => k.try_sign(data).map(|s| s.encode().to_vec()),
+			InnerKey::MlDsa87(k) => k.try_sign(data).map(|s| s.encode().to_vec()),
+			_ => {
+				return Err(format!("{}: not a ML-DSA key", self.key_type).into());
+			}
+		};
+		signature.map_err(|e| Error::from(e.to_string()))
+	}
+
+	fn get_jwk_public_key(&self, thumbprint: bool) -> Result<Value, Error> {
+		match &self.inner_key {
+			InnerKey::Rsa(k) => Ok(get_rsa_jwk(k, thumbprint)),
+			InnerKey::EcdsaP256(k) => {
+				let point = k.public_key().to_encoded_point(false);
+				get_ecdsa_jwk("P-256", "ES256", point.x(), point.y(), thumbprint)
+			}
+			InnerKey::EcdsaP384(k) => {
+				let point = k.public_key().to_encoded_point(false);
+				get_ecdsa_jwk("P-384", "ES384", point.x(), point.y(), thumbprint)
+			}
+			InnerKey::EcdsaP521(k) => {
+				let point = k.public_key().to_encoded_point(false);
+				get_ecdsa_jwk("P-521", "ES512", point.x(), point.y(), thumbprint)
+			}
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => Ok(get_eddsa_jwk(
+				"Ed25519",
+				k.verifying_key().as_bytes(),
+				thumbprint,
+			)),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(k) => Ok(get_akp_jwk(
+				"ML-DSA-44",
+				&k.verifying_key().encode(),
+				thumbprint,
+			)),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa65(k) => Ok(get_akp_jwk(
+				"ML-DSA-65",
+				&k.verifying_key().encode(),
+				thumbprint,
+			)),
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa87(k) => Ok(get_akp_jwk(
+				"ML-DSA-87",
+				&k.verifying_key().encode(),
+				thumbprint,
+			)),
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => k.public_key().jwk(thumbprint),
+		}
+	}
+}
+
+impl Signer for KeyPair {
+	fn key_type(&self) -> KeyType {
+		self.key_type
+	}
+
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error> {
+		let doc = match &self.inner_key {
+			InnerKey::Rsa(k) => k.to_public_key().to_public_key_der()?,
+			InnerKey::EcdsaP256(k) => k.public_key().to_public_key_der()?,
+			InnerKey::EcdsaP384(k) => k.public_key().to_public_key_der()?,
+			InnerKey::EcdsaP521(k) => k.public_key().to_public_key_der()?,
+			#[cfg(feature = "ed25519")]
+			InnerKey::Ed25519(k) => k.verifying_key().to_public_key_der()?,
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa44(k) => {
+				return ml_dsa_der!(ml_dsa::pkcs8::EncodePublicKey::to_public_key_der(
+					&k.verifying_key()
+				))
+			}
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa65(k) => {
+				return ml_dsa_der!(ml_dsa::pkcs8::EncodePublicKey::to_public_key_der(
+					&k.verifying_key()
+				))
+			}
+			#[cfg(feature = "ml_dsa")]
+			InnerKey::MlDsa87(k) => {
+				return ml_dsa_der!(ml_dsa::pkcs8::EncodePublicKey::to_public_key_der(
+					&k.verifying_key()
+				))
+			}
+			#[cfg(feature = "pkcs11")]
+			InnerKey::Pkcs11(k) => {
+				return k.public_key().to_der();
+			}
+		};
+		Ok(doc.into_vec())
+	}
+
+	fn sign(&self, alg: &JwsSignatureAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error> {
+		self.key_type.check_alg_compatibility(alg)?;
+		#[cfg(feature = "pkcs11")]
+		if let InnerKey::Pkcs11(k) = &self.inner_key {
+			return k.sign(alg, data);
+		}
+		match alg {
+			JwsSignatureAlgorithm::Hs256
+			| JwsSignatureAlgorithm::Hs384
+			| JwsSignatureAlgorithm::Hs512 => Err(format!(
+				"{} key pair cannot be used for the {alg} signature algorithm",
+				self.key_type
+			)
+			.into()),
+			JwsSignatureAlgorithm::Rs256 => self.sign_rsa(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Rs384 => self.sign_rsa(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Rs512 => self.sign_rsa(&HashFunction::Sha512, data),
+			JwsSignatureAlgorithm::Ps256 => self.sign_rsa_pss(&HashFunction::Sha256, data),
+			JwsSignatureAlgorithm::Ps384 => self.sign_rsa_pss(&HashFunction::Sha384, data),
+			JwsSignatureAlgorithm::Ps512 => self.sign_rsa_pss(&HashFunction::Sha512, data),
+			JwsSignatureAlgorithm::Es256 => self.sign_ecdsa(&HashFunction::Sha256, data, false),
+			JwsSignatureAlgorithm::Es384 => self.sign_ecdsa(&HashFunction::Sha384, data, false),
+			JwsSignatureAlgorithm::Es512 => self.sign_ecdsa(&HashFunction::Sha512, data, false),
+			#[cfg(feature = "ed25519")]
+			JwsSignatureAlgorithm::Ed25519 => self.sign_eddsa(data),
+			#[cfg(feature = "ed448")]
+			JwsSignatureAlgorithm::Ed448 => self.sign_eddsa(data),
+			#[cfg(feature = "ml_dsa")]
+			JwsSignatureAlgorithm::MlDsa44
+			| JwsSignatureAlgorithm::MlDsa65
+			| JwsSignatureAlgorithm::MlDsa87 => self.sign_ml_dsa(data),
+		}
+	}
+
+	fn jwk_public_key(&self) -> Result<Value, Error> {
+		self.get_jwk_public_key(false)
+	}
+
+	fn jwk_public_key_thumbprint(&self) -> Result<Value, Error> {
+		self.get_jwk_public_key(true)
+	}
+}
+
+fn get_rsa_jwk(key: &RsaPrivateKey, thumbprint: bool) -> Value {
+	let e = b64_encode(&key.e().to_bytes_be());
+	let n = b64_encode(&key.n().to_bytes_be());
+	if thumbprint {
+		json!({
+			"kty": "RSA",
+			"e": e,
+			"n": n,
+		})
+	} else {
+		json!({
+			"alg": "RS256",
+			"kty": "RSA",
+			"use": "sig",
+			"e": e,
+			"n": n,
+		})
+	}
+}
+
+fn get_ecdsa_jwk<T: AsRef<[u8]>>(
+	crv: &str,
+	alg: &str,
+	x: Option<&T>,
+	y: Option<&T>,
+	thumbprint: bool,
+) -> Result<Value, Error> {
+	let (x, y) = match (x, y) {
+		(Some(x), Some(y)) => (b64_encode(x), b64_encode(y)),
+		_ => {
+			return Err("invalid ECDSA public key".into());
+		}
+	};
+	let jwk = if thumbprint {
+		json!({
+			"crv": crv,
+			"kty": "EC",
+			"x": x,
+			"y": y,
+		})
+	} else {
+		json!({
+			"alg": alg,
+			"crv": crv,
+			"kty": "EC",
+			"use": "sig",
+			"x": x,
+			"y": y,
+		})
+	};
+	Ok(jwk)
+}
+
+#[cfg(feature = "ed25519")]
+fn get_eddsa_jwk(crv: &str, x: &[u8], thumbprint: bool) -> Value {
+	let x = b64_encode(x);
+	if thumbprint {
+		json!({
+			"crv": crv,
+			"kty": "OKP",
+			"x": &x,
+		})
+	} else {
+		json!({
+			"alg": "EdDSA",
+			"crv": crv,
+			"kty": "OKP",
+			"use": "sig",
+			"x": &x,
+		})
+	}
+}
+
+// Algorithm Key Pair, as defined by the JOSE and COSE ML-DSA draft
+// (draft-ietf-cose-dilithium).
+#[cfg(feature = "ml_dsa")]
+fn get_akp_jwk(alg: &str, public_key: &[u8], thumbprint: bool) -> Value {
+	let public_key = b64_encode(public_key);
+	if thumbprint {
+		json!({
+			"alg": alg,
+			"kty": "AKP",
+			"pub": &public_key,
+		})
+	} else {
+		json!({
+			"alg": alg,
+			"kty": "AKP",
+			"pub": &public_key,
+			"use": "sig",
+		})
+	}
+}
+
+/// Return the type of a DER-encoded public key (SubjectPublicKeyInfo).
+pub(crate) fn get_public_key_type(der_data: &[u8]) -> Result<KeyType, Error> {
+	let spki = SubjectPublicKeyInfoRef::from_der(der_data)?;
+	match spki.algorithm.oid {
+		RSA_ALGORITHM_OID => {
+			let key = RsaPublicKey::from_public_key_der(der_data)?;
+			KeyType::from_rsa_size(key.n().bits() as u32)
+		}
+		EC_ALGORITHM_OID => match spki.algorithm.parameters_oid()? {
+			OID_P256 => Ok(KeyType::EcdsaP256),
+			OID_P384 => Ok(KeyType::EcdsaP384),
+			OID_P521 => Ok(KeyType::EcdsaP521),
+			_ => Err("unsupported EC key".into()),
+		},
+		#[cfg(feature = "ed25519")]
+		OID_ED25519 => Ok(KeyType::Ed25519),
+		#[cfg(feature = "ml_dsa")]
+		OID_ML_DSA_44 => Ok(KeyType::MlDsa44),
+		#[cfg(feature = "ml_dsa")]
+		OID_ML_DSA_65 => Ok(KeyType::MlDsa65),
+		#[cfg(feature = "ml_dsa")]
+		OID_ML_DSA_87 => Ok(KeyType::MlDsa87),
+		_ => Err("unsupported key type".into()),
+	}
+}
+
+impl KeyGenerator for KeyPair {
+	fn generate(key_type: KeyType) -> Result<Self, Error> {
+		gen_keypair(key_type)
+	}
+}
+
+pub fn gen_keypair(key_type: KeyType) -> Result<KeyPair, Error> {
+	let inner_key = match key_type {
+		KeyType::Rsa2048 => RsaPrivateKey::new(&mut OsRng, 2048).map(InnerKey::Rsa),
+		KeyType::Rsa3072 => RsaPrivateKey::new(&mut OsRng, 3072).map(InnerKey::Rsa),
+		KeyType::Rsa4096 => RsaPrivateKey::new(&mut OsRng, 4096).map(InnerKey::Rsa),
+		KeyType::Rsa(nb_bits) => {
+			RsaPrivateKey::new(&mut OsRng, nb_bits as usize).map(InnerKey::Rsa)
+		}
+		KeyType::EcdsaP256 => Ok(InnerKey::EcdsaP256(p256::SecretKey::random(&mut OsRng))),
+		KeyType::EcdsaP384 => Ok(InnerKey::EcdsaP384(p384::SecretKey::random(&mut OsRng))),
+		KeyType::EcdsaP521 => Ok(InnerKey::EcdsaP521(p521::SecretKey::random(&mut OsRng))),
+		#[cfg(feature = "ed25519")]
+		KeyType::Ed25519 => Ok(InnerKey::Ed25519(ed25519_dalek::SigningKey::generate(
+			&mut OsRng,
+		))),
+		#[cfg(feature = "ed448")]
+		KeyType::Ed448 => {
+			return Err(format!("{key_type}: unsupported key type").into());
+		}
+		#[cfg(feature = "ml_dsa")]
+		KeyType::MlDsa44 => Ok(InnerKey::MlDsa44(Box::new(ml_dsa::SigningKey::from_seed(
+			&gen_ml_dsa_seed(),
+		)))),
+		#[cfg(feature = "ml_dsa")]
+		KeyType::MlDsa65 => Ok(InnerKey::MlDsa65(Box::new(ml_dsa::SigningKey::from_seed(
+			&gen_ml_dsa_seed(),
+		)))),
+		#[cfg(feature = "ml_dsa")]
+		KeyType::MlDsa87 => Ok(InnerKey::MlDsa87(Box::new(ml_dsa::SigningKey::from_seed(
+			&gen_ml_dsa_seed(),
+		)))),
+	}
+	.map_err(|_| Error::from(format!("unable to generate a {key_type} key pair")))?;
+	Ok(KeyPair {
+		key_type,
+		inner_key,
+	})
+}
@@ -0,0 +1 @@
+pub type SubjectAttribute = super::BaseSubjectAttribute;
@@ -0,0 +1,7 @@
+pub fn get_lib_name() -> String {
+	env!("ACMED_TLS_LIB_NAME").to_string()
+}
+
+pub fn get_lib_version() -> String {
+	env!("ACMED_TLS_LIB_VERSION").to_string()
+}
@@ -0,0 +1,117 @@
+use crate::crypto::{
+	ExtendedKeyUsage, HashFunction, JwsSignatureAlgorithm, KeyType, KeyUsage, SubjectAttribute,
+};
+use crate::error::Error;
+use serde_json::value::Value;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+/// A private key able to sign data and to export its public part.
+pub trait Signer {
+	fn key_type(&self) -> KeyType;
+
+	fn sign(&self, alg: &JwsSignatureAlgorithm, data: &[u8]) -> Result<Vec<u8>, Error>;
+
+	/// Return the public key as a JSON Web Key (RFC 7517).
+	fn jwk_public_key(&self) -> Result<Value, Error>;
+
+	/// Return the public key as a JSON Web Key containing only the members
+	/// required to compute its thumbprint (RFC 7638).
+	fn jwk_public_key_thumbprint(&self) -> Result<Value, Error>;
+
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error>;
+}
+
+/// A signer which can be generated from scratch.
+pub trait KeyGenerator: Signer + Sized {
+	fn generate(key_type: KeyType) -> Result<Self, Error>;
+}
+
+/// A certificate signing request (RFC 2986) signed by the key of type `S`.
+pub trait CsrBuilder<S: Signer>: Sized {
+	fn build(
+		key_pair: &S,
+		digest: HashFunction,
+		domains: &[String],
+		ips: &[String],
+		subject_attributes: &HashMap<SubjectAttribute, String>,
+	) -> Result<Self, Error>;
+
+	fn to_der_base64(&self) -> Result<String, Error>;
+
+	fn to_pem(&self) -> Result<String, Error>;
+}
+
+/// Read access to the content of a certificate signing request.
+pub trait CsrInspector {
+	/// Return the type of the key the request is signed with.
+	fn key_type(&self) -> Result<KeyType, Error>;
+
+	/// Return the DNS names and IP addresses of the subject alternative
+	/// name extension.
+	fn subject_alt_names(&self) -> Result<HashSet<String>, Error>;
+}
+
+/// Read access to the content of an X.509 certificate.
+pub trait CertificateInspector {
+	fn to_der(&self) -> Result<Vec<u8>, Error>;
+
+	/// Return the SHA-256 digest of the DER-encoded certificate.
+	fn fingerprint(&self) -> Result<Vec<u8>, Error> {
+		Ok(HashFunction::Sha256.hash(&self.to_der()?))
+	}
+
+	/// Return the serial number as an upper-case hexadecimal string.
+	fn serial_number(&self) -> Result<String, Error>;
+
+	/// Return the issuer's distinguished name as a string (RFC 4514).
+	fn issuer(&self) -> Result<String, Error>;
+
+	/// Return the subject's distinguished name as a string (RFC 4514).
+	fn subject(&self) -> Result<String, Error>;
+
+	fn not_before(&self) -> Result<SystemTime, Error>;
+
+	fn not_after(&self) -> Result<SystemTime, Error>;
+
+	fn expires_in(&self) -> Result<Duration, Error>;
+
+	/// Return the period of time between the notBefore and notAfter dates.
+	fn lifetime(&self) -> Result<Duration, Error>;
+
+	fn subject_alt_names(&self) -> HashSet<String>;
+
+	fn authority_key_identifier(&self) -> Result<Option<Vec<u8>>, Error>;
+
+	fn subject_key_identifier(&self) -> Result<Option<Vec<u8>>, Error>;
+
+	/// Return the DER-encoded public key (SubjectPublicKeyInfo).
+	fn public_key_to_der(&self) -> Result<Vec<u8>, Error>;
+
+	fn key_type(&self) -> Result<KeyType, Error>;
+
+	/// Check whether or not the certificate has been issued for the public
+	/// part of the given key.
+	fn matches_key<S: Signer>(&self, key: &S) -> Result<bool, Error>
+	where
+		Self: Sized,
+	{
+		Ok(self.public_key_to_der()? == key.public_key_to_der()?)
+	}
+
+	/// Return the key usages, or `None` if the certificate does not have a
+	/// key usage extension.
+	fn key_usage(&self) -> Result<Option<HashSet<KeyUsage>>, Error>;
+
+	/// Return the extended key usages, or `None` if the certificate does not
+	/// have an extended key usage extension.
+	fn extended_key_usage(&self) -> Result<Option<Vec<ExtendedKeyUsage>>, Error>;
+
+	/// Return the OCSP responders' URLs of the authority information access
+	/// extension.
+	fn ocsp_urls(&self) -> Result<Vec<String>, Error>;
+
+	/// Return the URLs of the issuer's certificate from the authority
+	/// information access extension.
+	fn ca_issuers_urls(&self) -> Result<Vec<String>, Error>;
+}
@@ -0,0 +1,84 @@
+//! Decoding of the X.509 extensions, shared by the cryptographic backends.
+
+use super::{ExtendedKeyUsage, KeyUsage};
+use crate::error::Error;
+use der::asn1::BitString;
+use der::oid::AssociatedOid;
+use der::Decode;
+use std::collections::HashSet;
+use std::net::IpAddr;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::{self, SubjectAltName};
+use x509_cert::request::{CertReq, ExtensionReq};
+use x509_cert::TbsCertificate;
+
+/// Return the DNS names and IP addresses of a list of general names.
+pub(crate) fn get_general_names(names: &[GeneralName]) -> HashSet<String> {
+	names
+		.iter()
+		.filter_map(|v| match v {
+			GeneralName::DnsName(d) => Some(d.to_string()),
+			GeneralName::IpAddress(i) => match i.as_bytes().len() {
+				4 => {
+					let mut ipv4 = [0; 4];
+					ipv4.copy_from_slice(i.as_bytes());
+					Some(IpAddr::from(ipv4).to_string())
+				}
+				16 => {
+					let mut ipv6 = [0; 16];
+					ipv6.copy_from_slice(i.as_bytes());
+					Some(IpAddr::from(ipv6).to_string())
+				}
+				_ => None,
+			},
+			_ => None,
+		})
+		.collect()
+}
+
+/// Return the DNS names and IP addresses of the subject alternative name
+/// extension requested in a certificate signing request.
+pub(crate) fn get_csr_subject_alt_names(csr: &CertReq) -> Result<HashSet<String>, Error> {
+	for attr in csr.info.attributes.iter() {
+		if attr.oid != ExtensionReq::OID {
+			continue;
+		}
+		for value in attr.values.iter() {
+			let ext_req: ExtensionReq = value.decode_as()?;
+			for ext in ext_req.0.iter() {
+				if ext.extn_id == SubjectAltName::OID {
+					let san = SubjectAltName::from_der(ext.extn_value.as_bytes())?;
+					return Ok(get_general_names(&san.0));
+				}
+			}
+		}
+	}
+	Ok(HashSet::new())
+}
+
+pub(crate) fn get_key_usage(tbs: &TbsCertificate) -> Result<Option<HashSet<KeyUsage>>, Error> {
+	let extension = tbs
+		.extensions
+		.iter()
+		.flatten()
+		.find(|ext| ext.extn_id == pkix::KeyUsage::OID);
+	match extension {
+		Some(ext) => {
+			let bits = BitString::from_der(ext.extn_value.as_bytes())?;
+			Ok(Some(KeyUsage::from_bits(bits.raw_bytes())))
+		}
+		None => Ok(None),
+	}
+}
+
+pub(crate) fn get_extended_key_usage(
+	tbs: &TbsCertificate,
+) -> Result<Option<Vec<ExtendedKeyUsage>>, Error> {
+	let eku = tbs.get::<pkix::ExtendedKeyUsage>()?;
+	Ok(eku.map(|(_, eku)| {
+		eku.0
+			.iter()
+			.map(|oid| ExtendedKeyUsage::from_oid(&oid.to_string()))
+			.collect()
+	}))
+}
@@ -0,0 +1,123 @@
+use super::SubjectAttribute;
+use crate::error::Error;
+use der::asn1::{
+	BitString, Ia5String, Ia5StringRef, ObjectIdentifier, OctetString, PrintableStringRef,
+	SetOfVec, Utf8StringRef,
+};
+use der::oid::AssociatedOid;
+use der::{Any, Encode};
+use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
+use std::net::IpAddr;
+use std::str::FromStr;
+use x509_cert::attr::AttributeTypeAndValue;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::pkix::SubjectAltName;
+use x509_cert::ext::Extension;
+use x509_cert::name::{Name, RdnSequence, RelativeDistinguishedName};
+use x509_cert::request::{CertReq, CertReqInfo, ExtensionReq};
+use x509_cert::spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+
+impl SubjectAttribute {
+	pub fn get_oid(&self) -> ObjectIdentifier {
+		let oid = match self {
+			SubjectAttribute::CountryName => "2.5.4.6",
+			SubjectAttribute::GenerationQualifier => "2.5.4.44",
+			SubjectAttribute::GivenName => "2.5.4.42",
+			SubjectAttribute::Initials => "2.5.4.43",
+			SubjectAttribute::LocalityName => "2.5.4.7",
+			SubjectAttribute::Name => "2.5.4.41",
+			SubjectAttribute::OrganizationName => "2.5.4.10",
+			SubjectAttribute::OrganizationalUnitName => "2.5.4.11",
+			SubjectAttribute::Pkcs9EmailAddress => "1.2.840.113549.1.9.1",
+			SubjectAttribute::PostalAddress => "2.5.4.16",
+			SubjectAttribute::PostalCode => "2.5.4.17",
+			SubjectAttribute::StateOrProvinceName => "2.5.4.8",
+			SubjectAttribute::Street => "2.5.4.9",
+			SubjectAttribute::Surname => "2.5.4.4",
+			SubjectAttribute::Title => "2.5.4.12",
+		};
+		ObjectIdentifier::new_unwrap(oid)
+	}
+
+	pub(crate) fn encode_value(&self, value: &str) -> Result<Any, Error> {
+		let value = match self {
+			SubjectAttribute::CountryName => Any::encode_from(&PrintableStringRef::new(value)?)?,
+			SubjectAttribute::Pkcs9EmailAddress => Any::encode_from(&Ia5StringRef::new(value)?)?,
+			_ => Any::encode_from(&Utf8StringRef::new(value)?)?,
+		};
+		Ok(value)
+	}
+}
+
+fn get_name(attributes: &[(SubjectAttribute, &str)]) -> Result<Name, Error> {
+	let mut rdns = Vec::with_capacity(attributes.len());
+	for (sattr, val) in attributes.iter() {
+		let atv = AttributeTypeAndValue {
+			oid: sattr.get_oid(),
+			value: sattr.encode_value(val)?,
+		};
+		rdns.push(RelativeDistinguishedName(SetOfVec::try_from(vec![atv])?));
+	}
+	Ok(RdnSequence(rdns))
+}
+
+pub(crate) fn get_extension<T: Encode + AssociatedOid>(
+	ext: &T,
+	critical: bool,
+) -> Result<Extension, Error> {
+	Ok(Extension {
+		extn_id: T::OID,
+		critical,
+		extn_value: OctetString::new(ext.to_der()?)?,
+	})
+}
+
+pub(crate) fn get_san_extension(domains: &[String], ips: &[String]) -> Result<Extension, Error> {
+	let mut names = Vec::with_capacity(domains.len() + ips.len());
+	for dns in domains.iter() {
+		names.push(GeneralName::DnsName(Ia5String::new(dns)?));
+	}
+	for ip in ips.iter() {
+		let ip = IpAddr::from_str(ip).map_err(|e| Error::from(e.to_string()).prefix(ip))?;
+		let ip = match ip {
+			IpAddr::V4(ip) => ip.octets().to_vec(),
+			IpAddr::V6(ip) => ip.octets().to_vec(),
+		};
+		names.push(GeneralName::IpAddress(OctetString::new(ip)?));
+	}
+	get_extension(&SubjectAltName(names), false)
+}
+
+/// Build a certificate signing request. The DER-encoded request
+/// information is signed using the `sign` function, which must produce a
+/// signature matching the given algorithm.
+pub(crate) fn build_cert_req<F>(
+	public_key: SubjectPublicKeyInfoOwned,
+	algorithm: AlgorithmIdentifierOwned,
+	domains: &[String],
+	ips: &[String],
+	subject_attributes: &HashMap<SubjectAttribute, String>,
+	sign: F,
+) -> Result<CertReq, Error>
+where
+	F: FnOnce(&[u8]) -> Result<Vec<u8>, Error>,
+{
+	let attrs: Vec<(SubjectAttribute, &str)> = subject_attributes
+		.iter()
+		.map(|(k, v)| (*k, v.as_str()))
+		.collect();
+	let ext_req = ExtensionReq(vec![get_san_extension(domains, ips)?]);
+	let info = CertReqInfo {
+		version: x509_cert::request::Version::V1,
+		subject: get_name(&attrs)?,
+		public_key,
+		attributes: SetOfVec::try_from(vec![ext_req.try_into()?])?,
+	};
+	let signature = sign(&info.to_der()?)?;
+	Ok(CertReq {
+		info,
+		algorithm,
+		signature: BitString::from_bytes(&signature)?,
+	})
+}
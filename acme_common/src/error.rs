@@ -125,6 +125,76 @@ impl From<openssl::error::ErrorStack> for Error {
 	}
 }
 
+#[cfg(any(feature = "crypto_openssl", feature = "crypto_rustcrypto"))]
+impl From<der::Error> for Error {
+	fn from(error: der::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(any(feature = "crypto_openssl", feature = "crypto_rustcrypto"))]
+impl From<der::pem::Error> for Error {
+	fn from(error: der::pem::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(any(feature = "crypto_openssl", feature = "crypto_rustcrypto"))]
+impl From<pkcs8::Error> for Error {
+	fn from(error: pkcs8::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<pkcs8::spki::Error> for Error {
+	fn from(error: pkcs8::spki::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<rsa::Error> for Error {
+	fn from(error: rsa::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<rsa::pkcs1::Error> for Error {
+	fn from(error: rsa::pkcs1::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<sec1::Error> for Error {
+	fn from(error: sec1::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<p256::ecdsa::Error> for Error {
+	fn from(error: p256::ecdsa::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+impl From<p256::elliptic_curve::Error> for Error {
+	fn from(error: p256::elliptic_curve::Error) -> Self {
+		format!("{error}").into()
+	}
+}
+
+#[cfg(feature = "pkcs11")]
+impl From<cryptoki::error::Error> for Error {
+	fn from(error: cryptoki::error::Error) -> Self {
+		format!("PKCS #11 error: {error}").into()
+	}
+}
+
 #[cfg(unix)]
 impl From<nix::Error> for Error {
 	fn from(error: nix::Error) -> Self {
@@ -1,5 +1,6 @@
 use base64::Engine;
 use daemonize::Daemonize;
+use idna::uts46::{AsciiDenyList, DnsLength, Hyphens, Uts46};
 use std::fs::File;
 use std::io::prelude::*;
 use std::{fs, process};
@@ -22,21 +23,54 @@ macro_rules! exit_match {
 	};
 }
 
-pub fn to_idna(domain_name: &str) -> Result<String, error::Error> {
-	let mut idna_parts = vec![];
-	let parts: Vec<&str> = domain_name.split('.').collect();
-	for name in parts.iter() {
-		let raw_name = name.to_lowercase();
-		let idna_name = if name.is_ascii() {
-			raw_name
-		} else {
-			let idna_name = punycode::encode(&raw_name)
-				.map_err(|_| error::Error::from("IDNA encoding failed."))?;
-			format!("xn--{idna_name}")
-		};
-		idna_parts.push(idna_name);
+const WILDCARD_PREFIX: &str = "*.";
+
+// Deny list of the STD3 ASCII rules minus the underscore, which is used in
+// DNS records such as the ACME challenge ones.
+const DNS_ASCII_DENY_LIST: AsciiDenyList =
+	AsciiDenyList::new(true, "!\"#$%&'()*+,/:;<=>?@[\\]^`{|}~");
+
+fn split_wildcard(domain_name: &str) -> (&str, &str) {
+	match domain_name.strip_prefix(WILDCARD_PREFIX) {
+		Some(name) => (WILDCARD_PREFIX, name),
+		None => ("", domain_name),
 	}
-	Ok(idna_parts.join("."))
+}
+
+fn process_idna(
+	domain_name: &str,
+	deny_list: AsciiDenyList,
+	hyphens: Hyphens,
+) -> Result<String, error::Error> {
+	// Fully qualified domain names are accepted, the root label is removed.
+	let name = domain_name.strip_suffix('.').unwrap_or(domain_name);
+	let (wildcard, name) = split_wildcard(name);
+	let name = Uts46::new()
+		.to_ascii(name.as_bytes(), deny_list, hyphens, DnsLength::Verify)
+		.map_err(|_| error::Error::from("invalid domain name").prefix(domain_name))?;
+	Ok(format!("{wildcard}{name}"))
+}
+
+/// Convert a host name to its ASCII form using the UTS #46 processing
+/// (IDNA2008 with the STD3 ASCII rules). Invalid names are rejected. A
+/// leading wildcard label is allowed and a trailing dot is removed.
+pub fn to_idna(domain_name: &str) -> Result<String, error::Error> {
+	process_idna(domain_name, AsciiDenyList::STD3, Hyphens::Check)
+}
+
+/// Same as `to_idna`, but for DNS names which may not be host names: the
+/// labels are allowed to contain underscores and hyphens at any position.
+pub fn dns_name_to_idna(domain_name: &str) -> Result<String, error::Error> {
+	process_idna(domain_name, DNS_ASCII_DENY_LIST, Hyphens::Allow)
+}
+
+/// Convert a domain name to its Unicode form. The result is meant to be
+/// displayed only and must not be used in any protocol.
+pub fn from_idna(domain_name: &str) -> Result<String, error::Error> {
+	let (wildcard, name) = split_wildcard(domain_name);
+	let (name, res) = Uts46::new().to_unicode(name.as_bytes(), DNS_ASCII_DENY_LIST, Hyphens::Allow);
+	res.map_err(|_| error::Error::from("invalid domain name").prefix(domain_name))?;
+	Ok(format!("{wildcard}{name}"))
 }
 
 pub fn b64_encode<T: ?Sized + AsRef<[u8]>>(input: &T) -> String {
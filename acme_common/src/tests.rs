@@ -1,5 +1,9 @@
 mod certificate;
 mod crypto_keys;
+mod csr;
+mod encrypted_data;
 mod hash;
 mod idna;
 mod jws_signature_algorithm;
+#[cfg(feature = "pkcs11")]
+mod pkcs11;
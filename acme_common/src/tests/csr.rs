@@ -0,0 +1,70 @@
+use crate::crypto::{gen_keypair, Csr, CsrBuilder, CsrInspector, HashFunction, KeyType};
+use std::collections::{HashMap, HashSet};
+use std::iter::FromIterator;
+
+const CSR_P384_SAN_PEM: &str = r#"-----BEGIN CERTIFICATE REQUEST-----
+MIIBaDCB7gIBADAYMRYwFAYDVQQDDA1sb2NhbC53aGF0LnRmMHYwEAYHKoZIzj0C
+AQYFK4EEACIDYgAEjGFtxBvr0XzEU/Z3xUgqv4ayrd7QtsvIGqhMdtsNk/K7CN9I
+oojrOwkY9N7Rz7twI1bsePYu5SaCyCw/tDv4cDiTEXvXWe603M5pKACpNZAxiG5V
+0YpX0Sqte5aHeE3voFcwVQYJKoZIhvcNAQkOMUgwRjBEBgNVHREBAf8EOjA4gg1s
+b2NhbC53aGF0LnRmgg8xLmxvY2FsLndoYXQudGaHBH8AAAGHEAAAAAAAAAAAAAAA
+AAAAAAEwCgYIKoZIzj0EAwIDaQAwZgIxAJqBVrnE/fayrnDhcAGV4fPb/sklz8wX
+sX6tA7QkDWoeNgqcC8hcneR7s+nbQZAw6QIxAMfVjNMrNe9XkrozXjD921Jy8RZS
+MnUA/H7vH0XairlwsmjKONX9u1bImFW9SbOIQw==
+-----END CERTIFICATE REQUEST-----"#;
+
+const CSR_P384_NO_SAN_PEM: &str = r#"-----BEGIN CERTIFICATE REQUEST-----
+MIIBEDCBlwIBADAYMRYwFAYDVQQDDA1sb2NhbC53aGF0LnRmMHYwEAYHKoZIzj0C
+AQYFK4EEACIDYgAEjGFtxBvr0XzEU/Z3xUgqv4ayrd7QtsvIGqhMdtsNk/K7CN9I
+oojrOwkY9N7Rz7twI1bsePYu5SaCyCw/tDv4cDiTEXvXWe603M5pKACpNZAxiG5V
+0YpX0Sqte5aHeE3voAAwCgYIKoZIzj0EAwIDaAAwZQIxAJSUSPZCSkaSfeBsMUQB
+MyKyfBhao38MryPo2xtIuhDWWU/gY2S0hEyshQt6N8DHbgIwTtqd4MEiJWmrsBpf
+308388hvgPafRZXB+wbYnwJzHvVEeAafH4vAHGL+HSPCyhPZ
+-----END CERTIFICATE REQUEST-----"#;
+
+#[test]
+fn test_csr_san() {
+	let san = ["local.what.tf", "1.local.what.tf", "127.0.0.1", "::1"];
+	let san = HashSet::from_iter(san.iter().map(|v| v.to_string()));
+	let csr = Csr::from_pem(CSR_P384_SAN_PEM.as_bytes()).unwrap();
+	assert_eq!(csr.subject_alt_names().unwrap(), san);
+	assert_eq!(csr.key_type().unwrap(), KeyType::EcdsaP384);
+}
+
+#[test]
+fn test_csr_no_san() {
+	let csr = Csr::from_pem(CSR_P384_NO_SAN_PEM.as_bytes()).unwrap();
+	assert!(csr.subject_alt_names().unwrap().is_empty());
+	assert_eq!(csr.key_type().unwrap(), KeyType::EcdsaP384);
+}
+
+#[test]
+fn test_csr_invalid() {
+	assert!(Csr::from_pem(b"").is_err());
+	assert!(Csr::from_pem(&CSR_P384_SAN_PEM.as_bytes()[..200]).is_err());
+}
+
+#[test]
+fn test_csr_from_builder() {
+	let domains = vec!["example.org".to_string(), "www.example.org".to_string()];
+	let ips = vec!["192.0.2.1".to_string()];
+	for key_type in [KeyType::Rsa2048, KeyType::EcdsaP256].iter() {
+		let key_pair = gen_keypair(*key_type).unwrap();
+		let csr = Csr::build(
+			&key_pair,
+			HashFunction::Sha256,
+			&domains,
+			&ips,
+			&HashMap::new(),
+		)
+		.unwrap();
+		let csr = Csr::from_pem(csr.to_pem().unwrap().as_bytes()).unwrap();
+		let san = domains
+			.iter()
+			.chain(ips.iter())
+			.cloned()
+			.collect::<HashSet<String>>();
+		assert_eq!(csr.subject_alt_names().unwrap(), san);
+		assert_eq!(csr.key_type().unwrap(), *key_type);
+	}
+}
@@ -0,0 +1,52 @@
+use crate::crypto::{decrypt_data, encrypt_data, is_encrypted_data, PBKDF2_ITERATIONS};
+
+#[test]
+fn test_encrypt_decrypt_data() {
+	let passphrase = b"correct horse battery staple";
+	let data = b"Lorem ipsum dolor sit amet.";
+	let encrypted = encrypt_data(passphrase, data).unwrap();
+	assert!(is_encrypted_data(&encrypted));
+	assert!(!is_encrypted_data(data));
+	assert_ne!(&encrypted[encrypted.len() - data.len()..], data);
+	let decrypted = decrypt_data(passphrase, &encrypted).unwrap();
+	assert_eq!(decrypted, data);
+
+	let encrypted_bis = encrypt_data(passphrase, data).unwrap();
+	assert_ne!(encrypted, encrypted_bis);
+}
+
+#[test]
+fn test_decrypt_data_invalid() {
+	let passphrase = b"correct horse battery staple";
+	let data = b"Lorem ipsum dolor sit amet.";
+	let encrypted = encrypt_data(passphrase, data).unwrap();
+	assert!(decrypt_data(b"invalid", &encrypted).is_err());
+	assert!(decrypt_data(passphrase, data).is_err());
+	assert!(decrypt_data(passphrase, &encrypted[..20]).is_err());
+
+	let mut tampered = encrypted.clone();
+	let last = tampered.len() - 1;
+	tampered[last] ^= 0x01;
+	assert!(decrypt_data(passphrase, &tampered).is_err());
+
+	// The header is authenticated.
+	let mut tampered = encrypted;
+	tampered[12] ^= 0x01;
+	assert!(decrypt_data(passphrase, &tampered).is_err());
+}
+
+#[test]
+fn test_decrypt_data_iterations() {
+	let passphrase = b"correct horse battery staple";
+	let data = b"Lorem ipsum dolor sit amet.";
+	let encrypted = encrypt_data(passphrase, data).unwrap();
+	assert_eq!(&encrypted[9..13], &PBKDF2_ITERATIONS.to_be_bytes());
+
+	// The iteration count is checked before the key is derived.
+	for iterations in [0, 10 * PBKDF2_ITERATIONS + 1, u32::MAX] {
+		let mut tampered = encrypted.clone();
+		tampered[9..13].copy_from_slice(&iterations.to_be_bytes());
+		let res = decrypt_data(passphrase, &tampered);
+		assert!(res.unwrap_err().message.contains("iteration count"));
+	}
+}
@@ -1,4 +1,4 @@
-use crate::to_idna;
+use crate::{dns_name_to_idna, from_idna, to_idna};
 
 #[test]
 fn test_no_idna() {
@@ -40,3 +40,144 @@ fn test_mixed_idna_parts() {
 		"ns1.xn--hlo-bma.xn--a-i-2lahae.example.com"
 	);
 }
+
+#[test]
+fn test_idna_case_mapping() {
+	let idna_res = to_idna("HÉLO.Example.COM");
+	assert_eq!(idna_res.unwrap(), "xn--hlo-bma.example.com");
+}
+
+#[test]
+fn test_idna_normalization() {
+	// "e" followed by a combining acute accent
+	let idna_res = to_idna("he\u{0301}lo.example.com");
+	assert_eq!(idna_res.unwrap(), "xn--hlo-bma.example.com");
+}
+
+#[test]
+fn test_idna_mapping() {
+	let idna_res = to_idna("\u{ff45}\u{ff58}\u{ff41}\u{ff4d}\u{ff50}\u{ff4c}\u{ff45}.com");
+	assert_eq!(idna_res.unwrap(), "example.com");
+	let idna_res = to_idna("example\u{3002}com");
+	assert_eq!(idna_res.unwrap(), "example.com");
+}
+
+#[test]
+fn test_idna_non_transitional() {
+	let idna_res = to_idna("faß.de");
+	assert_eq!(idna_res.unwrap(), "xn--fa-hia.de");
+}
+
+#[test]
+fn test_idna_wildcard() {
+	let idna_res = to_idna("*.Hélo.example.com");
+	assert_eq!(idna_res.unwrap(), "*.xn--hlo-bma.example.com");
+	let idna_res = to_idna("*.example.com");
+	assert_eq!(idna_res.unwrap(), "*.example.com");
+}
+
+#[test]
+fn test_idna_trailing_dot() {
+	let idna_res = to_idna("Hélo.example.com.");
+	assert_eq!(idna_res.unwrap(), "xn--hlo-bma.example.com");
+	let idna_res = to_idna("*.example.com.");
+	assert_eq!(idna_res.unwrap(), "*.example.com");
+	let idna_res = dns_name_to_idna("_acme-challenge.example.com.");
+	assert_eq!(idna_res.unwrap(), "_acme-challenge.example.com");
+}
+
+#[test]
+fn test_idna_invalid_wildcard() {
+	let names = [
+		"*",
+		"*.",
+		"*example.com",
+		"*.*.example.com",
+		"www.*.example.com",
+		"www*.example.com",
+	];
+	for name in names.iter() {
+		assert!(to_idna(name).is_err(), "{}", name);
+	}
+}
+
+#[test]
+fn test_idna_invalid_labels() {
+	let long_label = "a".repeat(64);
+	let long_label = format!("{long_label}.example.com");
+	let long_name = format!("{}example.com", "abcdefghi.".repeat(25));
+	let names = [
+		"",
+		".",
+		"..",
+		"example.com..",
+		"example..com",
+		".example.com",
+		"exa mple.com",
+		"under_score.example.com",
+		"-leading.example.com",
+		"trailing-.example.com",
+		"ab--cd.example.com",
+		"xn--hlo-bma-.example.com",
+		"xn--a.example.com",
+		"a\u{200d}b.example.com",
+		"a\u{5d0}.example.com",
+		"\u{0301}a.example.com",
+		long_label.as_str(),
+		long_name.as_str(),
+	];
+	for name in names.iter() {
+		assert!(to_idna(name).is_err(), "{}", name);
+	}
+}
+
+#[test]
+fn test_idna_bidi() {
+	let idna_res = to_idna("\u{5e9}\u{5dc}\u{5d5}\u{5dd}.example.com");
+	assert_eq!(idna_res.unwrap(), "xn--9dbne9b.example.com");
+}
+
+#[test]
+fn test_idna_dns_name() {
+	let idna_res = dns_name_to_idna("_acme-challenge.Hélo.example.com");
+	assert_eq!(idna_res.unwrap(), "_acme-challenge.xn--hlo-bma.example.com");
+	assert!(to_idna("_acme-challenge.example.com").is_err());
+	assert!(dns_name_to_idna("exa mple.com").is_err());
+	assert!(dns_name_to_idna("example..com").is_err());
+}
+
+#[test]
+fn test_from_idna() {
+	let names = [
+		("example.com", "example.com"),
+		("xn--hlo-bma.example.com", "hélo.example.com"),
+		("*.xn--hlo-bma.example.com", "*.hélo.example.com"),
+		(
+			"ns1.xn--hlo-bma.xn--a-i-2lahae.example.com",
+			"ns1.hélo.aç-éièè.example.com",
+		),
+		("xn--fa-hia.de", "faß.de"),
+		("_acme-challenge.example.com", "_acme-challenge.example.com"),
+	];
+	for (idna, unicode) in names.iter() {
+		assert_eq!(from_idna(idna).unwrap(), *unicode);
+	}
+}
+
+#[test]
+fn test_from_idna_invalid() {
+	assert!(from_idna("xn--a.example.com").is_err());
+	assert!(from_idna("exa mple.com").is_err());
+}
+
+#[test]
+fn test_idna_round_trip() {
+	let names = [
+		"hélo.example.com",
+		"*.faß.de",
+		"\u{5e9}\u{5dc}\u{5d5}\u{5dd}.example.com",
+	];
+	for name in names.iter() {
+		assert_eq!(from_idna(&to_idna(name).unwrap()).unwrap(), *name);
+	}
+}
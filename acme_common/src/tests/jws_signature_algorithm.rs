@@ -1,6 +1,6 @@
-use crate::crypto::{gen_keypair, JwsSignatureAlgorithm, KeyType};
+use crate::crypto::{gen_keypair, JwsSignatureAlgorithm, KeyType, Signer};
 
-const TEST_DATA: &'static [u8] = &[72, 101, 108, 108, 111, 32, 119, 111, 114, 108, 100, 33];
+const TEST_DATA: &[u8] = &[72, 101, 108, 108, 111, 32, 119, 111, 114, 108, 100, 33];
 
 #[test]
 fn test_rs256_sign_rsa2048() {
@@ -8,12 +8,41 @@ fn test_rs256_sign_rsa2048() {
 	let _ = k.sign(&JwsSignatureAlgorithm::Rs256, TEST_DATA).unwrap();
 }
 
+#[test]
+fn test_rs256_sign_rsa3072() {
+	let k = gen_keypair(KeyType::Rsa3072).unwrap();
+	let _ = k.sign(&JwsSignatureAlgorithm::Rs256, TEST_DATA).unwrap();
+}
+
 #[test]
 fn test_rs256_sign_rsa4096() {
 	let k = gen_keypair(KeyType::Rsa4096).unwrap();
 	let _ = k.sign(&JwsSignatureAlgorithm::Rs256, TEST_DATA).unwrap();
 }
 
+#[test]
+fn test_rsa_algorithms_sign_rsa2048() {
+	let k = gen_keypair(KeyType::Rsa2048).unwrap();
+	let algs = [
+		JwsSignatureAlgorithm::Rs384,
+		JwsSignatureAlgorithm::Rs512,
+		JwsSignatureAlgorithm::Ps256,
+		JwsSignatureAlgorithm::Ps384,
+		JwsSignatureAlgorithm::Ps512,
+	];
+	for alg in algs.iter() {
+		let sig = k.sign(alg, TEST_DATA).unwrap();
+		assert_eq!(sig.len(), 256);
+	}
+}
+
+#[test]
+fn test_ps256_sign_ecdsa() {
+	let k = gen_keypair(KeyType::EcdsaP256).unwrap();
+	let res = k.sign(&JwsSignatureAlgorithm::Ps256, TEST_DATA);
+	assert!(res.is_err());
+}
+
 #[test]
 fn test_rs256_sign_ecdsa() {
 	let k = gen_keypair(KeyType::EcdsaP256).unwrap();
@@ -60,3 +89,21 @@ fn test_ed448_sign() {
 	let k = gen_keypair(KeyType::Ed448).unwrap();
 	let _ = k.sign(&JwsSignatureAlgorithm::Ed448, TEST_DATA).unwrap();
 }
+
+#[cfg(all(feature = "ml_dsa", not(feature = "crypto_openssl")))]
+#[test]
+fn test_ml_dsa_sign() {
+	let algs = [
+		(KeyType::MlDsa44, JwsSignatureAlgorithm::MlDsa44, 2420),
+		(KeyType::MlDsa65, JwsSignatureAlgorithm::MlDsa65, 3309),
+		(KeyType::MlDsa87, JwsSignatureAlgorithm::MlDsa87, 4627),
+	];
+	for (key_type, alg, sig_len) in algs.iter() {
+		let k = gen_keypair(*key_type).unwrap();
+		let sig = k.sign(alg, TEST_DATA).unwrap();
+		assert_eq!(sig.len(), *sig_len);
+	}
+	let k = gen_keypair(KeyType::MlDsa44).unwrap();
+	let res = k.sign(&JwsSignatureAlgorithm::MlDsa65, TEST_DATA);
+	assert!(res.is_err());
+}
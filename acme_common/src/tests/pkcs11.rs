@@ -0,0 +1,349 @@
+use crate::b64_decode;
+use crate::crypto::{
+	ecdsa_signature_to_der, gen_keypair, pkcs11_uri_without_pin, KeyType, Pkcs11PublicKey,
+	Pkcs11Uri, Signer,
+};
+
+const EC_PARAMS_P256: &[u8] = &[0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+
+fn get_jwk_value(jwk: &serde_json::Value, name: &str) -> Vec<u8> {
+	b64_decode(jwk.get(name).unwrap().as_str().unwrap()).unwrap()
+}
+
+#[test]
+fn test_pkcs11_uri() {
+	let uri: Pkcs11Uri = "pkcs11:token=My%20Token;manufacturer=SoftHSM%20project;model=SoftHSM%20v2;serial=42;slot-id=3;object=acme%3baccount;id=%01%a2;type=private?module-path=/usr/lib/softhsm/libsofthsm2.so&pin-source=file:/run/secrets/pin"
+		.parse()
+		.unwrap();
+	assert_eq!(uri.token, Some("My Token".to_string()));
+	assert_eq!(uri.manufacturer, Some("SoftHSM project".to_string()));
+	assert_eq!(uri.model, Some("SoftHSM v2".to_string()));
+	assert_eq!(uri.serial, Some("42".to_string()));
+	assert_eq!(uri.slot_id, Some(3));
+	assert_eq!(uri.object, Some("acme;account".to_string()));
+	assert_eq!(uri.id, Some(vec![0x01, 0xa2]));
+	assert_eq!(uri.module_path, "/usr/lib/softhsm/libsofthsm2.so");
+	assert_eq!(uri.pin_value, None);
+	assert_eq!(uri.pin_source, Some("file:/run/secrets/pin".to_string()));
+
+	let uri: Pkcs11Uri = "pkcs11:object=acme?module-path=/lib/p11.so&pin-value=1234"
+		.parse()
+		.unwrap();
+	assert_eq!(uri.token, None);
+	assert_eq!(uri.object, Some("acme".to_string()));
+	assert_eq!(uri.id, None);
+	assert_eq!(uri.pin_value, Some("1234".to_string()));
+}
+
+#[test]
+fn test_pkcs11_uri_without_pin() {
+	let uris = [
+		(
+			"pkcs11:object=acme?module-path=/lib/p11.so&pin-value=1234",
+			"pkcs11:object=acme?module-path=/lib/p11.so",
+		),
+		(
+			"pkcs11:object=acme?pin-source=file:/run/pin&module-path=/lib/p11.so",
+			"pkcs11:object=acme?module-path=/lib/p11.so",
+		),
+		(
+			"pkcs11:object=acme?module-path=/lib/p11.so",
+			"pkcs11:object=acme?module-path=/lib/p11.so",
+		),
+		("pkcs11:object=acme?pin-value=1234", "pkcs11:object=acme"),
+		("pkcs11:object=acme", "pkcs11:object=acme"),
+	];
+	for (uri, expected) in uris {
+		assert_eq!(pkcs11_uri_without_pin(uri), expected);
+	}
+}
+
+#[test]
+fn test_invalid_pkcs11_uri() {
+	let invalid_uris = [
+		"",
+		"object=acme?module-path=/lib/p11.so",
+		"file:object=acme?module-path=/lib/p11.so",
+		"pkcs11:object=acme",
+		"pkcs11:token=test?module-path=/lib/p11.so",
+		"pkcs11:object=acme;type=public?module-path=/lib/p11.so",
+		"pkcs11:object=acme;unknown=1?module-path=/lib/p11.so",
+		"pkcs11:object=acme;slot-id=abc?module-path=/lib/p11.so",
+		"pkcs11:object=acme%zz?module-path=/lib/p11.so",
+		"pkcs11:object=acme?module-path=/lib/p11.so&unknown=1",
+	];
+	for uri in invalid_uris {
+		let res = uri.parse::<Pkcs11Uri>();
+		assert!(res.is_err(), "{}", uri);
+	}
+}
+
+#[test]
+fn test_pkcs11_rsa_public_key() {
+	let k = gen_keypair(KeyType::Rsa2048).unwrap();
+	let jwk = k.jwk_public_key().unwrap();
+	let mut n = vec![0];
+	n.extend(get_jwk_value(&jwk, "n"));
+	let e = get_jwk_value(&jwk, "e");
+	let pk = Pkcs11PublicKey::from_rsa_components(&n, &e);
+	assert_eq!(pk.key_type().unwrap(), KeyType::Rsa2048);
+	assert_eq!(pk.to_der().unwrap(), k.public_key_to_der().unwrap());
+	assert_eq!(pk.to_pem().unwrap(), k.public_key_to_pem().unwrap());
+	assert_eq!(pk.jwk(false).unwrap(), jwk);
+	assert_eq!(
+		pk.jwk(true).unwrap(),
+		k.jwk_public_key_thumbprint().unwrap()
+	);
+}
+
+#[test]
+fn test_pkcs11_ecdsa_public_key() {
+	let k = gen_keypair(KeyType::EcdsaP256).unwrap();
+	let jwk = k.jwk_public_key().unwrap();
+	let mut point = vec![0x04];
+	point.extend(get_jwk_value(&jwk, "x"));
+	point.extend(get_jwk_value(&jwk, "y"));
+	let mut wrapped_point = vec![0x04, point.len() as u8];
+	wrapped_point.extend(&point);
+	for p in [&point, &wrapped_point] {
+		let pk = Pkcs11PublicKey::from_ec_components(EC_PARAMS_P256, p).unwrap();
+		assert_eq!(pk.key_type().unwrap(), KeyType::EcdsaP256);
+		assert_eq!(pk.to_der().unwrap(), k.public_key_to_der().unwrap());
+		assert_eq!(pk.to_pem().unwrap(), k.public_key_to_pem().unwrap());
+		assert_eq!(pk.jwk(false).unwrap(), jwk);
+		assert_eq!(
+			pk.jwk(true).unwrap(),
+			k.jwk_public_key_thumbprint().unwrap()
+		);
+	}
+	let res = Pkcs11PublicKey::from_ec_components(&[0x06, 0x03, 0x2b, 0x65, 0x70], &point);
+	assert!(res.is_err());
+	let res = Pkcs11PublicKey::from_ec_components(EC_PARAMS_P256, &point[..33]);
+	assert!(res.is_err());
+}
+
+#[test]
+fn test_ecdsa_signature_to_der() {
+	let mut signature = vec![0x80];
+	signature.extend([0x01; 31]);
+	signature.extend([0x00; 2]);
+	signature.extend([0x7f; 30]);
+	let der = ecdsa_signature_to_der(&signature).unwrap();
+	let mut expected = vec![0x30, 0x43, 0x02, 0x21, 0x00, 0x80];
+	expected.extend([0x01; 31]);
+	expected.extend([0x02, 0x1e]);
+	expected.extend([0x7f; 30]);
+	assert_eq!(der, expected);
+	assert!(ecdsa_signature_to_der(&[]).is_err());
+	assert!(ecdsa_signature_to_der(&[0x01, 0x02, 0x03]).is_err());
+}
+
+// The token is emulated using a software key, its signatures being encoded
+// as the token's ones.
+#[cfg(feature = "crypto_openssl")]
+#[test]
+fn test_pkcs11_csr_signature() {
+	use crate::crypto::{build_cert_req, HashFunction, JwsSignatureAlgorithm};
+	use der::Encode;
+	use openssl::pkey::PKey;
+	use openssl::x509::X509Req;
+	use std::collections::HashMap;
+
+	for key_type in [KeyType::Rsa2048, KeyType::EcdsaP256] {
+		let k = gen_keypair(key_type).unwrap();
+		let jwk = k.jwk_public_key().unwrap();
+		let (pk, alg) = match key_type {
+			KeyType::Rsa2048 => {
+				let n = get_jwk_value(&jwk, "n");
+				let e = get_jwk_value(&jwk, "e");
+				let pk = Pkcs11PublicKey::from_rsa_components(&n, &e);
+				(pk, JwsSignatureAlgorithm::Rs256)
+			}
+			_ => {
+				let mut point = vec![0x04];
+				point.extend(get_jwk_value(&jwk, "x"));
+				point.extend(get_jwk_value(&jwk, "y"));
+				let pk = Pkcs11PublicKey::from_ec_components(EC_PARAMS_P256, &point).unwrap();
+				(pk, JwsSignatureAlgorithm::Es256)
+			}
+		};
+		let csr = build_cert_req(
+			pk.public_key_info().unwrap(),
+			pk.x509_signature_algorithm(&HashFunction::Sha256),
+			&["example.org".to_string()],
+			&["203.0.113.1".to_string()],
+			&HashMap::new(),
+			|cri| {
+				let signature = k.sign(&alg, cri)?;
+				match alg {
+					JwsSignatureAlgorithm::Es256 => ecdsa_signature_to_der(&signature),
+					_ => Ok(signature),
+				}
+			},
+		)
+		.unwrap();
+		let req = X509Req::from_der(&csr.to_der().unwrap()).unwrap();
+		let pkey = PKey::public_key_from_der(&pk.to_der().unwrap()).unwrap();
+		assert!(req.verify(&pkey).unwrap(), "{}", key_type);
+	}
+}
+
+#[cfg(feature = "crypto_openssl")]
+const SOFTHSM_MODULE_PATHS: &[&str] = &[
+	"/usr/lib/softhsm/libsofthsm2.so",
+	"/usr/lib/x86_64-linux-gnu/softhsm/libsofthsm2.so",
+	"/usr/lib64/pkcs11/libsofthsm2.so",
+	"/usr/local/lib/softhsm/libsofthsm2.so",
+];
+
+/// Return the path to the SoftHSM module, which may be set using the
+/// `SOFTHSM2_MODULE` environment variable, if both the module and the
+/// `softhsm2-util` tool are available.
+#[cfg(feature = "crypto_openssl")]
+fn get_softhsm_module() -> Option<String> {
+	use std::path::Path;
+	use std::process::{Command, Stdio};
+
+	let module = match std::env::var("SOFTHSM2_MODULE") {
+		Ok(path) => path,
+		Err(_) => SOFTHSM_MODULE_PATHS
+			.iter()
+			.find(|p| Path::new(p).exists())?
+			.to_string(),
+	};
+	let has_util = Command::new("softhsm2-util")
+		.arg("--version")
+		.stdout(Stdio::null())
+		.stderr(Stdio::null())
+		.status()
+		.map(|s| s.success())
+		.unwrap_or(false);
+	if has_util {
+		Some(module)
+	} else {
+		None
+	}
+}
+
+#[cfg(feature = "crypto_openssl")]
+fn softhsm_util(args: &[&str]) {
+	let status = std::process::Command::new("softhsm2-util")
+		.args(args)
+		.stdout(std::process::Stdio::null())
+		.status()
+		.unwrap();
+	assert!(status.success(), "softhsm2-util {}", args.join(" "));
+}
+
+// A PKCS #11 module is only initialized once per process, hence every
+// check using SoftHSM is done in this single test. Since it requires
+// SoftHSM to be installed, it has to be run explicitly:
+// cargo test --all-features -- --ignored test_pkcs11_softhsm
+#[cfg(feature = "crypto_openssl")]
+#[test]
+#[ignore]
+fn test_pkcs11_softhsm() {
+	use crate::crypto::{Csr, CsrBuilder, HashFunction, JwsSignatureAlgorithm, KeyPair, Signer};
+	use openssl::hash::MessageDigest;
+	use openssl::pkey::PKey;
+	use openssl::rsa::Padding;
+	use openssl::sign::{RsaPssSaltlen, Verifier};
+	use openssl::x509::X509Req;
+	use std::collections::HashMap;
+	use std::fs;
+
+	let module = get_softhsm_module().expect("SoftHSM is not available");
+	let dir = std::env::temp_dir().join(format!("acme_common-softhsm-{}", std::process::id()));
+	let token_dir = dir.join("tokens");
+	fs::create_dir_all(&token_dir).unwrap();
+	let conf_path = dir.join("softhsm2.conf");
+	let conf = format!(
+		"directories.tokendir = {}\nobjectstore.backend = file\nlog.level = ERROR\n",
+		token_dir.display()
+	);
+	fs::write(&conf_path, conf).unwrap();
+	std::env::set_var("SOFTHSM2_CONF", &conf_path);
+	softhsm_util(&[
+		"--init-token",
+		"--free",
+		"--label",
+		"acmed",
+		"--so-pin",
+		"0000",
+		"--pin",
+		"1234",
+	]);
+
+	let keys = [
+		("ecdsa", "01", KeyType::EcdsaP256),
+		("rsa", "02", KeyType::Rsa2048),
+	];
+	for (label, id, key_type) in keys {
+		let soft_key = gen_keypair(key_type).unwrap();
+		let key_path = dir.join(format!("{label}.pem"));
+		fs::write(&key_path, soft_key.private_key_to_pem().unwrap()).unwrap();
+		softhsm_util(&[
+			"--import",
+			key_path.to_str().unwrap(),
+			"--token",
+			"acmed",
+			"--label",
+			label,
+			"--id",
+			id,
+			"--pin",
+			"1234",
+		]);
+		let uri = format!("pkcs11:token=acmed;object={label}?module-path={module}&pin-value=1234");
+		let key = KeyPair::from_pkcs11_uri(&uri).unwrap();
+		assert_eq!(key.key_type, key_type);
+		assert_eq!(
+			key.pkcs11_uri(),
+			Some(pkcs11_uri_without_pin(&uri).as_str())
+		);
+		let public_key = key.public_key_to_der().unwrap();
+		assert_eq!(public_key, soft_key.public_key_to_der().unwrap());
+		let pkey = PKey::public_key_from_der(&public_key).unwrap();
+
+		// JWS signatures
+		let data = b"Hello, World!";
+		let algs = match key_type {
+			KeyType::EcdsaP256 => vec![JwsSignatureAlgorithm::Es256],
+			_ => vec![JwsSignatureAlgorithm::Rs256, JwsSignatureAlgorithm::Ps256],
+		};
+		for alg in algs {
+			let signature = key.sign(&alg, data).unwrap();
+			let mut verifier = Verifier::new(MessageDigest::sha256(), &pkey).unwrap();
+			let signature = match alg {
+				JwsSignatureAlgorithm::Es256 => ecdsa_signature_to_der(&signature).unwrap(),
+				JwsSignatureAlgorithm::Ps256 => {
+					verifier.set_rsa_padding(Padding::PKCS1_PSS).unwrap();
+					verifier
+						.set_rsa_pss_saltlen(RsaPssSaltlen::DIGEST_LENGTH)
+						.unwrap();
+					verifier.set_rsa_mgf1_md(MessageDigest::sha256()).unwrap();
+					signature
+				}
+				_ => signature,
+			};
+			verifier.update(data).unwrap();
+			assert!(verifier.verify(&signature).unwrap(), "{}", alg);
+		}
+
+		// X.509 signature of a certificate signing request
+		for digest in [HashFunction::Sha256, HashFunction::Sha384] {
+			let csr = Csr::build(
+				&key,
+				digest,
+				&["example.org".to_string()],
+				&[],
+				&HashMap::new(),
+			)
+			.unwrap();
+			let der = b64_decode(&csr.to_der_base64().unwrap()).unwrap();
+			let req = X509Req::from_der(&der).unwrap();
+			assert!(req.verify(&pkey).unwrap());
+		}
+	}
+	let _ = fs::remove_dir_all(&dir);
+}
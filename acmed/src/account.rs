@@ -1,5 +1,9 @@
+use crate::acme_dns::AcmeDnsCredentials;
 use crate::acme_proto::account::{register_account, update_account_contacts, update_account_key};
 use crate::endpoint::Endpoint;
+use crate::hooks::{
+	self, AccountCreatedHookData, AccountKeyRolledHookData, Hook, HookEnvData, HookType,
+};
 use crate::logs::HasLogger;
 use crate::storage::FileManager;
 use acme_common::crypto::{gen_keypair, HashFunction, JwsSignatureAlgorithm, KeyPair, KeyType};
@@ -25,10 +29,20 @@ pub struct AccountKey {
 }
 
 impl AccountKey {
-	fn new(key_type: KeyType, signature_algorithm: JwsSignatureAlgorithm) -> Result<Self, Error> {
+	/// Create a new account key. If a key stored in a PKCS #11 token is
+	/// provided, it is used instead of generating a new one.
+	fn new(
+		key_type: KeyType,
+		signature_algorithm: JwsSignatureAlgorithm,
+		token_key: &Option<KeyPair>,
+	) -> Result<Self, Error> {
+		let key = match token_key {
+			Some(k) => k.to_owned(),
+			None => gen_keypair(key_type)?,
+		};
 		Ok(AccountKey {
 			creation_date: SystemTime::now(),
-			key: gen_keypair(key_type)?,
+			key,
 			signature_algorithm,
 		})
 	}
@@ -42,6 +56,9 @@ pub struct AccountEndpoint {
 	pub key_hash: Vec<u8>,
 	pub contacts_hash: Vec<u8>,
 	pub external_account_hash: Vec<u8>,
+	/// Signature algorithm negotiated with the server, overriding the one of
+	/// the current key.
+	pub signature_algorithm: Option<JwsSignatureAlgorithm>,
 }
 
 impl AccountEndpoint {
@@ -53,10 +70,27 @@ impl AccountEndpoint {
 			key_hash: Vec::new(),
 			contacts_hash: Vec::new(),
 			external_account_hash: Vec::new(),
+			signature_algorithm: None,
 		}
 	}
 }
 
+#[derive(Clone, Debug)]
+pub struct AcmeDnsRegistration {
+	pub server: String,
+	pub identifier: String,
+	pub credentials: AcmeDnsCredentials,
+}
+
+#[derive(Clone, Debug)]
+pub struct AccountAuthorization {
+	pub endpoint: String,
+	pub identifier_type: String,
+	pub identifier: String,
+	pub url: String,
+	pub expires: SystemTime,
+}
+
 #[derive(Clone, Debug)]
 pub struct Account {
 	pub name: String,
@@ -66,6 +100,10 @@ pub struct Account {
 	pub past_keys: Vec<AccountKey>,
 	pub file_manager: FileManager,
 	pub external_account: Option<ExternalAccount>,
+	pub acme_dns: Vec<AcmeDnsRegistration>,
+	pub authorizations: Vec<AccountAuthorization>,
+	pub hooks: Vec<Hook>,
+	pub env: HashMap<String, String>,
 }
 
 impl HasLogger for Account {
@@ -113,6 +151,33 @@ impl Account {
 		}
 	}
 
+	/// Return the signature algorithm to use with the given key on the given
+	/// endpoint. The algorithm negotiated with the server, if any, takes
+	/// precedence over the one associated with the key as long as both are
+	/// compatible.
+	pub fn get_signature_algorithm(
+		&self,
+		endpoint_name: &str,
+		key: &AccountKey,
+	) -> Result<JwsSignatureAlgorithm, Error> {
+		let ep = self.get_endpoint(endpoint_name)?;
+		let alg = match ep.signature_algorithm {
+			Some(alg) if key.key.key_type.check_alg_compatibility(&alg).is_ok() => alg,
+			_ => key.signature_algorithm,
+		};
+		Ok(alg)
+	}
+
+	pub fn set_signature_algorithm(
+		&mut self,
+		endpoint_name: &str,
+		signature_algorithm: JwsSignatureAlgorithm,
+	) -> Result<(), Error> {
+		let ep = self.get_endpoint_mut(endpoint_name)?;
+		ep.signature_algorithm = Some(signature_algorithm);
+		Ok(())
+	}
+
 	pub fn get_past_key(&self, key_hash: &[u8]) -> Result<&AccountKey, Error> {
 		let key_hash = key_hash.to_vec();
 		for key in &self.past_keys {
@@ -129,6 +194,7 @@ impl Account {
 		name: &str,
 		contacts: &[(String, String)],
 		key_type: &Option<String>,
+		key_uri: &Option<String>,
 		signature_algorithm: &Option<String>,
 		external_account: &Option<ExternalAccount>,
 	) -> Result<Self, Error> {
@@ -136,18 +202,35 @@ impl Account {
 			.iter()
 			.map(|(k, v)| contact::AccountContact::new(k, v))
 			.collect::<Result<Vec<contact::AccountContact>, Error>>()?;
-		let key_type = match key_type {
-			Some(kt) => kt.parse()?,
-			None => crate::DEFAULT_ACCOUNT_KEY_TYPE,
+		let token_key = match key_uri {
+			Some(uri) => Some(KeyPair::from_pkcs11_uri(uri).map_err(|e| e.prefix(name))?),
+			None => None,
+		};
+		let key_type = match (key_type, &token_key) {
+			(Some(kt), Some(k)) => {
+				let kt: KeyType = kt.parse()?;
+				if kt != k.key_type {
+					let msg = format!(
+						"{name}: the key type is set to {kt} but the key stored in the token is a {} key",
+						k.key_type
+					);
+					return Err(msg.into());
+				}
+				kt
+			}
+			(Some(kt), None) => kt.parse()?,
+			(None, Some(k)) => k.key_type,
+			(None, None) => crate::DEFAULT_ACCOUNT_KEY_TYPE,
 		};
 		let signature_algorithm = match signature_algorithm {
 			Some(sa) => sa.parse()?,
 			None => key_type.get_default_signature_alg(),
 		};
 		key_type.check_alg_compatibility(&signature_algorithm)?;
-		let account = match storage::fetch(file_manager, name).await? {
+		let account = match storage::fetch(file_manager, name, key_uri.as_deref()).await? {
 			Some(mut a) => {
-				a.update_keys(key_type, signature_algorithm).await?;
+				a.update_keys(key_type, signature_algorithm, &token_key)
+					.await?;
 				a.contacts = contacts;
 				a.external_account = external_account.to_owned();
 				a
@@ -157,10 +240,14 @@ impl Account {
 					name: name.to_string(),
 					endpoints: HashMap::new(),
 					contacts,
-					current_key: AccountKey::new(key_type, signature_algorithm)?,
+					current_key: AccountKey::new(key_type, signature_algorithm, &token_key)?,
 					past_keys: Vec::new(),
 					file_manager: file_manager.clone(),
 					external_account: external_account.to_owned(),
+					acme_dns: Vec::new(),
+					authorizations: Vec::new(),
+					hooks: Vec::new(),
+					env: HashMap::new(),
 				};
 				account.debug("initializing a new account");
 				account
@@ -249,20 +336,164 @@ impl Account {
 		Ok(())
 	}
 
+	pub fn get_acme_dns_credentials(
+		&self,
+		server: &str,
+		identifier: &str,
+	) -> Option<AcmeDnsCredentials> {
+		self.acme_dns
+			.iter()
+			.find(|r| r.server == server && r.identifier == identifier)
+			.map(|r| r.credentials.clone())
+	}
+
+	pub fn set_acme_dns_credentials(
+		&mut self,
+		server: &str,
+		identifier: &str,
+		credentials: &AcmeDnsCredentials,
+	) {
+		self.acme_dns
+			.retain(|r| r.server != server || r.identifier != identifier);
+		self.acme_dns.push(AcmeDnsRegistration {
+			server: server.to_string(),
+			identifier: identifier.to_string(),
+			credentials: credentials.to_owned(),
+		});
+	}
+
+	/// Return the authorization obtained on this endpoint for the given
+	/// identifier if it is still valid at the given time.
+	pub fn get_authorization(
+		&self,
+		endpoint_name: &str,
+		identifier_type: &str,
+		identifier: &str,
+		valid_at: SystemTime,
+	) -> Option<&AccountAuthorization> {
+		self.authorizations.iter().find(|a| {
+			a.endpoint == endpoint_name
+				&& a.identifier_type == identifier_type
+				&& a.identifier == identifier
+				&& a.expires > valid_at
+		})
+	}
+
+	/// Return the authorization obtained on this endpoint at the given URL
+	/// if it is still valid at the given time.
+	pub fn get_authorization_by_url(
+		&self,
+		endpoint_name: &str,
+		url: &str,
+		valid_at: SystemTime,
+	) -> Option<&AccountAuthorization> {
+		self.authorizations
+			.iter()
+			.find(|a| a.endpoint == endpoint_name && a.url == url && a.expires > valid_at)
+	}
+
+	pub fn remove_authorization(&mut self, endpoint_name: &str, url: &str) {
+		self.authorizations
+			.retain(|a| a.endpoint != endpoint_name || a.url != url);
+	}
+
+	/// Keep track of a valid authorization, replacing any previous one for
+	/// the same identifier on the same endpoint. Expired authorizations are
+	/// removed.
+	pub fn set_authorization(&mut self, authorization: AccountAuthorization) {
+		let now = SystemTime::now();
+		self.authorizations.retain(|a| {
+			a.expires > now
+				&& (a.endpoint != authorization.endpoint
+					|| a.identifier_type != authorization.identifier_type
+					|| a.identifier != authorization.identifier)
+		});
+		self.authorizations.push(authorization);
+	}
+
+	pub async fn call_account_created_hooks(&self, endpoint_name: &str) {
+		let mut hook_data = AccountCreatedHookData {
+			account_name: self.name.to_owned(),
+			endpoint_name: endpoint_name.to_string(),
+			account_url: self
+				.get_endpoint(endpoint_name)
+				.map(|e| e.account_url.to_owned())
+				.unwrap_or_default(),
+			contacts: self.contacts.iter().map(|c| c.to_string()).collect(),
+			key_type: self.current_key.key.key_type.to_string(),
+			signature_algorithm: self
+				.get_signature_algorithm(endpoint_name, &self.current_key)
+				.unwrap_or(self.current_key.signature_algorithm)
+				.to_string(),
+			env: HashMap::new(),
+		};
+		hook_data.set_env(&self.env);
+		if let Err(e) = hooks::call(self, &self.hooks, &hook_data, HookType::AccountCreated).await {
+			self.warn(&e.prefix("account-created hook error").message);
+		}
+	}
+
+	pub async fn call_account_key_rolled_hooks(&self, endpoint_name: &str, old_key: &AccountKey) {
+		let mut hook_data = AccountKeyRolledHookData {
+			account_name: self.name.to_owned(),
+			endpoint_name: endpoint_name.to_string(),
+			account_url: self
+				.get_endpoint(endpoint_name)
+				.map(|e| e.account_url.to_owned())
+				.unwrap_or_default(),
+			old_key_type: old_key.key.key_type.to_string(),
+			old_signature_algorithm: self
+				.get_signature_algorithm(endpoint_name, old_key)
+				.unwrap_or(old_key.signature_algorithm)
+				.to_string(),
+			new_key_type: self.current_key.key.key_type.to_string(),
+			new_signature_algorithm: self
+				.get_signature_algorithm(endpoint_name, &self.current_key)
+				.unwrap_or(self.current_key.signature_algorithm)
+				.to_string(),
+			env: HashMap::new(),
+		};
+		hook_data.set_env(&self.env);
+		if let Err(e) = hooks::call(self, &self.hooks, &hook_data, HookType::AccountKeyRolled).await
+		{
+			self.warn(&e.prefix("account-key-rolled hook error").message);
+		}
+	}
+
 	async fn update_keys(
 		&mut self,
 		key_type: KeyType,
 		signature_algorithm: JwsSignatureAlgorithm,
+		token_key: &Option<KeyPair>,
 	) -> Result<(), Error> {
-		if self.current_key.key.key_type != key_type
-			|| self.current_key.signature_algorithm != signature_algorithm
-		{
+		let key_changed = match token_key {
+			Some(k) => k.public_key_to_pem()? != self.current_key.key.public_key_to_pem()?,
+			None => {
+				self.current_key.key.pkcs11_uri().is_some()
+					|| self.current_key.key.key_type != key_type
+					|| self.current_key.signature_algorithm != signature_algorithm
+			}
+		};
+		if key_changed {
 			self.debug("account key has been changed in the configuration, creating a new one...");
 			self.past_keys.push(self.current_key.to_owned());
-			self.current_key = AccountKey::new(key_type, signature_algorithm)?;
+			self.current_key = AccountKey::new(key_type, signature_algorithm, token_key)?;
 			self.save().await?;
 			let msg = format!("new {key_type} account key created, using {signature_algorithm} as signing algorithm");
 			self.info(&msg);
+		} else if let Some(k) = token_key {
+			// The token key is still the same, however the way to access it
+			// or the signature algorithm may have changed.
+			if k.pkcs11_uri() != self.current_key.key.pkcs11_uri()
+				|| self.current_key.signature_algorithm != signature_algorithm
+			{
+				self.current_key.key = k.to_owned();
+				self.current_key.signature_algorithm = signature_algorithm;
+				self.save().await?;
+				self.debug("account key settings updated");
+			} else {
+				self.trace("account key is up to date");
+			}
 		} else {
 			self.trace("account key is up to date");
 		}
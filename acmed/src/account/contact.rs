@@ -90,7 +90,7 @@ mod tests {
 
 	#[test]
 	fn test_account_contact_in_vec() {
-		let contacts = vec![
+		let contacts = [
 			AccountContact::new("mailto", "derp.derpson@example.com").unwrap(),
 			AccountContact::new("mailto", "derp@example.com").unwrap(),
 		];
@@ -100,7 +100,7 @@ mod tests {
 
 	#[test]
 	fn test_account_contact_not_in_vec() {
-		let contacts = vec![
+		let contacts = [
 			AccountContact::new("mailto", "derp.derpson@example.com").unwrap(),
 			AccountContact::new("mailto", "derp@example.com").unwrap(),
 		];
@@ -1,7 +1,11 @@
 use crate::account::contact::AccountContact;
-use crate::account::{Account, AccountEndpoint, AccountKey, ExternalAccount};
+use crate::account::{
+	Account, AccountAuthorization, AccountEndpoint, AccountKey, AcmeDnsRegistration,
+	ExternalAccount,
+};
+use crate::acme_dns::AcmeDnsCredentials;
 use crate::storage::{account_files_exists, get_account_data, set_account_data, FileManager};
-use acme_common::crypto::KeyPair;
+use acme_common::crypto::{pkcs11_uri_without_pin, KeyPair};
 use acme_common::error::Error;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -39,19 +43,52 @@ struct AccountKeyStorage {
 	signature_algorithm: String,
 }
 
+// Keys stored in a PKCS #11 token cannot be exported, hence their URI is
+// stored instead. Since a DER-encoded key always starts with a sequence tag,
+// both cannot be mistaken for each other. The PIN is never stored: it is
+// taken from the URI set in the configuration when the key is opened.
+const PKCS11_URI_PREFIX: &[u8] = b"pkcs11:";
+
 impl AccountKeyStorage {
 	fn new(key: &AccountKey) -> Result<Self, Error> {
+		let key_data = match key.key.pkcs11_uri() {
+			Some(uri) => uri.as_bytes().to_vec(),
+			None => key.key.private_key_to_der()?,
+		};
 		Ok(AccountKeyStorage {
 			creation_date: key.creation_date,
-			key: key.key.private_key_to_der()?,
+			key: key_data,
 			signature_algorithm: key.signature_algorithm.to_string(),
 		})
 	}
 
-	fn to_generic(&self) -> Result<AccountKey, Error> {
+	fn is_pkcs11(&self) -> bool {
+		self.key.starts_with(PKCS11_URI_PREFIX)
+	}
+
+	/// Whether the stored PKCS #11 URI includes a PIN, as written by previous
+	/// versions.
+	fn has_pkcs11_pin(&self) -> bool {
+		match std::str::from_utf8(&self.key) {
+			Ok(uri) if self.is_pkcs11() => pkcs11_uri_without_pin(uri) != uri,
+			_ => false,
+		}
+	}
+
+	fn to_generic(&self, key_uri: Option<&str>) -> Result<AccountKey, Error> {
+		let key = if self.is_pkcs11() {
+			let uri = String::from_utf8(self.key.to_owned())?;
+			let uri = match key_uri {
+				Some(u) if pkcs11_uri_without_pin(u) == pkcs11_uri_without_pin(&uri) => u,
+				_ => &uri,
+			};
+			KeyPair::from_pkcs11_uri(uri)?
+		} else {
+			KeyPair::from_der(&self.key)?
+		};
 		Ok(AccountKey {
 			creation_date: self.creation_date,
-			key: KeyPair::from_der(&self.key)?,
+			key,
 			signature_algorithm: self.signature_algorithm.parse()?,
 		})
 	}
@@ -87,6 +124,63 @@ impl AccountEndpointStorage {
 			key_hash: self.key_hash.clone(),
 			contacts_hash: self.contacts_hash.clone(),
 			external_account_hash: self.external_account_hash.clone(),
+			signature_algorithm: None,
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct AcmeDnsRegistrationStorage {
+	server: String,
+	identifier: String,
+	credentials: AcmeDnsCredentials,
+}
+
+impl AcmeDnsRegistrationStorage {
+	fn new(registration: &AcmeDnsRegistration) -> Self {
+		AcmeDnsRegistrationStorage {
+			server: registration.server.to_owned(),
+			identifier: registration.identifier.to_owned(),
+			credentials: registration.credentials.to_owned(),
+		}
+	}
+
+	fn to_generic(&self) -> AcmeDnsRegistration {
+		AcmeDnsRegistration {
+			server: self.server.to_owned(),
+			identifier: self.identifier.to_owned(),
+			credentials: self.credentials.to_owned(),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct AccountAuthorizationStorage {
+	endpoint: String,
+	identifier_type: String,
+	identifier: String,
+	url: String,
+	expires: SystemTime,
+}
+
+impl AccountAuthorizationStorage {
+	fn new(authorization: &AccountAuthorization) -> Self {
+		AccountAuthorizationStorage {
+			endpoint: authorization.endpoint.to_owned(),
+			identifier_type: authorization.identifier_type.to_owned(),
+			identifier: authorization.identifier.to_owned(),
+			url: authorization.url.to_owned(),
+			expires: authorization.expires,
+		}
+	}
+
+	fn to_generic(&self) -> AccountAuthorization {
+		AccountAuthorization {
+			endpoint: self.endpoint.to_owned(),
+			identifier_type: self.identifier_type.to_owned(),
+			identifier: self.identifier.to_owned(),
+			url: self.url.to_owned(),
+			expires: self.expires,
 		}
 	}
 }
@@ -99,36 +193,129 @@ struct AccountStorage {
 	current_key: AccountKeyStorage,
 	past_keys: Vec<AccountKeyStorage>,
 	external_account: Option<ExternalAccountStorage>,
+	acme_dns: Vec<AcmeDnsRegistrationStorage>,
+	authorizations: Vec<AccountAuthorizationStorage>,
+	signature_algorithms: HashMap<String, String>,
+}
+
+// Account files start with this marker followed by the version of their
+// format, each older version being migrated to the current one when read.
+// Files written before the format was versioned (version 0) begin with the
+// length of the account name as a 64-bit little-endian integer, which is
+// never large enough to be mistaken for the marker.
+const ACCOUNT_FILE_MAGIC: &[u8] = b"\0acmed";
+const ACCOUNT_FILE_VERSION: u32 = 1;
+
+// Version 0: account files written before the acme-dns registrations, the
+// authorizations and the negotiated signature algorithms were stored.
+#[derive(Serialize, Deserialize)]
+struct AccountStorageV0 {
+	name: String,
+	endpoints: HashMap<String, AccountEndpointStorage>,
+	contacts: Vec<(String, String)>,
+	current_key: AccountKeyStorage,
+	past_keys: Vec<AccountKeyStorage>,
+	external_account: Option<ExternalAccountStorage>,
+}
+
+impl From<AccountStorageV0> for AccountStorage {
+	fn from(v0: AccountStorageV0) -> Self {
+		AccountStorage {
+			name: v0.name,
+			endpoints: v0.endpoints,
+			contacts: v0.contacts,
+			current_key: v0.current_key,
+			past_keys: v0.past_keys,
+			external_account: v0.external_account,
+			acme_dns: Vec::new(),
+			authorizations: Vec::new(),
+			signature_algorithms: HashMap::new(),
+		}
+	}
+}
+
+fn decode_version<T: serde::de::DeserializeOwned>(data: &[u8]) -> Result<T, Error> {
+	let cfg = bincode::config::legacy();
+	let (obj, _) =
+		bincode::serde::decode_from_slice(data, cfg).map_err(|e| Error::from(&e.to_string()))?;
+	Ok(obj)
 }
 
-async fn do_fetch(file_manager: &FileManager, name: &str) -> Result<Option<Account>, Error> {
+fn decode(data: &[u8]) -> Result<AccountStorage, Error> {
+	let (version, payload) = match data.strip_prefix(ACCOUNT_FILE_MAGIC) {
+		Some(rest) => {
+			if rest.len() < 4 {
+				return Err("truncated account file".into());
+			}
+			let (version, payload) = rest.split_at(4);
+			let version = u32::from_le_bytes([version[0], version[1], version[2], version[3]]);
+			(version, payload)
+		}
+		None => (0, data),
+	};
+	match version {
+		0 => Ok(decode_version::<AccountStorageV0>(payload)?.into()),
+		ACCOUNT_FILE_VERSION => decode_version(payload),
+		v => Err(format!("unsupported account file version: {v}").into()),
+	}
+}
+
+fn encode(account_storage: &AccountStorage) -> Result<Vec<u8>, Error> {
+	let cfg = bincode::config::legacy();
+	let payload = bincode::serde::encode_to_vec(account_storage, cfg)
+		.map_err(|e| Error::from(&e.to_string()))?;
+	let mut encoded = ACCOUNT_FILE_MAGIC.to_vec();
+	encoded.extend_from_slice(&ACCOUNT_FILE_VERSION.to_le_bytes());
+	encoded.extend_from_slice(&payload);
+	Ok(encoded)
+}
+
+async fn do_fetch(
+	file_manager: &FileManager,
+	name: &str,
+	key_uri: Option<&str>,
+) -> Result<Option<Account>, Error> {
 	if account_files_exists(file_manager) {
 		let data = get_account_data(file_manager).await?;
-		let cfg = bincode::config::legacy();
-		let obj: AccountStorage = bincode::serde::decode_from_slice(&data[..], cfg)
-			.map_err(|e| Error::from(&e.to_string()).prefix(name))?
-			.0;
-		let endpoints = obj
+		let obj = decode(&data[..]).map_err(|e| e.prefix(name))?;
+		let mut endpoints: HashMap<String, AccountEndpoint> = obj
 			.endpoints
 			.iter()
 			.map(|(k, v)| (k.clone(), v.to_generic()))
 			.collect();
+		for (endpoint_name, alg) in obj.signature_algorithms.iter() {
+			if let Some(ep) = endpoints.get_mut(endpoint_name) {
+				ep.signature_algorithm = Some(alg.parse()?);
+			}
+		}
 		let contacts = obj
 			.contacts
 			.iter()
 			.map(|(t, v)| AccountContact::new(t, v))
 			.collect::<Result<Vec<AccountContact>, Error>>()?;
-		let current_key = obj.current_key.to_generic()?;
-		let past_keys = obj
-			.past_keys
-			.iter()
-			.map(|k| k.to_generic())
-			.collect::<Result<Vec<AccountKey>, Error>>()?;
+		let current_key = obj.current_key.to_generic(key_uri)?;
+		let mut past_keys = Vec::with_capacity(obj.past_keys.len());
+		for key in obj.past_keys.iter() {
+			match key.to_generic(key_uri) {
+				Ok(k) => past_keys.push(k),
+				// A past key which has been removed from its token should
+				// not prevent the account from being used.
+				Err(e) if key.is_pkcs11() => {
+					let msg = format!("account \"{name}\": past key ignored: {e}");
+					log::warn!("{msg}");
+				}
+				Err(e) => {
+					return Err(e);
+				}
+			}
+		}
 		let external_account = match obj.external_account {
 			Some(a) => Some(a.to_generic()?),
 			None => None,
 		};
-		Ok(Some(Account {
+		let acme_dns = obj.acme_dns.iter().map(|r| r.to_generic()).collect();
+		let authorizations = obj.authorizations.iter().map(|a| a.to_generic()).collect();
+		let account = Account {
 			name: obj.name,
 			endpoints,
 			contacts,
@@ -136,7 +323,20 @@ async fn do_fetch(file_manager: &FileManager, name: &str) -> Result<Option<Accou
 			past_keys,
 			file_manager: file_manager.clone(),
 			external_account,
-		}))
+			acme_dns,
+			authorizations,
+			hooks: Vec::new(),
+			env: HashMap::new(),
+		};
+		let has_pin = obj.current_key.has_pkcs11_pin()
+			|| obj.past_keys.iter().any(AccountKeyStorage::has_pkcs11_pin);
+		if has_pin {
+			let msg =
+				format!("account \"{name}\": removing the PKCS #11 PIN from the account file");
+			log::info!("{msg}");
+			do_save(file_manager, &account).await?;
+		}
+		Ok(Some(account))
 	} else {
 		Ok(None)
 	}
@@ -162,6 +362,21 @@ async fn do_save(file_manager: &FileManager, account: &Account) -> Result<(), Er
 		.external_account
 		.as_ref()
 		.map(ExternalAccountStorage::new);
+	let acme_dns = account
+		.acme_dns
+		.iter()
+		.map(AcmeDnsRegistrationStorage::new)
+		.collect();
+	let authorizations = account
+		.authorizations
+		.iter()
+		.map(AccountAuthorizationStorage::new)
+		.collect();
+	let signature_algorithms = account
+		.endpoints
+		.iter()
+		.filter_map(|(k, v)| v.signature_algorithm.map(|a| (k.to_owned(), a.to_string())))
+		.collect();
 	let account_storage = AccountStorage {
 		name: account.name.to_owned(),
 		endpoints,
@@ -169,15 +384,20 @@ async fn do_save(file_manager: &FileManager, account: &Account) -> Result<(), Er
 		current_key: AccountKeyStorage::new(&account.current_key)?,
 		past_keys,
 		external_account,
+		acme_dns,
+		authorizations,
+		signature_algorithms,
 	};
-	let cfg = bincode::config::legacy();
-	let encoded: Vec<u8> = bincode::serde::encode_to_vec(&account_storage, cfg)
-		.map_err(|e| Error::from(&e.to_string()).prefix(&account.name))?;
+	let encoded = encode(&account_storage).map_err(|e| e.prefix(&account.name))?;
 	set_account_data(file_manager, &encoded).await
 }
 
-pub async fn fetch(file_manager: &FileManager, name: &str) -> Result<Option<Account>, Error> {
-	do_fetch(file_manager, name).await.map_err(|_| {
+pub async fn fetch(
+	file_manager: &FileManager,
+	name: &str,
+	key_uri: Option<&str>,
+) -> Result<Option<Account>, Error> {
+	do_fetch(file_manager, name, key_uri).await.map_err(|_| {
 		format!("account \"{name}\": unable to load account file: file may be corrupted").into()
 	})
 }
@@ -187,3 +407,75 @@ pub async fn save(file_manager: &FileManager, account: &Account) -> Result<(), E
 		.await
 		.map_err(|e| format!("unable to save account file: {e}").into())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn key_storage() -> AccountKeyStorage {
+		AccountKeyStorage {
+			creation_date: SystemTime::UNIX_EPOCH,
+			key: vec![0x30, 0x00],
+			signature_algorithm: "ES256".to_string(),
+		}
+	}
+
+	#[test]
+	fn test_decode_current_version() {
+		let mut signature_algorithms = HashMap::new();
+		signature_algorithms.insert("example".to_string(), "ES256".to_string());
+		let storage = AccountStorage {
+			name: "test".to_string(),
+			endpoints: HashMap::new(),
+			contacts: vec![("mailto".to_string(), "derp@example.com".to_string())],
+			current_key: key_storage(),
+			past_keys: Vec::new(),
+			external_account: None,
+			acme_dns: Vec::new(),
+			authorizations: Vec::new(),
+			signature_algorithms,
+		};
+		let encoded = encode(&storage).unwrap();
+		assert!(encoded.starts_with(ACCOUNT_FILE_MAGIC));
+		assert_eq!(decode(&encoded).unwrap(), storage);
+	}
+
+	#[test]
+	fn test_decode_version_0() {
+		let v0 = AccountStorageV0 {
+			name: "test".to_string(),
+			endpoints: HashMap::new(),
+			contacts: Vec::new(),
+			current_key: key_storage(),
+			past_keys: vec![key_storage()],
+			external_account: None,
+		};
+		let cfg = bincode::config::legacy();
+		let encoded = bincode::serde::encode_to_vec(&v0, cfg).unwrap();
+		let storage = decode(&encoded).unwrap();
+		assert_eq!(storage.name, "test");
+		assert_eq!(storage.past_keys.len(), 1);
+		assert!(storage.acme_dns.is_empty());
+		assert!(storage.authorizations.is_empty());
+		assert!(storage.signature_algorithms.is_empty());
+	}
+
+	#[test]
+	fn test_pkcs11_pin() {
+		let mut key = key_storage();
+		assert!(!key.is_pkcs11());
+		assert!(!key.has_pkcs11_pin());
+		key.key = b"pkcs11:object=acme?module-path=/lib/p11.so".to_vec();
+		assert!(key.is_pkcs11());
+		assert!(!key.has_pkcs11_pin());
+		key.key = b"pkcs11:object=acme?module-path=/lib/p11.so&pin-value=1234".to_vec();
+		assert!(key.has_pkcs11_pin());
+	}
+
+	#[test]
+	fn test_decode_unsupported_version() {
+		let mut encoded = ACCOUNT_FILE_MAGIC.to_vec();
+		encoded.extend_from_slice(&(ACCOUNT_FILE_VERSION + 1).to_le_bytes());
+		assert!(decode(&encoded).is_err());
+	}
+}
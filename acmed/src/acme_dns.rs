@@ -0,0 +1,179 @@
+use crate::http::{get_client, CONTENT_TYPE_JSON};
+use crate::logs::HasLogger;
+use crate::AccountSync;
+use acme_common::error::Error;
+use reqwest::header;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const HEADER_API_USER: &str = "X-Api-User";
+const HEADER_API_KEY: &str = "X-Api-Key";
+
+#[derive(Clone, Debug)]
+pub struct AcmeDnsServer {
+	pub name: String,
+	pub url: String,
+	pub allow_from: Vec<String>,
+	pub root_certificates: Vec<String>,
+}
+
+impl AcmeDnsServer {
+	fn get_url(&self, path: &str) -> String {
+		format!("{}/{path}", self.url.trim_end_matches('/'))
+	}
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct AcmeDnsCredentials {
+	pub username: String,
+	pub password: String,
+	#[serde(rename = "fulldomain")]
+	pub full_domain: String,
+	pub subdomain: String,
+}
+
+async fn register(server: &AcmeDnsServer) -> Result<AcmeDnsCredentials, Error> {
+	let client = get_client(&server.root_certificates)?;
+	let mut request = client
+		.post(server.get_url("register"))
+		.header(header::ACCEPT, CONTENT_TYPE_JSON);
+	if !server.allow_from.is_empty() {
+		let body = json!({ "allowfrom": server.allow_from });
+		request = request
+			.header(header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+			.body(body.to_string());
+	}
+	let response = request.send().await?;
+	let status = response.status();
+	let body = response.text().await?;
+	if !status.is_success() {
+		let msg = format!("registration failed: HTTP error: {}", status.as_u16());
+		return Err(msg.into());
+	}
+	let creds: AcmeDnsCredentials = serde_json::from_str(&body)?;
+	Ok(creds)
+}
+
+async fn update(
+	server: &AcmeDnsServer,
+	creds: &AcmeDnsCredentials,
+	value: &str,
+) -> Result<(), Error> {
+	let client = get_client(&server.root_certificates)?;
+	let body = json!({
+		"subdomain": creds.subdomain,
+		"txt": value,
+	});
+	let response = client
+		.post(server.get_url("update"))
+		.header(header::ACCEPT, CONTENT_TYPE_JSON)
+		.header(header::CONTENT_TYPE, CONTENT_TYPE_JSON)
+		.header(HEADER_API_USER, &creds.username)
+		.header(HEADER_API_KEY, &creds.password)
+		.body(body.to_string())
+		.send()
+		.await?;
+	let status = response.status();
+	if !status.is_success() {
+		let body = response.text().await.unwrap_or_default();
+		let msg = format!("update failed: HTTP error: {}: {body}", status.as_u16());
+		return Err(msg.into());
+	}
+	Ok(())
+}
+
+/// Return the credentials of the account for this identifier, registering
+/// to the server if there are none yet.
+///
+/// The account is locked during the registration: the challenges of an
+/// order are set up concurrently and an identifier and its wildcard share
+/// the same record, hence they must share the same registration.
+async fn get_credentials<L: HasLogger>(
+	logger: &L,
+	account_s: &AccountSync,
+	server: &AcmeDnsServer,
+	identifier: &str,
+) -> Result<AcmeDnsCredentials, Error> {
+	let mut account = account_s.write().await;
+	if let Some(creds) = account.get_acme_dns_credentials(&server.name, identifier) {
+		return Ok(creds);
+	}
+	logger.debug(&format!(
+		"acme-dns \"{}\": registering for {identifier}",
+		server.name
+	));
+	let creds = register(server)
+		.await
+		.map_err(|e| e.prefix(&format!("acme-dns \"{}\"", server.name)))?;
+	account.set_acme_dns_credentials(&server.name, identifier, &creds);
+	account.save().await?;
+	logger.info(&format!(
+		"acme-dns \"{}\": registered for {identifier}, the record _acme-challenge.{identifier} should be a CNAME to {}",
+		server.name, creds.full_domain
+	));
+	Ok(creds)
+}
+
+/// Set the TXT record of an identifier on an acme-dns server and return the
+/// name of the record that has been updated.
+///
+/// If the account has not been registered to this server for this
+/// identifier yet, a new registration is done and stored alongside the
+/// account.
+pub async fn set_txt_record<L: HasLogger>(
+	logger: &L,
+	account_s: &AccountSync,
+	server: &AcmeDnsServer,
+	identifier: &str,
+	value: &str,
+) -> Result<String, Error> {
+	let creds = get_credentials(logger, account_s, server, identifier).await?;
+	update(server, &creds, value)
+		.await
+		.map_err(|e| e.prefix(&format!("acme-dns \"{}\"", server.name)))?;
+	logger.debug(&format!(
+		"acme-dns \"{}\": TXT record {} updated",
+		server.name, creds.full_domain
+	));
+	Ok(creds.full_domain.trim_end_matches('.').to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{AcmeDnsCredentials, AcmeDnsServer};
+
+	#[test]
+	fn test_registration_response() {
+		let data = r#"{
+  "allowfrom": [],
+  "fulldomain": "8e5700ea-a4bf-41c7-8a77-e990661dcc6a.auth.acme-dns.io",
+  "password": "htB9mR9DYgcu9bX_afHF62erXaH2TS7bg9KW3F7Z",
+  "subdomain": "8e5700ea-a4bf-41c7-8a77-e990661dcc6a",
+  "username": "c36f50e8-4632-44f0-83fe-e070fef28a10"
+}"#;
+		let creds: AcmeDnsCredentials = serde_json::from_str(data).unwrap();
+		assert_eq!(
+			creds.full_domain,
+			"8e5700ea-a4bf-41c7-8a77-e990661dcc6a.auth.acme-dns.io"
+		);
+		assert_eq!(creds.subdomain, "8e5700ea-a4bf-41c7-8a77-e990661dcc6a");
+		assert_eq!(creds.username, "c36f50e8-4632-44f0-83fe-e070fef28a10");
+		assert_eq!(creds.password, "htB9mR9DYgcu9bX_afHF62erXaH2TS7bg9KW3F7Z");
+	}
+
+	#[test]
+	fn test_server_url() {
+		let mut server = AcmeDnsServer {
+			name: "test".to_string(),
+			url: "https://auth.example.org".to_string(),
+			allow_from: vec![],
+			root_certificates: vec![],
+		};
+		assert_eq!(server.get_url("update"), "https://auth.example.org/update");
+		server.url = "https://auth.example.org/acme-dns/".to_string();
+		assert_eq!(
+			server.get_url("register"),
+			"https://auth.example.org/acme-dns/register"
+		);
+	}
+}
@@ -1,19 +1,26 @@
+use crate::account::AccountAuthorization;
 use crate::acme_proto::structs::{
 	AcmeError, ApiError, Authorization, AuthorizationStatus, NewOrder, Order, OrderStatus,
 };
 use crate::certificate::Certificate;
+use crate::hooks::{ChallengeHookData, HookType};
 use crate::http::HttpError;
 use crate::identifier::IdentifierType;
 use crate::jws::encode_kid;
 use crate::logs::HasLogger;
-use crate::storage;
+use crate::storage::{self, PendingOrder};
+use crate::timestamp::parse_rfc3339;
 use crate::{AccountSync, EndpointSync};
-use acme_common::crypto::Csr;
+use acme_common::crypto::{Csr, CsrBuilder};
 use acme_common::error::Error;
+use futures::future::join_all;
 use serde_json::json;
+use std::collections::HashMap;
 use std::fmt;
+use std::time::SystemTime;
 
 pub mod account;
+pub mod authorization;
 mod certificate;
 mod http;
 pub mod structs;
@@ -65,7 +72,7 @@ macro_rules! set_data_builder_sync {
 		move |n: &str, url: &str| {
 			encode_kid(
 				&$account.current_key.key,
-				&$account.current_key.signature_algorithm,
+				&$account.get_signature_algorithm(endpoint_name, &$account.current_key)?,
 				&($account.get_endpoint(endpoint_name)?.account_url),
 				$data,
 				url,
@@ -85,204 +92,688 @@ macro_rules! set_data_builder {
 	};
 }
 
-pub async fn request_certificate(
-	cert: &Certificate,
-	account_s: AccountSync,
-	endpoint_s: EndpointSync,
-) -> Result<(), Error> {
-	let mut hook_datas = vec![];
-	let endpoint_name = endpoint_s.read().await.name.clone();
+/// Error raised while processing an order. Unless the order reached a final
+/// state or the server rejected a request, the order in progress is kept so
+/// that it can be resumed by the next attempt.
+struct OrderError {
+	error: Error,
+	is_final: bool,
+}
 
-	// Refresh the directory
-	http::refresh_directory(&mut *(endpoint_s.write().await))
-		.await
-		.map_err(HttpError::in_err)?;
+impl OrderError {
+	fn final_state(error: Error) -> Self {
+		OrderError {
+			error,
+			is_final: true,
+		}
+	}
+}
 
-	// Synchronize the account
-	account_s
-		.write()
-		.await
-		.synchronize(&mut *(endpoint_s.write().await))
+impl<E: Into<Error>> From<E> for OrderError {
+	fn from(error: E) -> Self {
+		OrderError {
+			error: error.into(),
+			is_final: false,
+		}
+	}
+}
+
+impl From<HttpError> for OrderError {
+	fn from(error: HttpError) -> Self {
+		let is_final = matches!(error, HttpError::ApiError(_));
+		OrderError {
+			error: HttpError::in_err(error),
+			is_final,
+		}
+	}
+}
+
+fn check_order_status(order: &Order) -> Result<(), OrderError> {
+	if order.status != OrderStatus::Invalid {
+		return Ok(());
+	}
+	let error = match order.get_error() {
+		Some(e) => e.prefix("the order is invalid"),
+		None => "the order is invalid".into(),
+	};
+	Err(OrderError::final_state(error))
+}
+
+struct PendingChallenge {
+	auth_url: String,
+	challenge: Challenge,
+	identifier: String,
+	file_name: String,
+	url: String,
+	proof: String,
+	raw_proof: Option<String>,
+}
+
+/// Set up a challenge by calling the associated hooks and return the data
+/// required for the clean hooks alongside the name of the DNS record that
+/// has been set, if any.
+async fn set_up_challenge(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	chall: &PendingChallenge,
+) -> Result<((ChallengeHookData, HookType), String), Error> {
+	// Find out where the DNS record has to be set
+	let dns_record_name = if chall.challenge == Challenge::Dns01 {
+		cert.set_dns_record(account_s, &chall.identifier, &chall.proof)
+			.await?
+	} else {
+		String::new()
+	};
+	let data = cert
+		.call_challenge_hooks(
+			&chall.file_name,
+			&chall.proof,
+			chall.raw_proof.to_owned(),
+			&chall.identifier,
+			chall.challenge,
+			&dns_record_name,
+		)
 		.await?;
+	Ok((data, dns_record_name))
+}
 
-	// Create a new order
-	let mut new_reg = false;
-	let (order, order_url) = loop {
-		let new_order = NewOrder::new(&cert.identifiers);
-		let new_order = serde_json::to_string(&new_order)?;
-		let data_builder = set_data_builder!(account_s, endpoint_name, new_order.as_bytes()).await;
-		match http::new_order(&mut *(endpoint_s.write().await), &data_builder).await {
-			Ok((order, order_url)) => {
-				if let Some(e) = order.get_error() {
-					cert.warn(&e.prefix("Error").message);
-				}
-				break (order, order_url);
+/// Return the first error, if any, and log the other ones.
+fn first_error<I>(cert: &Certificate, results: I) -> Result<(), Error>
+where
+	I: IntoIterator<Item = Result<(), Error>>,
+{
+	let mut ret = Ok(());
+	for res in results {
+		if let Err(e) = res {
+			if ret.is_ok() {
+				ret = Err(e);
+			} else {
+				cert.warn(&e.message);
 			}
+		}
+	}
+	ret
+}
+
+/// Pool an authorization until it is no longer pending.
+async fn pool_authorization(
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	endpoint_name: &str,
+	auth_url: &str,
+) -> Result<Authorization, Error> {
+	let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
+	let break_fn = |a: &Authorization| a.status != AuthorizationStatus::Pending;
+	http::pool_authorization(endpoint_s, &data_builder, &break_fn, auth_url)
+		.await
+		.map_err(HttpError::in_err)
+}
+
+/// Tell the server the challenges have been completed and pool every
+/// pending authorization concurrently. The challenges the server considered
+/// invalid are added to the failed challenges list.
+async fn validate_authorizations(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	endpoint_name: &str,
+	challenges: &[PendingChallenge],
+	dns_records: &[(String, &str)],
+	failed_challenges: &mut HashMap<String, Vec<Challenge>>,
+) -> Result<(), Error> {
+	// Wait for the DNS records to be visible by the server
+	join_all(
+		dns_records
+			.iter()
+			.map(|(name, proof)| cert.wait_for_dns_propagation(name, proof)),
+	)
+	.await;
+
+	// Tell the server the challenges have been completed
+	for chall in challenges.iter() {
+		let data_builder = set_data_builder!(account_s, endpoint_name, b"{}").await;
+		http::post_jose_no_response(&mut *(endpoint_s.write().await), &data_builder, &chall.url)
+			.await
+			.map_err(HttpError::in_err)?;
+		drop(data_builder);
+	}
+
+	// Pool the authorizations in order to see whether or not they are valid
+	let results =
+		join_all(challenges.iter().map(|chall| {
+			pool_authorization(account_s, endpoint_s, endpoint_name, &chall.auth_url)
+		}))
+		.await;
+	let mut validation_results = vec![];
+	for (chall, res) in challenges.iter().zip(results) {
+		let auth = match res {
+			Ok(auth) => auth,
 			Err(e) => {
-				if !new_reg && e.is_acme_err(AcmeError::AccountDoesNotExist) {
-					drop(data_builder);
-					account_s
-						.write()
-						.await
-						.register(&mut *(endpoint_s.write().await))
-						.await?;
-					new_reg = true;
-				} else {
-					return Err(HttpError::in_err(e));
-				}
+				validation_results.push(Err(e));
+				continue;
 			}
 		};
-	};
-
-	// Begin iter over authorizations
-	for auth_url in order.authorizations.iter() {
-		// Fetch the authorization
-		let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
-		let auth =
-			http::get_authorization(&mut *(endpoint_s.write().await), &data_builder, auth_url)
-				.await
-				.map_err(HttpError::in_err)?;
-		drop(data_builder);
-		if let Some(e) = auth.get_error() {
-			cert.warn(&e.prefix("error").message);
-		}
 		if auth.status == AuthorizationStatus::Valid {
+			cert.info(&format!(
+				"{}: authorization validated using the {} challenge",
+				chall.identifier, chall.challenge
+			));
+			record_authorization(account_s, endpoint_name, &chall.auth_url, &auth).await;
 			continue;
 		}
-		if auth.status != AuthorizationStatus::Pending {
-			let msg = format!(
-				"{}: authorization status is {}",
-				auth.identifier, auth.status
-			);
-			return Err(msg.into());
+		failed_challenges
+			.entry(chall.identifier.to_owned())
+			.or_default()
+			.push(chall.challenge);
+		let msg = format!(
+			"{}: authorization status is {} using the {} challenge",
+			chall.identifier, auth.status, chall.challenge
+		);
+		let err = match auth.get_error() {
+			Some(e) => e.prefix(&msg),
+			None => msg.into(),
+		};
+		validation_results.push(Err(err));
+	}
+	first_error(cert, validation_results)
+}
+
+/// Select the challenge to use for a pending authorization and compute its
+/// proof.
+async fn select_challenge(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	auth_url: &str,
+	auth: &Authorization,
+	failed_challenges: &HashMap<String, Vec<Challenge>>,
+) -> Result<PendingChallenge, Error> {
+	let current_identifier = cert.get_identifier_from_str(&auth.identifier.value)?;
+	let excluded = failed_challenges
+		.get(&auth.identifier.value)
+		.map(Vec::as_slice)
+		.unwrap_or_default();
+	let current_challenge = current_identifier
+		.select_challenge(excluded, |c| auth.challenges.iter().any(|ac| c == ac))
+		.ok_or_else(|| {
+			Error::from(format!(
+				"{}: none of the remaining challenges is offered by the server",
+				auth.identifier
+			))
+		})?;
+	let challenge = auth
+		.challenges
+		.iter()
+		.find(|ac| current_challenge == **ac)
+		.ok_or_else(|| Error::from("challenge not found"))?;
+	let (proof, raw_proof) = challenge.get_proof(&account_s.read().await.current_key.key)?;
+	Ok(PendingChallenge {
+		auth_url: auth_url.to_owned(),
+		challenge: current_challenge,
+		identifier: auth.identifier.value.to_owned(),
+		file_name: challenge.get_file_name(),
+		url: challenge.get_url(),
+		proof,
+		raw_proof,
+	})
+}
+
+/// Keep track of a valid authorization in the account. The account has to
+/// be saved afterward.
+async fn record_authorization(
+	account_s: &AccountSync,
+	endpoint_name: &str,
+	auth_url: &str,
+	auth: &Authorization,
+) {
+	let expires = match auth.expires.as_deref().map(parse_rfc3339) {
+		Some(Ok(t)) => t,
+		Some(Err(e)) => {
+			account_s.read().await.debug(&e.message);
+			return;
 		}
+		None => return,
+	};
+	account_s
+		.write()
+		.await
+		.set_authorization(AccountAuthorization {
+			endpoint: endpoint_name.to_string(),
+			identifier_type: auth.identifier.id_type.to_string(),
+			identifier: auth.identifier_value(),
+			url: auth_url.to_string(),
+			expires,
+		});
+}
 
-		// Fetch the associated challenges
-		let current_identifier = cert.get_identifier_from_str(&auth.identifier.value)?;
-		let current_challenge = current_identifier.challenge;
-		for challenge in auth.challenges.iter() {
-			if current_challenge == *challenge {
-				let (proof, raw_proof) =
-					challenge.get_proof(&account_s.read().await.current_key.key)?;
-				let file_name = challenge.get_file_name();
-				let identifier = auth.identifier.value.to_owned();
-
-				// Call the challenge hook in order to complete it
-				let mut data = cert
-					.call_challenge_hooks(&file_name, &proof, raw_proof, &identifier)
-					.await?;
+async fn save_account(account_s: &AccountSync) {
+	let account = account_s.read().await;
+	if let Err(e) = account.save().await {
+		account.warn(&e.message);
+	}
+}
+
+/// Select a new signature algorithm if the server rejected the current one.
+/// Return whether or not the request should be sent again.
+async fn renegotiate_signature_algorithm(
+	account_s: &AccountSync,
+	endpoint_name: &str,
+	error: &HttpError,
+) -> Result<bool, Error> {
+	let has_changed = account::negotiate_signature_algorithm(
+		&mut *(account_s.write().await),
+		endpoint_name,
+		error,
+	)?;
+	if has_changed {
+		save_account(account_s).await;
+	}
+	Ok(has_changed)
+}
+
+/// Call the challenge hooks, validate the authorizations and then clean
+/// every challenge that has been set up, whether or not the validation
+/// succeeded.
+async fn validate_challenges(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	endpoint_name: &str,
+	pending_challenges: &[PendingChallenge],
+	failed_challenges: &mut HashMap<String, Vec<Challenge>>,
+) -> Result<(), Error> {
+	// Call the challenge hooks of every pending authorization at once
+	let results = join_all(
+		pending_challenges
+			.iter()
+			.map(|chall| set_up_challenge(cert, account_s, chall)),
+	)
+	.await;
+	let mut hook_datas = vec![];
+	let mut dns_records = vec![];
+	let mut set_up_results = vec![];
+	for (chall, res) in pending_challenges.iter().zip(results) {
+		match res {
+			Ok((mut data, dns_record_name)) => {
 				data.0.is_clean_hook = true;
 				hook_datas.push(data);
-
-				// Tell the server the challenge has been completed
-				let chall_url = challenge.get_url();
-				let data_builder = set_data_builder!(account_s, endpoint_name, b"{}").await;
-				http::post_jose_no_response(
-					&mut *(endpoint_s.write().await),
-					&data_builder,
-					&chall_url,
-				)
-				.await
-				.map_err(HttpError::in_err)?;
-				drop(data_builder);
+				if chall.challenge == Challenge::Dns01 {
+					dns_records.push((dns_record_name, chall.proof.as_str()));
+				}
 			}
+			Err(e) => set_up_results.push(Err(e)),
 		}
+	}
 
-		// Pool the authorization in order to see whether or not it is valid
-		let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
-		let break_fn = |a: &Authorization| a.status == AuthorizationStatus::Valid;
-		let _ = http::pool_authorization(
-			&mut *(endpoint_s.write().await),
-			&data_builder,
-			&break_fn,
-			auth_url,
-		)
+	// Validate the authorizations
+	let res = match first_error(cert, set_up_results) {
+		Ok(_) => {
+			validate_authorizations(
+				cert,
+				account_s,
+				endpoint_s,
+				endpoint_name,
+				pending_challenges,
+				&dns_records,
+				failed_challenges,
+			)
+			.await
+		}
+		Err(e) => Err(e),
+	};
+	// Clean every challenge that has been set up
+	let clean_res =
+		first_error(
+			cert,
+			join_all(hook_datas.iter().map(|(data, hook_type)| {
+				cert.call_challenge_hooks_clean(data, hook_type.to_owned())
+			}))
+			.await,
+		);
+	match (res, clean_res) {
+		(Err(e), Err(clean_err)) => {
+			cert.warn(&clean_err.prefix("clean").message);
+			Err(e)
+		}
+		(Err(e), Ok(_)) | (Ok(_), Err(e)) => Err(e),
+		(Ok(_), Ok(_)) => Ok(()),
+	}
+}
+
+pub async fn refresh_directory(endpoint_s: &EndpointSync) -> Result<(), Error> {
+	http::refresh_directory(&mut *(endpoint_s.write().await))
 		.await
-		.map_err(HttpError::in_err)?;
-		drop(data_builder);
-		for (data, hook_type) in hook_datas.iter() {
-			cert.call_challenge_hooks_clean(data, (*hook_type).to_owned())
-				.await?;
+		.map_err(HttpError::in_err)
+}
+
+pub async fn request_certificate(
+	cert: &Certificate,
+	account_s: AccountSync,
+	endpoint_s: EndpointSync,
+) -> Result<(), Error> {
+	// Each time a challenge fails, a new order is created using the next
+	// challenge available for the affected identifiers.
+	let mut failed_challenges: HashMap<String, Vec<Challenge>> = HashMap::new();
+	loop {
+		let nb_failed: usize = failed_challenges.values().map(Vec::len).sum();
+		let err =
+			match order_certificate(cert, &account_s, &endpoint_s, &mut failed_challenges).await {
+				Ok(_) => return Ok(()),
+				Err(e) => e,
+			};
+		let has_new_failure = failed_challenges.values().map(Vec::len).sum::<usize>() > nb_failed;
+		// A failed challenge invalidates the order, any other error which is
+		// not final may be transient.
+		if err.is_final || has_new_failure {
+			discard_pending_order(cert).await;
+		}
+		let err = err.error;
+		let has_fallback = failed_challenges.iter().all(|(id, excluded)| {
+			cert.get_identifier_from_str(id)
+				.map(|i| i.has_fallback_challenge(excluded))
+				.unwrap_or(false)
+		});
+		if !has_new_failure || !has_fallback {
+			return Err(err);
 		}
-		hook_datas.clear();
+		cert.warn(
+			&err.prefix("validation failed, retrying with the next challenge")
+				.message,
+		);
 	}
-	// End iter over authorizations
+}
 
-	// Pool the order in order to see whether or not it is ready
-	let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
-	let break_fn = |o: &Order| o.status == OrderStatus::Ready;
-	let order = http::pool_order(
-		&mut *(endpoint_s.write().await),
-		&data_builder,
-		&break_fn,
-		&order_url,
-	)
-	.await
-	.map_err(HttpError::in_err)?;
-	drop(data_builder);
+/// Forget the order in progress so that the next attempt creates a new one.
+async fn discard_pending_order(cert: &Certificate) {
+	if let Err(e) = storage::set_pending_order(&cert.file_manager, None).await {
+		cert.warn(&e.prefix("unable to discard the order in progress").message);
+	}
+}
 
-	// Finalize the order by sending the CSR
-	let key_pair = certificate::get_key_pair(cert).await?;
-	let domains: Vec<String> = cert
-		.identifiers
-		.iter()
-		.filter(|e| e.id_type == IdentifierType::Dns)
-		.map(|e| e.value.to_owned())
-		.collect();
-	let ips: Vec<String> = cert
+async fn save_pending_order(cert: &Certificate, pending_order: &PendingOrder) {
+	if let Err(e) = storage::set_pending_order(&cert.file_manager, Some(pending_order)).await {
+		cert.warn(&e.prefix("unable to save the order in progress").message);
+	}
+}
+
+fn has_same_identifiers(cert: &Certificate, order: &Order) -> bool {
+	let mut cert_ids: Vec<String> = cert
 		.identifiers
 		.iter()
-		.filter(|e| e.id_type == IdentifierType::Ip)
-		.map(|e| e.value.to_owned())
+		.map(|id| format!("{}:{}", id.id_type, id.value))
 		.collect();
-	let csr = Csr::new(
-		&key_pair,
-		cert.csr_digest,
-		domains.as_slice(),
-		ips.as_slice(),
-		&cert.subject_attributes,
-	)?;
-	cert.trace(&format!("new CSR:\n{}", csr.to_pem()?));
-	let csr = json!({
-		"csr": csr.to_der_base64()?,
-	});
-	let csr = csr.to_string();
-	let data_builder = set_data_builder!(account_s, endpoint_name, csr.as_bytes()).await;
-	let order = http::finalize_order(
-		&mut *(endpoint_s.write().await),
-		&data_builder,
-		&order.finalize,
-	)
-	.await
-	.map_err(HttpError::in_err)?;
-	drop(data_builder);
-	if let Some(e) = order.get_error() {
-		cert.warn(&e.prefix("error").message);
-	}
+	let mut order_ids: Vec<String> = order.identifiers.iter().map(|id| id.to_string()).collect();
+	cert_ids.sort();
+	order_ids.sort();
+	cert_ids == order_ids
+}
 
-	// Pool the order in order to see whether or not it is valid
+/// Fetch the order left in progress by a previous run, if any, so that it
+/// can be resumed instead of creating a new one.
+async fn get_pending_order(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	endpoint_name: &str,
+) -> Option<(Order, PendingOrder)> {
+	let pending_order = match storage::get_certificate_metadata(&cert.file_manager).await {
+		Ok(metadata) => metadata?.pending_order?,
+		Err(e) => {
+			cert.warn(&e.prefix("unable to load the order in progress").message);
+			return None;
+		}
+	};
+	if pending_order.endpoint != endpoint_name {
+		cert.debug("the order in progress has been created on another endpoint");
+		return None;
+	}
+	if pending_order.settings != cert.get_order_settings() {
+		cert.debug("the order in progress has been created using different settings");
+		return None;
+	}
 	let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
-	let break_fn = |o: &Order| o.status == OrderStatus::Valid;
-	let order = http::pool_order(
+	let res = http::get_order(
 		&mut *(endpoint_s.write().await),
 		&data_builder,
-		&break_fn,
-		&order_url,
+		&pending_order.url,
 	)
-	.await
-	.map_err(HttpError::in_err)?;
+	.await;
 	drop(data_builder);
+	let order = match res {
+		Ok(order) => order,
+		Err(e) => {
+			let e = HttpError::in_err(e);
+			cert.debug(&e.prefix("unable to fetch the order in progress").message);
+			return None;
+		}
+	};
+	if order.status == OrderStatus::Invalid {
+		cert.debug("the order in progress is invalid");
+		return None;
+	}
+	if !has_same_identifiers(cert, &order) {
+		cert.debug("the order in progress does not have the required identifiers");
+		return None;
+	}
+	cert.info(&format!(
+		"resuming the order in progress (status: {})",
+		order.status
+	));
+	Some((order, pending_order))
+}
+
+async fn order_certificate(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	failed_challenges: &mut HashMap<String, Vec<Challenge>>,
+) -> Result<(), OrderError> {
+	let endpoint_name = endpoint_s.read().await.name.clone();
+
+	// Refresh the directory
+	http::refresh_directory(&mut *(endpoint_s.write().await)).await?;
+	if let Some(profile) = &cert.profile {
+		endpoint_s.read().await.dir.check_profile(profile)?;
+	}
+
+	// Synchronize the account
+	account_s
+		.write()
+		.await
+		.synchronize(&mut *(endpoint_s.write().await))
+		.await?;
+
+	// Resume the order in progress or create a new one
+	let (mut order, mut pending_order) =
+		match get_pending_order(cert, account_s, endpoint_s, &endpoint_name).await {
+			Some(o) => o,
+			None => {
+				let mut new_reg = false;
+				let (order, order_url) = loop {
+					let now = SystemTime::now();
+					let new_order = NewOrder::new(
+						&cert.identifiers,
+						cert.not_before.map(|t| t.resolve(now)),
+						cert.not_after.map(|t| t.resolve(now)),
+						cert.profile.as_deref(),
+					);
+					let new_order = serde_json::to_string(&new_order)?;
+					let data_builder =
+						set_data_builder!(account_s, endpoint_name, new_order.as_bytes()).await;
+					match http::new_order(&mut *(endpoint_s.write().await), &data_builder).await {
+						Ok((order, order_url)) => {
+							if let Some(e) = order.get_error() {
+								cert.warn(&e.prefix("Error").message);
+							}
+							break (order, order_url);
+						}
+						Err(e) => {
+							drop(data_builder);
+							if renegotiate_signature_algorithm(account_s, &endpoint_name, &e)
+								.await?
+							{
+								continue;
+							}
+							if !new_reg && e.is_acme_err(AcmeError::AccountDoesNotExist) {
+								account_s
+									.write()
+									.await
+									.register(&mut *(endpoint_s.write().await))
+									.await?;
+								new_reg = true;
+							} else {
+								return Err(e.into());
+							}
+						}
+					};
+				};
+				let pending_order = PendingOrder {
+					endpoint: endpoint_name.clone(),
+					url: order_url,
+					authorizations: order.authorizations.clone(),
+					private_key: None,
+					settings: cert.get_order_settings(),
+				};
+				save_pending_order(cert, &pending_order).await;
+				(order, pending_order)
+			}
+		};
+	let order_url = pending_order.url.clone();
+
+	if order.status == OrderStatus::Pending {
+		// Fetch the pending authorizations and select their challenges
+		let mut pending_challenges = vec![];
+		for auth_url in order.authorizations.iter() {
+			// Skip the authorizations known to be valid
+			if let Some(auth) = account_s.read().await.get_authorization_by_url(
+				&endpoint_name,
+				auth_url,
+				SystemTime::now(),
+			) {
+				cert.debug(&format!(
+					"{}: reusing a valid authorization",
+					auth.identifier
+				));
+				continue;
+			}
+			let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
+			let auth =
+				http::get_authorization(&mut *(endpoint_s.write().await), &data_builder, auth_url)
+					.await?;
+			drop(data_builder);
+			if let Some(e) = auth.get_error() {
+				cert.warn(&e.prefix("error").message);
+			}
+			if auth.status == AuthorizationStatus::Valid {
+				record_authorization(account_s, &endpoint_name, auth_url, &auth).await;
+				continue;
+			}
+			if auth.status != AuthorizationStatus::Pending {
+				let msg = format!(
+					"{}: authorization status is {}",
+					auth.identifier, auth.status
+				);
+				return Err(OrderError::final_state(msg.into()));
+			}
+			let chall =
+				select_challenge(cert, account_s, auth_url, &auth, failed_challenges).await?;
+			pending_challenges.push(chall);
+		}
+
+		let res = validate_challenges(
+			cert,
+			account_s,
+			endpoint_s,
+			&endpoint_name,
+			&pending_challenges,
+			failed_challenges,
+		)
+		.await;
+		save_account(account_s).await;
+		res?;
+
+		// Pool the order in order to see whether or not it is ready
+		let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
+		let break_fn =
+			|o: &Order| o.status == OrderStatus::Ready || o.status == OrderStatus::Invalid;
+		order = http::pool_order(endpoint_s, &data_builder, &break_fn, &order_url).await?;
+		drop(data_builder);
+		check_order_status(&order)?;
+	}
+
+	if order.status == OrderStatus::Ready {
+		// Finalize the order by sending the CSR
+		let csr = match cert.get_csr().await? {
+			Some(csr) => csr,
+			None => {
+				let key_pair = certificate::get_key_pair(cert, &mut pending_order).await?;
+				save_pending_order(cert, &pending_order).await;
+				let domains: Vec<String> = cert
+					.identifiers
+					.iter()
+					.filter(|e| e.id_type == IdentifierType::Dns)
+					.map(|e| e.value.to_owned())
+					.collect();
+				let ips: Vec<String> = cert
+					.identifiers
+					.iter()
+					.filter(|e| e.id_type == IdentifierType::Ip)
+					.map(|e| e.value.to_owned())
+					.collect();
+				Csr::build(
+					&key_pair,
+					cert.csr_digest,
+					domains.as_slice(),
+					ips.as_slice(),
+					&cert.subject_attributes,
+				)?
+			}
+		};
+		cert.trace(&format!("new CSR:\n{}", csr.to_pem()?));
+		let csr = json!({
+			"csr": csr.to_der_base64()?,
+		});
+		let csr = csr.to_string();
+		let data_builder = set_data_builder!(account_s, endpoint_name, csr.as_bytes()).await;
+		order = http::finalize_order(
+			&mut *(endpoint_s.write().await),
+			&data_builder,
+			&order.finalize,
+		)
+		.await?;
+		drop(data_builder);
+		if let Some(e) = order.get_error() {
+			cert.warn(&e.prefix("error").message);
+		}
+	}
+
+	if order.status != OrderStatus::Valid {
+		// Pool the order in order to see whether or not it is valid
+		let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
+		let break_fn =
+			|o: &Order| o.status == OrderStatus::Valid || o.status == OrderStatus::Invalid;
+		order = http::pool_order(endpoint_s, &data_builder, &break_fn, &order_url).await?;
+		drop(data_builder);
+		check_order_status(&order)?;
+	}
 
 	// Download the certificate
 	let crt_url = order
 		.certificate
 		.ok_or_else(|| Error::from("no certificate available for download"))?;
 	let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
-	let crt = http::get_certificate(&mut *(endpoint_s.write().await), &data_builder, &crt_url)
-		.await
-		.map_err(HttpError::in_err)?;
+	let crt =
+		http::get_certificate(&mut *(endpoint_s.write().await), &data_builder, &crt_url).await?;
 	drop(data_builder);
+	certificate::save_key_pair(cert, &pending_order).await?;
 	storage::write_certificate(&cert.file_manager, crt.as_bytes()).await?;
+	let metadata = storage::CertificateMetadata {
+		profile: cert.profile.clone(),
+		pre_authorized: false,
+		pending_order: None,
+	};
+	if let Err(e) = storage::set_certificate_metadata(&cert.file_manager, &metadata).await {
+		cert.warn(&e.prefix("unable to save the certificate metadata").message);
+	}
 
 	cert.info(&format!(
 		"certificate renewed (identifiers: {})",
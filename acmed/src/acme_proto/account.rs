@@ -1,11 +1,14 @@
 use crate::account::Account as BaseAccount;
 use crate::acme_proto::http;
-use crate::acme_proto::structs::{Account, AccountKeyRollover, AccountUpdate, AcmeError};
+use crate::acme_proto::structs::{
+	Account, AccountKeyRollover, AccountResponse, AccountUpdate, AcmeError,
+};
 use crate::endpoint::Endpoint;
 use crate::http::HttpError;
 use crate::jws::{encode_jwk, encode_kid};
 use crate::logs::HasLogger;
 use crate::set_data_builder_sync;
+use acme_common::crypto::{JwsSignatureAlgorithm, KeyPair, KeyType};
 use acme_common::error::Error;
 
 macro_rules! create_account_if_does_not_exist {
@@ -30,6 +33,65 @@ macro_rules! create_account_if_does_not_exist {
 	};
 }
 
+/// Return the first signature algorithm supported by the server that can be
+/// used with the given key type.
+fn select_signature_algorithm(
+	key_type: &KeyType,
+	algorithms: &[String],
+) -> Option<JwsSignatureAlgorithm> {
+	algorithms
+		.iter()
+		.filter_map(|a| a.parse::<JwsSignatureAlgorithm>().ok())
+		.find(|a| key_type.check_alg_compatibility(a).is_ok())
+}
+
+/// If the server rejected the signature algorithm (RFC 8555, section 6.2),
+/// select one of the algorithms it supports that can be used with the
+/// account's current key and use it for every subsequent request. Return
+/// whether or not the request should be sent again.
+pub fn negotiate_signature_algorithm(
+	account: &mut BaseAccount,
+	endpoint_name: &str,
+	error: &HttpError,
+) -> Result<bool, Error> {
+	let api_error = match error {
+		HttpError::ApiError(e) if e.get_acme_type() == AcmeError::BadSignatureAlgorithm => e,
+		_ => {
+			return Ok(false);
+		}
+	};
+	let key_type = account.current_key.key.key_type;
+	let current_alg = account.get_signature_algorithm(endpoint_name, &account.current_key)?;
+	let new_alg = match select_signature_algorithm(&key_type, &api_error.get_algorithms()) {
+		Some(alg) if alg != current_alg => alg,
+		_ => {
+			return Err(api_error.to_string().into());
+		}
+	};
+	let msg = format!("endpoint \"{endpoint_name}\" does not support the {current_alg} signature algorithm, using {new_alg} instead");
+	account.info(&msg);
+	account.set_signature_algorithm(endpoint_name, new_alg)?;
+	Ok(true)
+}
+
+async fn new_account(
+	endpoint: &mut Endpoint,
+	key: &KeyPair,
+	signature_algorithm: &JwsSignatureAlgorithm,
+	account_struct: &str,
+) -> Result<(AccountResponse, String), HttpError> {
+	let data_builder = |n: &str, url: &str| {
+		encode_jwk(
+			key,
+			signature_algorithm,
+			account_struct.as_bytes(),
+			url,
+			Some(n.to_string()),
+		)
+	};
+	http::new_account(endpoint, &data_builder).await
+}
+
 pub async fn register_account(
 	endpoint: &mut Endpoint,
 	account: &mut BaseAccount,
@@ -40,21 +102,16 @@ pub async fn register_account(
 	));
 	let account_struct = Account::new(account, endpoint)?;
 	let account_struct = serde_json::to_string(&account_struct)?;
-	let acc_ref = &account_struct;
-	let kp_ref = &account.current_key.key;
-	let signature_algorithm = &account.current_key.signature_algorithm;
-	let data_builder = |n: &str, url: &str| {
-		encode_jwk(
-			kp_ref,
-			signature_algorithm,
-			acc_ref.as_bytes(),
-			url,
-			Some(n.to_string()),
-		)
+	let endpoint_name = endpoint.name.clone();
+	let key = account.current_key.key.clone();
+	let (acc_rep, account_url) = loop {
+		let signature_algorithm =
+			account.get_signature_algorithm(&endpoint_name, &account.current_key)?;
+		match new_account(endpoint, &key, &signature_algorithm, &account_struct).await {
+			Err(e) if negotiate_signature_algorithm(account, &endpoint_name, &e)? => {}
+			res => break res.map_err(HttpError::in_err)?,
+		}
 	};
-	let (acc_rep, account_url) = http::new_account(endpoint, &data_builder)
-		.await
-		.map_err(HttpError::in_err)?;
 	account.set_account_url(&endpoint.name, &account_url)?;
 	let orders_url = match acc_rep.orders {
 		Some(url) => url,
@@ -77,6 +134,7 @@ pub async fn register_account(
 		"account created on endpoint \"{}\"",
 		&endpoint.name
 	));
+	account.call_account_created_hooks(&endpoint.name).await;
 	Ok(())
 }
 
@@ -91,15 +149,17 @@ pub async fn update_account_contacts(
 	let new_contacts: Vec<String> = account.contacts.iter().map(|c| c.to_string()).collect();
 	let acc_up_struct = AccountUpdate::new(&new_contacts);
 	let acc_up_struct = serde_json::to_string(&acc_up_struct)?;
-	let account_owned = account.clone();
-	let data_builder =
-		set_data_builder_sync!(account_owned, endpoint_name, acc_up_struct.as_bytes());
+	let acc_up_struct = acc_up_struct.as_bytes();
 	let url = account.get_endpoint(&endpoint_name)?.account_url.clone();
-	create_account_if_does_not_exist!(
-		http::post_jose_no_response(endpoint, &data_builder, &url).await,
-		endpoint,
-		account
-	)?;
+	let res = loop {
+		let account_owned = account.clone();
+		let data_builder = set_data_builder_sync!(account_owned, endpoint_name, acc_up_struct);
+		match http::post_jose_no_response(endpoint, &data_builder, &url).await {
+			Err(e) if negotiate_signature_algorithm(account, &endpoint_name, &e)? => {}
+			res => break res,
+		}
+	};
+	create_account_if_does_not_exist!(res, endpoint, account)?;
 	account.update_contacts_hash(&endpoint_name)?;
 	account.save().await?;
 	account.info(&format!(
@@ -118,37 +178,72 @@ pub async fn update_account_key(
 	));
 	let url = endpoint.dir.key_change.clone();
 	let ep = account.get_endpoint(&endpoint_name)?;
-	let old_account_key = account.get_past_key(&ep.key_hash)?;
+	let old_account_key = account.get_past_key(&ep.key_hash)?.clone();
 	let old_key = &old_account_key.key;
 	let account_url = account.get_endpoint(&endpoint_name)?.account_url.clone();
 	let rollover_struct = AccountKeyRollover::new(&account_url, old_key)?;
 	let rollover_struct = serde_json::to_string(&rollover_struct)?;
-	let rollover_payload = encode_jwk(
-		&account.current_key.key,
-		&account.current_key.signature_algorithm,
-		rollover_struct.as_bytes(),
-		&url,
-		None,
-	)?;
-	let data_builder = |n: &str, url: &str| {
-		encode_kid(
-			old_key,
-			&old_account_key.signature_algorithm,
-			&account_url,
-			rollover_payload.as_bytes(),
-			url,
-			n,
-		)
+	let res = loop {
+		let old_signature_algorithm =
+			account.get_signature_algorithm(&endpoint_name, &old_account_key)?;
+		let new_signature_algorithm =
+			account.get_signature_algorithm(&endpoint_name, &account.current_key)?;
+		let rollover_payload = encode_jwk(
+			&account.current_key.key,
+			&new_signature_algorithm,
+			rollover_struct.as_bytes(),
+			&url,
+			None,
+		)?;
+		let data_builder = |n: &str, url: &str| {
+			encode_kid(
+				old_key,
+				&old_signature_algorithm,
+				&account_url,
+				rollover_payload.as_bytes(),
+				url,
+				n,
+			)
+		};
+		match http::post_jose_no_response(endpoint, &data_builder, &url).await {
+			Err(e) if negotiate_signature_algorithm(account, &endpoint_name, &e)? => {}
+			res => break res,
+		}
 	};
-	create_account_if_does_not_exist!(
-		http::post_jose_no_response(endpoint, &data_builder, &url).await,
-		endpoint,
-		account
-	)?;
+	create_account_if_does_not_exist!(res, endpoint, account)?;
 	account.update_key_hash(&endpoint_name)?;
 	account.save().await?;
 	account.info(&format!(
 		"account key updated on endpoint \"{endpoint_name}\""
 	));
+	account
+		.call_account_key_rolled_hooks(&endpoint_name, &old_account_key)
+		.await;
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::select_signature_algorithm;
+	use acme_common::crypto::{JwsSignatureAlgorithm, KeyType};
+
+	#[test]
+	fn test_select_signature_algorithm() {
+		let algs = vec![
+			"ES256".to_string(),
+			"unknown".to_string(),
+			"PS384".to_string(),
+			"RS256".to_string(),
+		];
+		assert_eq!(
+			select_signature_algorithm(&KeyType::Rsa2048, &algs),
+			Some(JwsSignatureAlgorithm::Ps384)
+		);
+		assert_eq!(
+			select_signature_algorithm(&KeyType::EcdsaP256, &algs),
+			Some(JwsSignatureAlgorithm::Es256)
+		);
+		assert_eq!(select_signature_algorithm(&KeyType::EcdsaP384, &algs), None);
+		assert_eq!(select_signature_algorithm(&KeyType::Rsa4096, &[]), None);
+	}
+}
@@ -0,0 +1,196 @@
+use crate::acme_proto::structs::{ApiError, AuthorizationStatus, NewAuthorization};
+use crate::acme_proto::{
+	http, record_authorization, renegotiate_signature_algorithm, save_account, select_challenge,
+	validate_challenges,
+};
+use crate::certificate::Certificate;
+use crate::http::HttpError;
+use crate::jws::encode_kid;
+use crate::logs::HasLogger;
+use crate::{set_data_builder, set_data_builder_sync};
+use crate::{AccountSync, EndpointSync};
+use acme_common::error::Error;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::SystemTime;
+
+/// Pre-authorize the identifiers of a certificate using the newAuthz
+/// resource (RFC 8555, section 7.4.1) so that the authorizations are
+/// already valid when the certificate is ordered. Identifiers having an
+/// authorization that is still valid at the given time are skipped.
+pub async fn pre_authorize(
+	cert: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	valid_until: SystemTime,
+) -> Result<(), Error> {
+	let endpoint_name = endpoint_s.read().await.name.clone();
+
+	// Refresh the directory
+	http::refresh_directory(&mut *(endpoint_s.write().await))
+		.await
+		.map_err(HttpError::in_err)?;
+	if endpoint_s.read().await.dir.new_authz.is_none() {
+		cert.debug("the server does not support pre-authorization");
+		return Ok(());
+	}
+
+	// Synchronize the account
+	account_s
+		.write()
+		.await
+		.synchronize(&mut *(endpoint_s.write().await))
+		.await?;
+
+	// Create the new authorizations
+	let mut pending_challenges = vec![];
+	let mut has_valid_auth = false;
+	for identifier in cert.identifiers.iter() {
+		// Wildcard identifiers cannot be pre-authorized
+		if identifier.value.starts_with("*.") {
+			continue;
+		}
+		let is_authorized = account_s
+			.read()
+			.await
+			.get_authorization(
+				&endpoint_name,
+				&identifier.id_type.to_string(),
+				&identifier.value,
+				valid_until,
+			)
+			.is_some();
+		if is_authorized {
+			cert.debug(&format!("{}: already authorized", identifier.value));
+			continue;
+		}
+		let new_authz = NewAuthorization::new(identifier);
+		let new_authz = serde_json::to_string(&new_authz)?;
+		let new_authz = new_authz.as_bytes();
+		let (auth, auth_url) = loop {
+			let data_builder = set_data_builder!(account_s, endpoint_name, new_authz).await;
+			let res =
+				http::new_authorization(&mut *(endpoint_s.write().await), &data_builder).await;
+			drop(data_builder);
+			match res {
+				Err(e)
+					if renegotiate_signature_algorithm(account_s, &endpoint_name, &e).await? => {}
+				res => break res.map_err(HttpError::in_err)?,
+			}
+		};
+		if let Some(e) = auth.get_error() {
+			cert.warn(&e.prefix("error").message);
+		}
+		match auth.status {
+			AuthorizationStatus::Valid => {
+				record_authorization(account_s, &endpoint_name, &auth_url, &auth).await;
+				has_valid_auth = true;
+			}
+			AuthorizationStatus::Pending => {
+				let chall =
+					select_challenge(cert, account_s, &auth_url, &auth, &HashMap::new()).await?;
+				pending_challenges.push(chall);
+			}
+			_ => {
+				let msg = format!(
+					"{}: authorization status is {}",
+					auth.identifier, auth.status
+				);
+				return Err(msg.into());
+			}
+		};
+	}
+	if pending_challenges.is_empty() {
+		if has_valid_auth {
+			save_account(account_s).await;
+		}
+		return Ok(());
+	}
+
+	// Validate the pending authorizations
+	let res = validate_challenges(
+		cert,
+		account_s,
+		endpoint_s,
+		&endpoint_name,
+		&pending_challenges,
+		&mut HashMap::new(),
+	)
+	.await;
+	save_account(account_s).await;
+	res?;
+	cert.info("identifiers pre-authorized");
+	Ok(())
+}
+
+/// Deactivate an authorization of an order in progress if it is related to
+/// one of the given identifiers and has not already been deactivated.
+/// Return the identifier of the deactivated authorization, if any. The
+/// account has to be saved afterward.
+pub async fn deactivate_order_authorization(
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	url: &str,
+	identifiers: &[String],
+) -> Result<Option<String>, Error> {
+	let endpoint_name = endpoint_s.read().await.name.clone();
+	http::refresh_directory(&mut *(endpoint_s.write().await))
+		.await
+		.map_err(HttpError::in_err)?;
+	let data_builder = set_data_builder!(account_s, endpoint_name, b"").await;
+	let auth = http::get_authorization(&mut *(endpoint_s.write().await), &data_builder, url)
+		.await
+		.map_err(HttpError::in_err)?;
+	drop(data_builder);
+	let identifier = auth.identifier_value();
+	let is_active = matches!(
+		auth.status,
+		AuthorizationStatus::Pending | AuthorizationStatus::Valid
+	);
+	if !is_active || !identifiers.contains(&identifier) {
+		return Ok(None);
+	}
+	deactivate_authorization(account_s, endpoint_s, url).await?;
+	Ok(Some(identifier))
+}
+
+/// Deactivate an authorization (RFC 8555, section 7.5.2) and stop tracking
+/// it. The account has to be saved afterward.
+pub async fn deactivate_authorization(
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	url: &str,
+) -> Result<(), Error> {
+	let endpoint_name = endpoint_s.read().await.name.clone();
+	http::refresh_directory(&mut *(endpoint_s.write().await))
+		.await
+		.map_err(HttpError::in_err)?;
+	let payload = json!({
+		"status": "deactivated",
+	});
+	let payload = payload.to_string();
+	let payload = payload.as_bytes();
+	let auth = loop {
+		let data_builder = set_data_builder!(account_s, endpoint_name, payload).await;
+		let res =
+			http::deactivate_authorization(&mut *(endpoint_s.write().await), &data_builder, url)
+				.await;
+		drop(data_builder);
+		match res {
+			Err(e) if renegotiate_signature_algorithm(account_s, &endpoint_name, &e).await? => {}
+			res => break res.map_err(HttpError::in_err)?,
+		}
+	};
+	if auth.status != AuthorizationStatus::Deactivated {
+		let msg = format!(
+			"{}: authorization status is {}",
+			auth.identifier, auth.status
+		);
+		return Err(msg.into());
+	}
+	account_s
+		.write()
+		.await
+		.remove_authorization(&endpoint_name, url);
+	Ok(())
+}
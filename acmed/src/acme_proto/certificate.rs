@@ -1,11 +1,15 @@
 use crate::certificate::Certificate;
-use crate::storage;
+use crate::logs::HasLogger;
+use crate::storage::{self, PendingOrder};
 use acme_common::crypto::{gen_keypair, KeyPair};
 use acme_common::error::Error;
+use std::path::Path;
 
-async fn gen_key_pair(cert: &Certificate) -> Result<KeyPair, Error> {
+fn gen_key_pair(cert: &Certificate, pending_order: &mut PendingOrder) -> Result<KeyPair, Error> {
 	let key_pair = gen_keypair(cert.key_type)?;
-	storage::set_keypair(&cert.file_manager, &key_pair).await?;
+	let pem = storage::private_key_to_pem(&cert.file_manager, &key_pair)?;
+	let pem = String::from_utf8(pem)?;
+	pending_order.private_key = Some(pem);
 	Ok(key_pair)
 }
 
@@ -13,13 +17,53 @@ async fn read_key_pair(cert: &Certificate) -> Result<KeyPair, Error> {
 	storage::get_keypair(&cert.file_manager).await
 }
 
-pub async fn get_key_pair(cert: &Certificate) -> Result<KeyPair, Error> {
+/// Return the key pair to use for the order in progress. A newly generated
+/// key pair is kept in the pending order and is only saved once the
+/// certificate has been issued. A key pair which is not managed by ACMEd is
+/// always used as is.
+pub async fn get_key_pair(
+	cert: &Certificate,
+	pending_order: &mut PendingOrder,
+) -> Result<KeyPair, Error> {
+	if let Some(key_pair) = &cert.key_pair {
+		return Ok(key_pair.to_owned());
+	}
+	if let Some(path) = &cert.key_file {
+		let key_pair = storage::get_external_keypair(&cert.file_manager, Path::new(path)).await?;
+		if key_pair.key_type != cert.key_type {
+			let msg = format!(
+				"{path}: the key type is set to {} but the key is a {} key",
+				cert.key_type, key_pair.key_type
+			);
+			return Err(msg.into());
+		}
+		return Ok(key_pair);
+	}
+	if let Some(pem) = &pending_order.private_key {
+		return storage::private_key_from_pem(&cert.file_manager, pem.as_bytes());
+	}
 	if cert.kp_reuse {
 		match read_key_pair(cert).await {
-			Ok(key_pair) => Ok(key_pair),
-			Err(_) => gen_key_pair(cert).await,
+			Ok(key_pair) if key_pair.key_type == cert.key_type => Ok(key_pair),
+			Ok(key_pair) => {
+				cert.info(&format!(
+					"the existing {} key pair does not match the requested {} key type and is therefore replaced",
+					key_pair.key_type, cert.key_type
+				));
+				gen_key_pair(cert, pending_order)
+			}
+			Err(_) => gen_key_pair(cert, pending_order),
 		}
 	} else {
-		gen_key_pair(cert).await
+		gen_key_pair(cert, pending_order)
+	}
+}
+
+/// Save the key pair generated for the order in progress, if any.
+pub async fn save_key_pair(cert: &Certificate, pending_order: &PendingOrder) -> Result<(), Error> {
+	if let Some(pem) = &pending_order.private_key {
+		let key_pair = storage::private_key_from_pem(&cert.file_manager, pem.as_bytes())?;
+		storage::set_keypair(&cert.file_manager, &key_pair).await?;
 	}
+	Ok(())
 }
@@ -1,14 +1,19 @@
 use crate::acme_proto::structs::{AccountResponse, Authorization, Directory, Order};
 use crate::endpoint::Endpoint;
 use crate::http;
+use crate::EndpointSync;
 use acme_common::error::Error;
-use std::{thread, time};
+use std::time::Duration;
+use tokio::time::sleep;
 
+// The endpoint is only locked during each request so that several objects
+// can be pooled at the same time.
 macro_rules! pool_object {
-	($obj_type: ty, $obj_name: expr, $endpoint: expr, $url: expr, $data_builder: expr, $break: expr) => {{
+	($obj_type: ty, $obj_name: expr, $endpoint_s: expr, $url: expr, $data_builder: expr, $break: expr) => {{
 		for _ in 0..crate::DEFAULT_POOL_NB_TRIES {
-			thread::sleep(time::Duration::from_secs(crate::DEFAULT_POOL_WAIT_SEC));
-			let response = http::post_jose($endpoint, $url, $data_builder).await?;
+			sleep(Duration::from_secs(crate::DEFAULT_POOL_WAIT_SEC)).await;
+			let response =
+				http::post_jose(&mut *($endpoint_s.write().await), $url, $data_builder).await?;
 			let obj = response.json::<$obj_type>()?;
 			if $break(&obj) {
 				return Ok(obj);
@@ -70,6 +75,26 @@ where
 	Ok((order_resp, order_uri))
 }
 
+pub async fn new_authorization<F>(
+	endpoint: &mut Endpoint,
+	data_builder: &F,
+) -> Result<(Authorization, String), http::HttpError>
+where
+	F: Fn(&str, &str) -> Result<String, Error>,
+{
+	let url = endpoint
+		.dir
+		.new_authz
+		.clone()
+		.ok_or_else(|| Error::from("the server does not support pre-authorization"))?;
+	let response = http::post_jose(endpoint, &url, data_builder).await?;
+	let auth_uri = response
+		.get_header(http::HEADER_LOCATION)
+		.ok_or_else(|| Error::from("no authorization location found"))?;
+	let auth = response.json::<Authorization>()?;
+	Ok((auth, auth_uri))
+}
+
 pub async fn get_authorization<F>(
 	endpoint: &mut Endpoint,
 	data_builder: &F,
@@ -83,9 +108,35 @@ where
 	Ok(auth)
 }
 
-pub async fn pool_authorization<F, S>(
+pub async fn deactivate_authorization<F>(
 	endpoint: &mut Endpoint,
 	data_builder: &F,
+	url: &str,
+) -> Result<Authorization, http::HttpError>
+where
+	F: Fn(&str, &str) -> Result<String, Error>,
+{
+	let response = http::post_jose(endpoint, url, data_builder).await?;
+	let auth = response.json::<Authorization>()?;
+	Ok(auth)
+}
+
+pub async fn get_order<F>(
+	endpoint: &mut Endpoint,
+	data_builder: &F,
+	url: &str,
+) -> Result<Order, http::HttpError>
+where
+	F: Fn(&str, &str) -> Result<String, Error>,
+{
+	let response = http::post_jose(endpoint, url, data_builder).await?;
+	let order = response.json::<Order>()?;
+	Ok(order)
+}
+
+pub async fn pool_authorization<F, S>(
+	endpoint_s: &EndpointSync,
+	data_builder: &F,
 	break_fn: &S,
 	url: &str,
 ) -> Result<Authorization, http::HttpError>
@@ -96,7 +147,7 @@ where
 	pool_object!(
 		Authorization,
 		"authorization",
-		endpoint,
+		endpoint_s,
 		url,
 		data_builder,
 		break_fn
@@ -104,7 +155,7 @@ where
 }
 
 pub async fn pool_order<F, S>(
-	endpoint: &mut Endpoint,
+	endpoint_s: &EndpointSync,
 	data_builder: &F,
 	break_fn: &S,
 	url: &str,
@@ -113,7 +164,7 @@ where
 	F: Fn(&str, &str) -> Result<String, Error>,
 	S: Fn(&Order) -> bool,
 {
-	pool_object!(Order, "order", endpoint, url, data_builder, break_fn)
+	pool_object!(Order, "order", endpoint_s, url, data_builder, break_fn)
 }
 
 pub async fn finalize_order<F>(
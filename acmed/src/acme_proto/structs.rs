@@ -22,7 +22,7 @@ mod order;
 pub use account::{
 	Account, AccountDeactivation, AccountKeyRollover, AccountResponse, AccountUpdate,
 };
-pub use authorization::{Authorization, AuthorizationStatus, Challenge};
+pub use authorization::{Authorization, AuthorizationStatus, Challenge, NewAuthorization};
 pub use deserialize_from_str;
 pub use directory::Directory;
 pub use error::{AcmeError, ApiError, HttpApiError};
@@ -1,6 +1,6 @@
 use crate::endpoint::Endpoint;
 use crate::jws::encode_kid_mac;
-use acme_common::crypto::KeyPair;
+use acme_common::crypto::Signer;
 use acme_common::error::Error;
 use serde::{Deserialize, Serialize};
 use serde_json::value::Value;
@@ -83,7 +83,7 @@ pub struct AccountKeyRollover {
 }
 
 impl AccountKeyRollover {
-	pub fn new(account_str: &str, old_key: &KeyPair) -> Result<Self, Error> {
+	pub fn new(account_str: &str, old_key: &impl Signer) -> Result<Self, Error> {
 		Ok(AccountKeyRollover {
 			account: account_str.to_string(),
 			old_key: old_key.jwk_public_key()?,
@@ -124,8 +124,8 @@ mod tests {
 			external_account_binding: None,
 		};
 		assert_eq!(a.contact.len(), 2);
-		assert_eq!(a.terms_of_service_agreed, true);
-		assert_eq!(a.only_return_existing, false);
+		assert!(a.terms_of_service_agreed);
+		assert!(!a.only_return_existing);
 		let a_str = serde_json::to_string(&a);
 		assert!(a_str.is_ok());
 		let a_str = a_str.unwrap();
@@ -1,8 +1,9 @@
 use crate::acme_proto::structs::{ApiError, HttpApiError, Identifier};
+use crate::identifier;
 use acme_common::b64_encode;
-use acme_common::crypto::{HashFunction, KeyPair};
+use acme_common::crypto::{HashFunction, Signer};
 use acme_common::error::Error;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
 
@@ -11,17 +12,39 @@ const ID_PE_ACME_ID: usize = 31;
 const DER_OCTET_STRING_ID: usize = 0x04;
 const DER_STRUCT_NAME: &str = "DER";
 
+#[derive(Serialize)]
+pub struct NewAuthorization {
+	pub identifier: Identifier,
+}
+
+impl NewAuthorization {
+	pub fn new(identifier: &identifier::Identifier) -> Self {
+		NewAuthorization {
+			identifier: Identifier::from_generic(identifier),
+		}
+	}
+}
+
 #[derive(Deserialize)]
 pub struct Authorization {
 	pub identifier: Identifier,
 	pub status: AuthorizationStatus,
-	#[allow(dead_code)]
 	pub expires: Option<String>,
 	pub challenges: Vec<Challenge>,
-	#[allow(dead_code)]
 	pub wildcard: Option<bool>,
 }
 
+impl Authorization {
+	/// Return the value of the identifier, prefixed by `*.` for wildcard
+	/// authorizations, as it is specified in the order.
+	pub fn identifier_value(&self) -> String {
+		match self.wildcard {
+			Some(true) => format!("*.{}", self.identifier.value),
+			_ => self.identifier.value.to_owned(),
+		}
+	}
+}
+
 impl FromStr for Authorization {
 	type Err = Error;
 
@@ -94,7 +117,7 @@ impl Challenge {
 		}
 	}
 
-	pub fn get_proof(&self, key_pair: &KeyPair) -> Result<(String, Option<String>), Error> {
+	pub fn get_proof(&self, key_pair: &impl Signer) -> Result<(String, Option<String>), Error> {
 		match self {
 			Challenge::Http01(tc) => {
 				let ka = tc.key_authorization(key_pair)?;
@@ -157,7 +180,7 @@ pub struct TokenChallenge {
 }
 
 impl TokenChallenge {
-	fn key_authorization(&self, key_pair: &KeyPair) -> Result<String, Error> {
+	fn key_authorization(&self, key_pair: &impl Signer) -> Result<String, Error> {
 		let thumbprint = key_pair.jwk_public_key_thumbprint()?;
 		let thumbprint = HashFunction::Sha256.hash(thumbprint.to_string().as_bytes());
 		let thumbprint = b64_encode(&thumbprint);
@@ -196,11 +219,28 @@ mod tests {
 		let a = a.unwrap();
 		assert_eq!(a.status, AuthorizationStatus::Pending);
 		assert!(a.challenges.is_empty());
+		assert_eq!(a.identifier_value(), "example.com".to_string());
 		let i = a.identifier;
 		assert_eq!(i.id_type, IdentifierType::Dns);
 		assert_eq!(i.value, "example.com".to_string());
 	}
 
+	#[test]
+	fn test_wildcard_authorization() {
+		let data = "{
+	\"status\": \"valid\",
+	\"identifier\": {
+		\"type\": \"dns\",
+		\"value\": \"example.com\"
+	},
+	\"challenges\": [],
+	\"wildcard\": true
+}";
+		let a = Authorization::from_str(data).unwrap();
+		assert_eq!(a.status, AuthorizationStatus::Valid);
+		assert_eq!(a.identifier_value(), "*.example.com".to_string());
+	}
+
 	#[test]
 	fn test_authorization_challenge() {
 		let data = "{
@@ -282,10 +322,7 @@ mod tests {
 		let challenge = challenge.unwrap();
 		let c = match challenge {
 			Challenge::Http01(c) => c,
-			_ => {
-				assert!(false);
-				return;
-			}
+			_ => panic!("invalid challenge type"),
 		};
 		assert_eq!(
 			c.url,
@@ -313,10 +350,7 @@ mod tests {
 		let challenge = challenge.unwrap();
 		let c = match challenge {
 			Challenge::Http01(c) => c,
-			_ => {
-				assert!(false);
-				return;
-			}
+			_ => panic!("invalid challenge type"),
 		};
 		assert_eq!(
 			c.url,
@@ -341,9 +375,6 @@ mod tests {
 }";
 		let challenge = Challenge::from_str(data);
 		assert!(challenge.is_ok());
-		match challenge.unwrap() {
-			Challenge::Unknown => assert!(true),
-			_ => assert!(false),
-		}
+		assert!(matches!(challenge.unwrap(), Challenge::Unknown));
 	}
 }
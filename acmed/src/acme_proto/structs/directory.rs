@@ -1,5 +1,6 @@
 use acme_common::error::Error;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::str::FromStr;
 
 #[derive(Clone, Debug, Deserialize)]
@@ -10,23 +11,43 @@ pub struct DirectoryMeta {
 	pub website: Option<String>,
 	pub caa_identities: Option<Vec<String>>,
 	pub external_account_required: Option<bool>,
+	pub profiles: Option<HashMap<String, String>>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Directory {
-	#[allow(dead_code)]
 	pub meta: Option<DirectoryMeta>,
 	pub new_nonce: String,
 	pub new_account: String,
 	pub new_order: String,
-	#[allow(dead_code)]
 	pub new_authz: Option<String>,
 	#[allow(dead_code)]
 	pub revoke_cert: String,
 	pub key_change: String,
 }
 
+impl Directory {
+	/// Check whether or not the server advertises the given certificate
+	/// profile (draft-aaron-acme-profiles).
+	pub fn check_profile(&self, profile: &str) -> Result<(), Error> {
+		let profiles = match self.meta.as_ref().and_then(|m| m.profiles.as_ref()) {
+			Some(p) if !p.is_empty() => p,
+			_ => return Err("the server does not support profiles".into()),
+		};
+		if profiles.contains_key(profile) {
+			return Ok(());
+		}
+		let mut available: Vec<&str> = profiles.keys().map(String::as_str).collect();
+		available.sort_unstable();
+		let msg = format!(
+			"{profile}: unknown profile, the server supports the following ones: {}",
+			available.join(", ")
+		);
+		Err(msg.into())
+	}
+}
+
 deserialize_from_str!(Directory);
 
 #[cfg(test)]
@@ -102,10 +123,11 @@ mod tests {
 	\"newOrder\": \"https://example.org/acme/new-order\",
 	\"revokeCert\": \"https://example.org/acme/revoke-cert\"
 }";
-		let parsed_dir = Directory::from_str(&data);
+		let parsed_dir = Directory::from_str(data);
 		assert!(parsed_dir.is_ok());
 		let parsed_dir = parsed_dir.unwrap();
 		assert!(parsed_dir.meta.is_some());
+		assert!(parsed_dir.check_profile("classic").is_err());
 		let meta = parsed_dir.meta.unwrap();
 		assert_eq!(
 			meta.terms_of_service,
@@ -117,6 +139,34 @@ mod tests {
 		assert_eq!(caa_identities.len(), 1);
 		assert_eq!(caa_identities.first(), Some(&"example.org".to_string()));
 		assert!(meta.external_account_required.is_none());
+		assert!(meta.profiles.is_none());
+	}
+
+	#[test]
+	fn test_directory_profiles() {
+		let data = "{
+	\"keyChange\": \"https://example.org/acme/key-change\",
+	\"meta\": {
+		\"profiles\": {
+			\"classic\": \"The same profile you're accustomed to\",
+			\"tlsserver\": \"https://example.org/docs/tlsserver\"
+		}
+	},
+	\"newAccount\": \"https://example.org/acme/new-acct\",
+	\"newNonce\": \"https://example.org/acme/new-nonce\",
+	\"newOrder\": \"https://example.org/acme/new-order\",
+	\"revokeCert\": \"https://example.org/acme/revoke-cert\"
+}";
+		let parsed_dir = Directory::from_str(data).unwrap();
+		let profiles = parsed_dir.meta.as_ref().unwrap().profiles.as_ref().unwrap();
+		assert_eq!(profiles.len(), 2);
+		assert_eq!(
+			profiles.get("tlsserver"),
+			Some(&"https://example.org/docs/tlsserver".to_string())
+		);
+		assert!(parsed_dir.check_profile("classic").is_ok());
+		assert!(parsed_dir.check_profile("tlsserver").is_ok());
+		assert!(parsed_dir.check_profile("shortlived").is_err());
 	}
 
 	#[test]
@@ -131,7 +181,7 @@ mod tests {
 	\"newOrder\": \"https://example.org/acme/new-order\",
 	\"revokeCert\": \"https://example.org/acme/revoke-cert\"
 }";
-		let parsed_dir = Directory::from_str(&data);
+		let parsed_dir = Directory::from_str(data);
 		assert!(parsed_dir.is_ok());
 		let parsed_dir = parsed_dir.unwrap();
 		assert_eq!(parsed_dir.new_nonce, "https://example.org/acme/new-nonce");
@@ -136,6 +136,9 @@ pub struct HttpApiError {
 	detail: Option<String>,
 	// instance: Option<String>,
 	// TODO: implement subproblems
+	/// Signature algorithms supported by the server, sent alongside a
+	/// badSignatureAlgorithm error (RFC 8555, section 6.2).
+	algorithms: Option<Vec<String>>,
 }
 
 crate::acme_proto::structs::deserialize_from_str!(HttpApiError);
@@ -164,6 +167,10 @@ impl HttpApiError {
 	pub fn get_acme_type(&self) -> AcmeError {
 		self.get_type().into()
 	}
+
+	pub fn get_algorithms(&self) -> Vec<String> {
+		self.algorithms.to_owned().unwrap_or_default()
+	}
 }
 
 impl From<HttpApiError> for Error {
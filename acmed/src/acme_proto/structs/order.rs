@@ -1,9 +1,11 @@
 use crate::acme_proto::structs::{ApiError, HttpApiError};
 use crate::identifier::{self, IdentifierType};
+use crate::timestamp::format_rfc3339;
 use acme_common::error::Error;
 use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::str::FromStr;
+use std::time::SystemTime;
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -13,14 +15,22 @@ pub struct NewOrder {
 	pub not_before: Option<String>,
 	#[serde(skip_serializing_if = "Option::is_none")]
 	pub not_after: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub profile: Option<String>,
 }
 
 impl NewOrder {
-	pub fn new(identifiers: &[identifier::Identifier]) -> Self {
+	pub fn new(
+		identifiers: &[identifier::Identifier],
+		not_before: Option<SystemTime>,
+		not_after: Option<SystemTime>,
+		profile: Option<&str>,
+	) -> Self {
 		NewOrder {
 			identifiers: identifiers.iter().map(Identifier::from_generic).collect(),
-			not_before: None,
-			not_after: None,
+			not_before: not_before.as_ref().map(format_rfc3339),
+			not_after: not_after.as_ref().map(format_rfc3339),
+			profile: profile.map(str::to_string),
 		}
 	}
 }
@@ -31,7 +41,6 @@ pub struct Order {
 	pub status: OrderStatus,
 	#[allow(dead_code)]
 	pub expires: Option<String>,
-	#[allow(dead_code)]
 	pub identifiers: Vec<Identifier>,
 	#[allow(dead_code)]
 	pub not_before: Option<String>,
@@ -100,8 +109,34 @@ impl fmt::Display for Identifier {
 
 #[cfg(test)]
 mod tests {
-	use super::{Identifier, IdentifierType};
+	use super::{Identifier, IdentifierType, NewOrder};
+	use crate::identifier;
+	use std::collections::HashMap;
 	use std::str::FromStr;
+	use std::time::{Duration, UNIX_EPOCH};
+
+	#[test]
+	fn new_order_serialize() {
+		let id = identifier::Identifier::new(
+			identifier::IdentifierType::Dns,
+			"example.org",
+			&["http-01"],
+			&HashMap::new(),
+		)
+		.unwrap();
+		let ids = vec![id];
+		let order = NewOrder::new(&ids, None, None, None);
+		assert_eq!(
+			serde_json::to_string(&order).unwrap(),
+			"{\"identifiers\":[{\"type\":\"dns\",\"value\":\"example.org\"}]}"
+		);
+		let not_after = UNIX_EPOCH + Duration::from_secs(1_453_298_947);
+		let order = NewOrder::new(&ids, None, Some(not_after), Some("shortlived"));
+		assert_eq!(
+			serde_json::to_string(&order).unwrap(),
+			"{\"identifiers\":[{\"type\":\"dns\",\"value\":\"example.org\"}],\"notAfter\":\"2016-01-20T14:09:07Z\",\"profile\":\"shortlived\"}"
+		);
+	}
 
 	#[test]
 	fn id_serialize() {
@@ -1,14 +1,28 @@
+use crate::acme_dns;
 use crate::acme_proto::Challenge;
-use crate::hooks::{self, ChallengeHookData, Hook, HookEnvData, HookType, PostOperationHookData};
-use crate::identifier::{Identifier, IdentifierType};
+use crate::dns;
+use crate::duration::RenewDelay;
+use crate::hooks::{
+	self, CertificateHookData, ChallengeHookData, ExpirationWarningHookData, Hook, HookEnvData,
+	HookType, PostOperationFailureHookData, PostOperationHookData,
+};
+use crate::identifier::{parse_subject_alt_name, Identifier, IdentifierType};
 use crate::logs::HasLogger;
-use crate::storage::{certificate_files_exists, get_certificate, FileManager};
-use acme_common::crypto::{HashFunction, KeyType, SubjectAttribute, X509Certificate};
+use crate::storage::{
+	certificate_files_exists, get_certificate, get_certificate_metadata, get_external_csr,
+	get_keypair_path, set_certificate_metadata, CertificateMetadata, FileManager, OrderSettings,
+};
+use crate::timestamp::TimeReference;
+use crate::AccountSync;
+use acme_common::crypto::{
+	CertificateInspector, Csr, CsrInspector, HashFunction, KeyPair, KeyType, SubjectAttribute,
+};
 use acme_common::error::Error;
 use log::{debug, info, trace, warn};
 use rand::{rng, Rng};
 use std::collections::{HashMap, HashSet};
 use std::fmt;
+use std::path::PathBuf;
 use std::time::Duration;
 
 #[derive(Clone, Debug)]
@@ -17,6 +31,13 @@ pub struct Certificate {
 	pub identifiers: Vec<Identifier>,
 	pub subject_attributes: HashMap<SubjectAttribute, String>,
 	pub key_type: KeyType,
+	/// Key pair which is not managed by ACMEd, such as a key stored in a
+	/// PKCS #11 token. When set, no private key file is written.
+	pub key_pair: Option<KeyPair>,
+	/// Private key file which is only read by ACMEd.
+	pub key_file: Option<String>,
+	/// Certificate signing request file used as is to finalize the orders.
+	pub csr_file: Option<String>,
 	pub csr_digest: HashFunction,
 	pub kp_reuse: bool,
 	pub endpoint_name: String,
@@ -24,7 +45,16 @@ pub struct Certificate {
 	pub crt_name: String,
 	pub env: HashMap<String, String>,
 	pub random_early_renew: Duration,
-	pub renew_delay: Duration,
+	pub renew_delay: RenewDelay,
+	pub expiration_warning: Duration,
+	pub pre_authorization_delay: Duration,
+	pub pre_operation_retry_delay: Duration,
+	pub not_before: Option<TimeReference>,
+	pub not_after: Option<TimeReference>,
+	pub profile: Option<String>,
+	pub dns_propagation_check: bool,
+	pub dns_propagation_timeout: Duration,
+	pub dns_resolve_delegation: bool,
 	pub file_manager: FileManager,
 }
 
@@ -72,14 +102,110 @@ impl Certificate {
 		Err(format!("{identifier}: identifier not found").into())
 	}
 
-	fn renew_in(&self, cert: &X509Certificate) -> Result<Duration, Error> {
+	/// Return the settings the orders of this certificate depend on.
+	pub fn get_order_settings(&self) -> OrderSettings {
+		let mut identifiers: Vec<String> = self
+			.identifiers
+			.iter()
+			.map(|id| format!("{}:{}", id.id_type, id.value))
+			.collect();
+		identifiers.sort();
+		OrderSettings {
+			identifiers,
+			not_before: self.not_before.map(|t| t.to_string()),
+			not_after: self.not_after.map(|t| t.to_string()),
+			profile: self.profile.clone(),
+			key_type: self.key_type.to_string(),
+			key_uri: self
+				.key_pair
+				.as_ref()
+				.and_then(|k| k.pkcs11_uri())
+				.map(str::to_string),
+			key_file: self.key_file.clone(),
+			csr_file: self.csr_file.clone(),
+		}
+	}
+
+	/// Return whether or not the private key is generated and stored by
+	/// ACMEd.
+	pub fn manages_private_key(&self) -> bool {
+		self.key_pair.is_none() && self.key_file.is_none() && self.csr_file.is_none()
+	}
+
+	pub async fn get_private_key_path(&self) -> Result<PathBuf, Error> {
+		match &self.key_file {
+			Some(path) => Ok(PathBuf::from(path)),
+			None => get_keypair_path(&self.file_manager).await,
+		}
+	}
+
+	/// Read the certificate signing request file, if any, and check it
+	/// matches both the key type and the identifiers.
+	pub async fn get_csr(&self) -> Result<Option<Csr>, Error> {
+		let path = match &self.csr_file {
+			Some(p) => PathBuf::from(p),
+			None => return Ok(None),
+		};
+		let csr = get_external_csr(&self.file_manager, &path).await?;
+		let key_type = csr.key_type()?;
+		if key_type != self.key_type {
+			let msg = format!(
+				"{}: the key type is set to {} but the request uses a {key_type} key",
+				path.display(),
+				self.key_type
+			);
+			return Err(msg.into());
+		}
+		let csr_names = csr
+			.subject_alt_names()?
+			.iter()
+			.map(|v| {
+				parse_subject_alt_name(v)
+					.map(|(_, name)| name)
+					.unwrap_or_else(|_| v.to_lowercase())
+			})
+			.collect::<HashSet<String>>();
+		let req_names = self
+			.identifiers
+			.iter()
+			.map(|v| v.value.to_owned())
+			.collect::<HashSet<String>>();
+		if csr_names != req_names {
+			let mut names = csr_names
+				.symmetric_difference(&req_names)
+				.map(|v| v.as_str())
+				.collect::<Vec<&str>>();
+			names.sort_unstable();
+			let msg = format!(
+				"{}: the subject alternative names of the request do not match the identifiers: {}",
+				path.display(),
+				names.join(", ")
+			);
+			return Err(msg.into());
+		}
+		Ok(Some(csr))
+	}
+
+	fn renew_in(&self, cert: &impl CertificateInspector) -> Result<Duration, Error> {
 		let expires_in = cert.expires_in()?;
+		let lifetime = cert.lifetime()?;
+		let renew_delay = match self.renew_delay.get_delay(lifetime) {
+			Ok(d) => d,
+			Err(e) => {
+				self.warn(&format!(
+					"{}, using {}% of the lifetime instead",
+					e.message,
+					crate::DEFAULT_CERT_RENEW_LIFETIME_PERCENT
+				));
+				RenewDelay::default().get_delay(lifetime)?
+			}
+		};
 		self.debug(&format!(
 			"certificate expires in {} days ({} days delay)",
 			expires_in.as_secs() / 86400,
-			self.renew_delay.as_secs() / 86400,
+			renew_delay.as_secs() / 86400,
 		));
-		let expires_in = expires_in.saturating_sub(self.renew_delay);
+		let expires_in = expires_in.saturating_sub(renew_delay);
 		let expires_in = if !self.random_early_renew.is_zero() {
 			expires_in.saturating_sub(rng().random_range(Duration::ZERO..self.random_early_renew))
 		} else {
@@ -88,7 +214,7 @@ impl Certificate {
 		Ok(expires_in)
 	}
 
-	fn has_missing_identifiers(&self, cert: &X509Certificate) -> bool {
+	fn has_missing_identifiers(&self, cert: &impl CertificateInspector) -> bool {
 		let cert_names = cert.subject_alt_names();
 		let req_names = self
 			.identifiers
@@ -109,12 +235,20 @@ impl Certificate {
 		has_miss
 	}
 
-	/// Return a comma-separated list of the domains this certificate is valid for.
+	fn get_identifier_values(&self) -> Vec<String> {
+		self.identifiers
+			.iter()
+			.map(|d| d.value.to_owned())
+			.collect()
+	}
+
+	/// Return a comma-separated list of the domains this certificate is valid
+	/// for, internationalized domain names being in their Unicode form.
 	pub fn identifier_list(&self) -> String {
 		self.identifiers
 			.iter()
-			.map(|d| d.value.as_str())
-			.collect::<Vec<&str>>()
+			.map(|d| d.unicode_value())
+			.collect::<Vec<String>>()
 			.join(",")
 	}
 
@@ -123,7 +257,7 @@ impl Certificate {
 			"checking for renewal (identifiers: {})",
 			self.identifier_list()
 		));
-		if !certificate_files_exists(&self.file_manager) {
+		if !certificate_files_exists(&self.file_manager, self.manages_private_key()) {
 			self.debug("certificate does not exist: requesting one");
 			return Ok(Duration::ZERO);
 		}
@@ -133,6 +267,32 @@ impl Certificate {
 			self.debug("the current certificate doesn't include all the required identifiers");
 			return Ok(Duration::ZERO);
 		}
+		let metadata = match get_certificate_metadata(&self.file_manager).await? {
+			Some(m) => m,
+			None => {
+				// The profile the certificate has been issued with is unknown,
+				// which is not a reason to renew it.
+				self.debug("the current certificate has no metadata: assuming it has been issued using the current profile");
+				let metadata = CertificateMetadata {
+					profile: self.profile.clone(),
+					pre_authorized: false,
+					pending_order: None,
+				};
+				set_certificate_metadata(&self.file_manager, &metadata).await?;
+				metadata
+			}
+		};
+		if metadata.pending_order.is_some() {
+			self.debug("an order is in progress: resuming it");
+			return Ok(Duration::ZERO);
+		}
+		if metadata.profile != self.profile {
+			self.debug(&format!(
+				"the current certificate has been issued using a different profile ({})",
+				metadata.profile.as_deref().unwrap_or("default")
+			));
+			return Ok(Duration::ZERO);
+		}
 		self.renew_in(&cert)
 	}
 
@@ -142,10 +302,13 @@ impl Certificate {
 		proof: &str,
 		raw_proof: Option<String>,
 		identifier: &str,
+		challenge: Challenge,
+		dns_record_name: &str,
 	) -> Result<(ChallengeHookData, HookType), Error> {
 		let identifier = self.get_identifier_from_str(identifier)?;
 		let mut hook_data = ChallengeHookData {
-			challenge: identifier.challenge.to_string(),
+			challenge: challenge.to_string(),
+			dns_record_name: dns_record_name.to_string(),
 			identifier: identifier.value.to_owned(),
 			identifier_tls_alpn: identifier.get_tls_alpn_name().unwrap_or_default(),
 			file_name: file_name.to_string(),
@@ -156,7 +319,7 @@ impl Certificate {
 		};
 		hook_data.set_env(&self.env);
 		hook_data.set_env(&identifier.env);
-		let hook_type = match identifier.challenge {
+		let hook_type = match challenge {
 			Challenge::Http01 => (HookType::ChallengeHttp01, HookType::ChallengeHttp01Clean),
 			Challenge::Dns01 => (HookType::ChallengeDns01, HookType::ChallengeDns01Clean),
 			Challenge::TlsAlpn01 => (
@@ -168,6 +331,61 @@ impl Certificate {
 		Ok((hook_data, hook_type.1))
 	}
 
+	/// Return the name of the TXT record that has to be set for the dns-01
+	/// challenge, taking any delegation into account. When the identifier
+	/// uses an acme-dns server, the record is set as well.
+	pub async fn set_dns_record(
+		&self,
+		account_s: &AccountSync,
+		identifier: &str,
+		proof: &str,
+	) -> Result<String, Error> {
+		let identifier = self.get_identifier_from_str(identifier)?;
+		let record_name = identifier.get_dns_record_name();
+		if let Some(server) = &identifier.acme_dns {
+			let value = identifier.value.trim_start_matches("*.");
+			let target = acme_dns::set_txt_record(self, account_s, server, value, proof).await?;
+			match dns::resolve_cname(self, &record_name).await {
+				Ok(name) if name.eq_ignore_ascii_case(&target) => {}
+				Ok(_) => self.warn(&format!(
+					"{record_name}: the record is not delegated to {target}"
+				)),
+				Err(e) => self.debug(&e.prefix(&record_name).message),
+			};
+			return Ok(target);
+		}
+		if let Some(target) = &identifier.dns_delegation {
+			self.debug(&format!("{record_name}: delegated to {target}"));
+			return Ok(target.to_owned());
+		}
+		if self.dns_resolve_delegation {
+			match dns::resolve_cname(self, &record_name).await {
+				Ok(target) => {
+					if target != record_name {
+						self.debug(&format!("{record_name}: delegated to {target}"));
+					}
+					return Ok(target);
+				}
+				Err(e) => {
+					self.warn(&e.prefix("unable to resolve the DNS delegation").message);
+				}
+			};
+		}
+		Ok(record_name)
+	}
+
+	pub async fn wait_for_dns_propagation(&self, record_name: &str, proof: &str) {
+		if !self.dns_propagation_check {
+			return;
+		}
+		self.debug(&format!("{record_name}: waiting for DNS propagation"));
+		if let Err(e) =
+			dns::wait_for_propagation(self, record_name, proof, self.dns_propagation_timeout).await
+		{
+			self.warn(&e.prefix("DNS propagation check failed").message);
+		}
+	}
+
 	pub async fn call_challenge_hooks_clean(
 		&self,
 		data: &ChallengeHookData,
@@ -176,27 +394,79 @@ impl Certificate {
 		hooks::call(self, &self.hooks, data, hook_type).await
 	}
 
+	async fn get_hook_data(&self) -> Result<CertificateHookData, Error> {
+		let mut hook_data = CertificateHookData {
+			identifiers: self.get_identifier_values(),
+			key_type: self.key_type.to_string(),
+			certificate_path: crate::storage::get_certificate_path(&self.file_manager).await?,
+			private_key_path: self.get_private_key_path().await?,
+			env: HashMap::new(),
+		};
+		hook_data.set_env(&self.env);
+		Ok(hook_data)
+	}
+
 	pub async fn call_post_operation_hooks(
 		&self,
 		status: &str,
 		is_success: bool,
 	) -> Result<(), Error> {
-		let identifiers = self
-			.identifiers
-			.iter()
-			.map(|d| d.value.to_owned())
-			.collect::<Vec<String>>();
-		let mut hook_data = PostOperationHookData {
-			identifiers,
-			key_type: self.key_type.to_string(),
+		let hook_data = PostOperationHookData {
+			certificate: self.get_hook_data().await?,
 			status: status.to_string(),
 			is_success,
-			certificate_path: crate::storage::get_certificate_path(&self.file_manager).await?,
-			private_key_path: crate::storage::get_keypair_path(&self.file_manager).await?,
-			env: HashMap::new(),
 		};
-		hook_data.set_env(&self.env);
 		hooks::call(self, &self.hooks, &hook_data, HookType::PostOperation).await?;
 		Ok(())
 	}
+
+	pub async fn call_pre_operation_hooks(&self) -> Result<(), Error> {
+		let hook_data = self.get_hook_data().await?;
+		hooks::call(self, &self.hooks, &hook_data, HookType::PreOperation).await
+	}
+
+	pub async fn call_post_operation_success_hooks(&self) -> Result<(), Error> {
+		let hook_data = self.get_hook_data().await?;
+		hooks::call(
+			self,
+			&self.hooks,
+			&hook_data,
+			HookType::PostOperationSuccess,
+		)
+		.await
+	}
+
+	pub async fn call_post_operation_failure_hooks(&self, error: &str) -> Result<(), Error> {
+		let hook_data = PostOperationFailureHookData {
+			certificate: self.get_hook_data().await?,
+			error: error.to_string(),
+		};
+		hooks::call(
+			self,
+			&self.hooks,
+			&hook_data,
+			HookType::PostOperationFailure,
+		)
+		.await
+	}
+
+	/// Call the expiration warning hooks if the current certificate expires
+	/// in less than the configured delay.
+	pub async fn check_expiration(&self) -> Result<(), Error> {
+		if !certificate_files_exists(&self.file_manager, self.manages_private_key()) {
+			return Ok(());
+		}
+		let cert = get_certificate(&self.file_manager).await?;
+		let expires_in = cert.expires_in()?;
+		if expires_in > self.expiration_warning {
+			return Ok(());
+		}
+		let expires_in_days = expires_in.as_secs() / 86400;
+		self.warn(&format!("certificate expires in {expires_in_days} days"));
+		let hook_data = ExpirationWarningHookData {
+			certificate: self.get_hook_data().await?,
+			expires_in_days,
+		};
+		hooks::call(self, &self.hooks, &hook_data, HookType::ExpirationWarning).await
+	}
 }
@@ -1,9 +1,12 @@
-use crate::duration::parse_duration;
+use crate::duration::{parse_duration, parse_renew_delay, RenewDelay};
 use crate::hooks;
-use crate::identifier::IdentifierType;
-use crate::storage::FileManager;
+use crate::identifier::{parse_subject_alt_name, IdentifierType};
+use crate::storage::{FileManager, Passphrase};
+use crate::timestamp::TimeReference;
 use acme_common::b64_decode;
-use acme_common::crypto::{HashFunction, JwsSignatureAlgorithm, KeyType, SubjectAttribute};
+use acme_common::crypto::{
+	Csr, CsrInspector, HashFunction, JwsSignatureAlgorithm, KeyPair, KeyType, SubjectAttribute,
+};
 use acme_common::error::Error;
 use glob::glob;
 use log::info;
@@ -14,6 +17,7 @@ use std::fs::{self, File};
 use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::result::Result;
+use std::str::FromStr;
 use std::time::Duration;
 
 macro_rules! set_cfg_attr {
@@ -57,6 +61,8 @@ pub struct Config {
 	pub global: Option<GlobalOptions>,
 	#[serde(default)]
 	pub endpoint: Vec<Endpoint>,
+	#[serde(default, rename = "acme-dns")]
+	pub acme_dns: Vec<AcmeDns>,
 	#[serde(default, rename = "rate-limit")]
 	pub rate_limit: Vec<RateLimit>,
 	#[serde(default)]
@@ -81,6 +87,15 @@ impl Config {
 		Err(format!("{name}: rate limit not found").into())
 	}
 
+	fn get_acme_dns(&self, name: &str) -> Result<crate::acme_dns::AcmeDnsServer, Error> {
+		for srv in self.acme_dns.iter() {
+			if srv.name == name {
+				return Ok(srv.to_generic(self));
+			}
+		}
+		Err(format!("{name}: acme-dns server not found").into())
+	}
+
 	pub fn get_account_dir(&self) -> String {
 		let account_dir = match &self.global {
 			Some(g) => match &g.accounts_directory {
@@ -98,14 +113,12 @@ impl Config {
 				let h = hooks::Hook {
 					name: hook.name.to_owned(),
 					hook_type: hook.hook_type.iter().map(|e| e.to_owned()).collect(),
-					cmd: hook.cmd.to_owned(),
-					args: hook.args.to_owned(),
-					stdin: get_stdin(hook)?,
-					stdout: hook.stdout.to_owned(),
-					stderr: hook.stderr.to_owned(),
+					kind: hook.get_kind(self)?,
 					allow_failure: hook
 						.allow_failure
 						.unwrap_or(crate::DEFAULT_HOOK_ALLOW_FAILURE),
+					timeout: hook.get_timeout(self)?,
+					parallel_group: None,
 				};
 				return Ok(vec![h]);
 			}
@@ -117,6 +130,11 @@ impl Config {
 					let mut h = self.get_hook(hook_name)?;
 					ret.append(&mut h);
 				}
+				if grp.parallel.unwrap_or(crate::DEFAULT_GROUP_PARALLEL) {
+					for h in ret.iter_mut() {
+						h.parallel_group = Some(grp.name.to_owned());
+					}
+				}
 				return Ok(ret);
 			}
 		}
@@ -184,6 +202,13 @@ impl Config {
 			None => None,
 		}
 	}
+
+	pub fn get_key_passphrase(&self) -> Result<Option<Passphrase>, Error> {
+		match &self.global {
+			Some(g) => g.get_key_passphrase(),
+			None => Ok(None),
+		}
+	}
 }
 
 #[derive(Clone, Deserialize)]
@@ -195,13 +220,23 @@ pub struct GlobalOptions {
 	pub cert_file_user: Option<String>,
 	pub cert_file_ext: Option<String>,
 	pub certificates_directory: Option<String>,
+	pub dns_propagation_check: Option<bool>,
+	pub dns_propagation_timeout: Option<String>,
+	pub dns_resolve_delegation: Option<bool>,
 	#[serde(default)]
 	pub env: HashMap<String, String>,
+	pub expiration_warning: Option<String>,
 	pub file_name_format: Option<String>,
+	pub hook_timeout: Option<String>,
+	pub key_passphrase_credential: Option<String>,
+	pub key_passphrase_env: Option<String>,
+	pub key_passphrase_file: Option<String>,
 	pub pk_file_group: Option<String>,
 	pub pk_file_mode: Option<u32>,
 	pub pk_file_user: Option<String>,
 	pub pk_file_ext: Option<String>,
+	pub pre_authorization_delay: Option<String>,
+	pub pre_operation_retry_delay: Option<String>,
 	pub random_early_renew: Option<String>,
 	pub renew_delay: Option<String>,
 	pub root_certificates: Option<Vec<String>>,
@@ -215,10 +250,13 @@ impl GlobalOptions {
 		}
 	}
 
-	pub fn get_renew_delay(&self) -> Result<Duration, Error> {
+	pub fn get_renew_delay(&self) -> Result<RenewDelay, Error> {
 		match &self.renew_delay {
-			Some(d) => parse_duration(d),
-			None => Ok(Duration::new(crate::DEFAULT_CERT_RENEW_DELAY, 0)),
+			Some(d) => parse_renew_delay(d),
+			None => Ok(RenewDelay::Fixed(Duration::new(
+				crate::DEFAULT_CERT_RENEW_DELAY,
+				0,
+			))),
 		}
 	}
 
@@ -228,6 +266,92 @@ impl GlobalOptions {
 			None => crate::DEFAULT_CERT_FORMAT.to_string(),
 		}
 	}
+
+	pub fn get_dns_propagation_check(&self) -> bool {
+		self.dns_propagation_check
+			.unwrap_or(crate::DEFAULT_DNS_PROPAGATION_CHECK)
+	}
+
+	pub fn get_dns_propagation_timeout(&self) -> Result<Duration, Error> {
+		match &self.dns_propagation_timeout {
+			Some(d) => parse_duration(d),
+			None => Ok(Duration::new(crate::DEFAULT_DNS_PROPAGATION_TIMEOUT, 0)),
+		}
+	}
+
+	pub fn get_expiration_warning(&self) -> Result<Duration, Error> {
+		match &self.expiration_warning {
+			Some(d) => parse_duration(d),
+			None => Ok(Duration::new(crate::DEFAULT_CERT_EXPIRATION_WARNING, 0)),
+		}
+	}
+
+	pub fn get_hook_timeout(&self) -> Result<Duration, Error> {
+		match &self.hook_timeout {
+			Some(d) => parse_duration(d),
+			None => Ok(Duration::new(crate::DEFAULT_HOOK_TIMEOUT, 0)),
+		}
+	}
+
+	pub fn get_pre_authorization_delay(&self) -> Result<Duration, Error> {
+		match &self.pre_authorization_delay {
+			Some(d) => parse_duration(d),
+			None => Ok(Duration::new(crate::DEFAULT_PRE_AUTHORIZATION_DELAY, 0)),
+		}
+	}
+
+	pub fn get_pre_operation_retry_delay(&self) -> Result<Duration, Error> {
+		match &self.pre_operation_retry_delay {
+			Some(d) => parse_duration(d),
+			None => Ok(Duration::new(crate::DEFAULT_PRE_OPERATION_RETRY_DELAY, 0)),
+		}
+	}
+
+	pub fn get_dns_resolve_delegation(&self) -> bool {
+		self.dns_resolve_delegation
+			.unwrap_or(crate::DEFAULT_DNS_RESOLVE_DELEGATION)
+	}
+
+	pub fn get_key_passphrase(&self) -> Result<Option<Passphrase>, Error> {
+		let passphrase = match (
+			&self.key_passphrase_file,
+			&self.key_passphrase_env,
+			&self.key_passphrase_credential,
+		) {
+			(None, None, None) => {
+				return Ok(None);
+			}
+			(Some(path), None, None) => read_file(Path::new(path))?,
+			(None, Some(var), None) => std::env::var(var)
+				.map_err(|e| Error::from(e.to_string()).prefix(var))?
+				.into_bytes(),
+			(None, None, Some(name)) => {
+				// systemd credentials, see systemd.exec(5)
+				if name.is_empty() || name.contains('/') {
+					let msg = format!("{name}: invalid credential name");
+					return Err(msg.into());
+				}
+				let dir = std::env::var("CREDENTIALS_DIRECTORY").map_err(|_| {
+					Error::from("CREDENTIALS_DIRECTORY: environment variable not set")
+						.prefix("key_passphrase_credential")
+				})?;
+				read_file(&Path::new(&dir).join(name))?
+			}
+			_ => {
+				return Err("only one of key_passphrase_file, key_passphrase_env and key_passphrase_credential can be set".into());
+			}
+		};
+		Passphrase::new(&passphrase).map(Some)
+	}
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>, Error> {
+	fs::read(path).map_err(|e| Error::from(e).prefix(&path.display().to_string()))
+}
+
+fn read_csr(path: &str) -> Result<Csr, Error> {
+	let data = read_file(Path::new(path))?;
+	Csr::from_pem(&data).map_err(|e| e.prefix(path))
 }
 
 #[derive(Clone, Deserialize)]
@@ -255,12 +379,15 @@ impl Endpoint {
 		}
 	}
 
-	pub fn get_renew_delay(&self, cnf: &Config) -> Result<Duration, Error> {
+	pub fn get_renew_delay(&self, cnf: &Config) -> Result<RenewDelay, Error> {
 		match &self.renew_delay {
-			Some(d) => parse_duration(d),
+			Some(d) => parse_renew_delay(d),
 			None => match &cnf.global {
 				Some(g) => g.get_renew_delay(),
-				None => Ok(Duration::new(crate::DEFAULT_CERT_RENEW_DELAY, 0)),
+				None => Ok(RenewDelay::Fixed(Duration::new(
+					crate::DEFAULT_CERT_RENEW_DELAY,
+					0,
+				))),
 			},
 		}
 	}
@@ -305,6 +432,36 @@ impl Endpoint {
 	}
 }
 
+#[derive(Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AcmeDns {
+	#[serde(default)]
+	pub allow_from: Vec<String>,
+	pub name: String,
+	pub root_certificates: Option<Vec<String>>,
+	pub url: String,
+}
+
+impl AcmeDns {
+	fn to_generic(&self, cnf: &Config) -> crate::acme_dns::AcmeDnsServer {
+		let mut root_lst: Vec<String> = vec![];
+		if let Some(crt_lst) = &self.root_certificates {
+			root_lst.extend(crt_lst.iter().map(|v| v.to_owned()));
+		}
+		if let Some(glob) = &cnf.global {
+			if let Some(crt_lst) = &glob.root_certificates {
+				root_lst.extend(crt_lst.iter().map(|v| v.to_owned()));
+			}
+		}
+		crate::acme_dns::AcmeDnsServer {
+			name: self.name.to_owned(),
+			url: self.url.to_owned(),
+			allow_from: self.allow_from.to_owned(),
+			root_certificates: root_lst,
+		}
+	}
+}
+
 #[derive(Clone, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct RateLimit {
@@ -318,14 +475,140 @@ pub struct RateLimit {
 pub struct Hook {
 	pub allow_failure: Option<bool>,
 	pub args: Option<Vec<String>>,
-	pub cmd: String,
+	pub body: Option<String>,
+	pub cmd: Option<String>,
+	#[serde(default)]
+	pub headers: HashMap<String, String>,
+	pub method: Option<String>,
 	pub name: String,
+	pub retries: Option<usize>,
+	pub root_certificates: Option<Vec<String>>,
 	pub stderr: Option<String>,
 	pub stdin: Option<String>,
 	pub stdin_str: Option<String>,
 	pub stdout: Option<String>,
+	pub success_status: Option<Vec<u16>>,
+	pub timeout: Option<String>,
+	pub tls_verify: Option<bool>,
 	#[serde(rename = "type")]
 	pub hook_type: Vec<HookType>,
+	pub log_output: Option<String>,
+	pub url: Option<String>,
+}
+
+impl Hook {
+	fn get_timeout(&self, cnf: &Config) -> Result<Duration, Error> {
+		match &self.timeout {
+			Some(d) => parse_duration(d),
+			None => match &cnf.global {
+				Some(g) => g.get_hook_timeout(),
+				None => Ok(Duration::new(crate::DEFAULT_HOOK_TIMEOUT, 0)),
+			},
+		}
+	}
+
+	fn get_log_output(&self) -> Result<Option<log::Level>, Error> {
+		match &self.log_output {
+			Some(l) => match l.to_lowercase().as_str() {
+				"warn" => Ok(Some(log::Level::Warn)),
+				"info" => Ok(Some(log::Level::Info)),
+				"debug" => Ok(Some(log::Level::Debug)),
+				"trace" => Ok(Some(log::Level::Trace)),
+				_ => Err(format!("{}: {l}: invalid log level", &self.name).into()),
+			},
+			None => Ok(None),
+		}
+	}
+
+	fn get_kind(&self, cnf: &Config) -> Result<hooks::HookKind, Error> {
+		match (&self.cmd, &self.url) {
+			(Some(cmd), None) => {
+				let http_only = [
+					self.body.is_some(),
+					!self.headers.is_empty(),
+					self.method.is_some(),
+					self.retries.is_some(),
+					self.root_certificates.is_some(),
+					self.success_status.is_some(),
+					self.tls_verify.is_some(),
+				];
+				if http_only.contains(&true) {
+					let msg = format!(
+						"{}: body, headers, method, retries, root_certificates, success_status and tls_verify can only be used with url",
+						&self.name
+					);
+					return Err(msg.into());
+				}
+				Ok(hooks::HookKind::Command(hooks::CommandHook {
+					cmd: cmd.to_owned(),
+					args: self.args.to_owned(),
+					stdin: get_stdin(self)?,
+					stdout: self.stdout.to_owned(),
+					stderr: self.stderr.to_owned(),
+					log_output: self.get_log_output()?,
+				}))
+			}
+			(None, Some(url)) => {
+				let cmd_only = [
+					self.args.is_some(),
+					self.log_output.is_some(),
+					self.stderr.is_some(),
+					self.stdin.is_some(),
+					self.stdin_str.is_some(),
+					self.stdout.is_some(),
+				];
+				if cmd_only.contains(&true) {
+					let msg = format!(
+						"{}: args, log_output, stderr, stdin, stdin_str and stdout can only be used with cmd",
+						&self.name
+					);
+					return Err(msg.into());
+				}
+				let method = self
+					.method
+					.as_deref()
+					.unwrap_or(crate::DEFAULT_HOOK_HTTP_METHOD)
+					.to_uppercase();
+				let method = reqwest::Method::from_bytes(method.as_bytes()).map_err(|_| {
+					Error::from(format!("{}: {method}: invalid HTTP method", &self.name))
+				})?;
+				let mut root_certificates = vec![];
+				if let Some(crt_lst) = &self.root_certificates {
+					root_certificates.extend(crt_lst.iter().map(|v| v.to_owned()));
+				}
+				if let Some(glob) = &cnf.global {
+					if let Some(crt_lst) = &glob.root_certificates {
+						root_certificates.extend(crt_lst.iter().map(|v| v.to_owned()));
+					}
+				}
+				let mut headers: Vec<(String, String)> = self
+					.headers
+					.iter()
+					.map(|(k, v)| (k.to_owned(), v.to_owned()))
+					.collect();
+				headers.sort();
+				Ok(hooks::HookKind::Http(hooks::HttpHook {
+					method,
+					url: url.to_owned(),
+					headers,
+					body: self.body.to_owned(),
+					tls_verify: self
+						.tls_verify
+						.unwrap_or(crate::DEFAULT_HOOK_HTTP_TLS_VERIFY),
+					root_certificates,
+					retries: self.retries.unwrap_or(crate::DEFAULT_HOOK_HTTP_RETRIES),
+					success_status: self.success_status.to_owned().unwrap_or_default(),
+				}))
+			}
+			_ => {
+				let msg = format!(
+					"{}: one and only one of `cmd` or `url` must be specified",
+					&self.name
+				);
+				Err(msg.into())
+			}
+		}
+	}
 }
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Deserialize)]
@@ -347,7 +630,13 @@ pub enum HookType {
 	ChallengeTlsAlpn01,
 	#[serde(rename = "challenge-tls-alpn-01-clean")]
 	ChallengeTlsAlpn01Clean,
+	PreOperation,
 	PostOperation,
+	PostOperationSuccess,
+	PostOperationFailure,
+	ExpirationWarning,
+	AccountCreated,
+	AccountKeyRolled,
 }
 
 #[derive(Deserialize)]
@@ -355,6 +644,7 @@ pub enum HookType {
 pub struct Group {
 	pub hooks: Vec<String>,
 	pub name: String,
+	pub parallel: Option<bool>,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -396,6 +686,7 @@ pub struct Account {
 	pub external_account: Option<ExternalAccount>,
 	pub hooks: Option<Vec<String>>,
 	pub key_type: Option<String>,
+	pub key_uri: Option<String>,
 	pub name: String,
 	pub signature_algorithm: Option<String>,
 }
@@ -434,6 +725,7 @@ impl Account {
 			&self.name,
 			&contacts,
 			&self.key_type,
+			&self.key_uri,
 			&self.signature_algorithm,
 			&external_account,
 		)
@@ -461,17 +753,31 @@ impl AccountContact {
 #[serde(deny_unknown_fields)]
 pub struct Certificate {
 	pub account: String,
+	pub challenge: Option<ChallengeList>,
 	pub csr_digest: Option<String>,
+	pub csr_file: Option<String>,
 	pub directory: Option<String>,
+	pub dns_propagation_check: Option<bool>,
+	pub dns_propagation_timeout: Option<String>,
+	pub dns_resolve_delegation: Option<bool>,
 	pub endpoint: String,
 	#[serde(default)]
 	pub env: HashMap<String, String>,
+	pub expiration_warning: Option<String>,
 	pub file_name_format: Option<String>,
 	pub hooks: Vec<String>,
+	#[serde(default)]
 	pub identifiers: Vec<Identifier>,
+	pub key_file: Option<String>,
 	pub key_type: Option<String>,
+	pub key_uri: Option<String>,
 	pub kp_reuse: Option<bool>,
 	pub name: Option<String>,
+	pub not_after: Option<String>,
+	pub not_before: Option<String>,
+	pub pre_authorization_delay: Option<String>,
+	pub pre_operation_retry_delay: Option<String>,
+	pub profile: Option<String>,
 	pub random_early_renew: Option<String>,
 	pub renew_delay: Option<String>,
 	#[serde(default)]
@@ -479,10 +785,57 @@ pub struct Certificate {
 }
 
 impl Certificate {
-	pub fn get_key_type(&self) -> Result<KeyType, Error> {
-		match &self.key_type {
-			Some(a) => a.parse(),
-			None => Ok(crate::DEFAULT_CERT_KEY_TYPE),
+	pub fn get_key_type(&self, external_key_type: Option<KeyType>) -> Result<KeyType, Error> {
+		match (&self.key_type, external_key_type) {
+			(Some(a), Some(ext)) => {
+				let key_type: KeyType = a.parse()?;
+				if key_type != ext {
+					let msg = format!(
+						"the key type is set to {key_type} but the externally managed key is a {ext} key"
+					);
+					return Err(msg.into());
+				}
+				Ok(key_type)
+			}
+			(Some(a), None) => a.parse(),
+			(None, Some(ext)) => Ok(ext),
+			(None, None) => Ok(crate::DEFAULT_CERT_KEY_TYPE),
+		}
+	}
+
+	/// Return the type of the key which is not managed by ACMEd, if any. Such
+	/// a key is either stored in a PKCS #11 token, read from a private key
+	/// file or used in a certificate signing request file.
+	pub fn get_external_key_type(
+		&self,
+		key_pair: &Option<KeyPair>,
+		key_passphrase: &Option<Passphrase>,
+	) -> Result<Option<KeyType>, Error> {
+		match (key_pair, &self.key_file, &self.csr_file) {
+			(None, None, None) => Ok(None),
+			(Some(kp), None, None) => Ok(Some(kp.key_type)),
+			(None, Some(path), None) => {
+				let data = read_file(Path::new(path))?;
+				let key_pair = match key_passphrase {
+					Some(passphrase) => KeyPair::from_pem_passphrase(&data, passphrase.as_bytes()),
+					None => KeyPair::from_pem(&data),
+				}
+				.map_err(|e| e.prefix(path))?;
+				Ok(Some(key_pair.key_type))
+			}
+			(None, None, Some(path)) => {
+				let csr = read_csr(path)?;
+				Ok(Some(csr.key_type().map_err(|e| e.prefix(path))?))
+			}
+			_ => Err("only one of key_uri, key_file and csr_file can be set".into()),
+		}
+	}
+
+	/// Return the key pair stored in a PKCS #11 token, if any.
+	pub fn get_key_pair(&self) -> Result<Option<KeyPair>, Error> {
+		match &self.key_uri {
+			Some(uri) => Ok(Some(KeyPair::from_pkcs11_uri(uri)?)),
+			None => Ok(None),
 		}
 	}
 
@@ -493,12 +846,54 @@ impl Certificate {
 		}
 	}
 
-	pub fn get_identifiers(&self) -> Result<Vec<crate::identifier::Identifier>, Error> {
-		let mut ret = vec![];
-		for id in self.identifiers.iter() {
-			ret.push(id.to_generic()?);
+	/// Return the identifiers to include in the certificate. If none are
+	/// specified, they are derived from the subject alternative names of the
+	/// certificate signing request file and use the certificate's challenge.
+	pub fn get_identifiers(
+		&self,
+		cnf: &Config,
+	) -> Result<Vec<crate::identifier::Identifier>, Error> {
+		if !self.identifiers.is_empty() {
+			if self.challenge.is_some() {
+				return Err("`challenge` cannot be used along with `identifiers`".into());
+			}
+			let mut ret = vec![];
+			for id in self.identifiers.iter() {
+				ret.push(id.to_generic(cnf)?);
+			}
+			return Ok(ret);
 		}
-		Ok(ret)
+		let path = self
+			.csr_file
+			.as_ref()
+			.ok_or_else(|| Error::from("certificate has no identifiers"))?;
+		let challenges = self
+			.challenge
+			.as_ref()
+			.ok_or_else(|| {
+				Error::from(
+					"`challenge` must be specified when the identifiers are derived from `csr_file`",
+				)
+			})?
+			.to_vec();
+		let mut names = read_csr(path)?
+			.subject_alt_names()
+			.map_err(|e| e.prefix(path))?
+			.iter()
+			.map(|name| parse_subject_alt_name(name).map_err(|e| e.prefix(path)))
+			.collect::<Result<Vec<(IdentifierType, String)>, Error>>()?;
+		if names.is_empty() {
+			let msg = format!("{path}: the request has no subject alternative names");
+			return Err(msg.into());
+		}
+		names.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+		let env = HashMap::new();
+		names
+			.into_iter()
+			.map(|(id_type, value)| {
+				crate::identifier::Identifier::new(id_type, &value, &challenges, &env)
+			})
+			.collect()
 	}
 
 	pub fn get_kp_reuse(&self) -> bool {
@@ -508,16 +903,22 @@ impl Certificate {
 		}
 	}
 
-	pub fn get_crt_name(&self) -> Result<String, Error> {
+	/// Return the certificate's name. By default, it is the first configured
+	/// identifier or, if the identifiers are derived from the certificate
+	/// signing request, the first one in alphabetical order.
+	pub fn get_crt_name(
+		&self,
+		identifiers: &[crate::identifier::Identifier],
+	) -> Result<String, Error> {
 		let name = match &self.name {
 			Some(n) => n.to_string(),
-			None => {
-				let id = self
-					.identifiers
+			None => match self.identifiers.first() {
+				Some(id) => id.to_string(),
+				None => identifiers
 					.first()
-					.ok_or_else(|| Error::from("certificate has no identifiers"))?;
-				id.to_string()
-			}
+					.map(|id| id.value.to_owned())
+					.ok_or_else(|| Error::from("certificate has no identifiers"))?,
+			},
 		};
 		let name = name.replace(['*', ':', '/'], "_");
 		Ok(name)
@@ -584,23 +985,115 @@ impl Certificate {
 		}
 	}
 
-	pub fn get_renew_delay(&self, cnf: &Config) -> Result<Duration, Error> {
+	pub fn get_renew_delay(&self, cnf: &Config) -> Result<RenewDelay, Error> {
 		match &self.renew_delay {
-			Some(d) => parse_duration(d),
+			Some(d) => parse_renew_delay(d),
 			None => {
 				let endpoint = self.do_get_endpoint(cnf)?;
 				endpoint.get_renew_delay(cnf)
 			}
 		}
 	}
+
+	pub fn get_dns_propagation_check(&self, cnf: &Config) -> bool {
+		match self.dns_propagation_check {
+			Some(c) => c,
+			None => match &cnf.global {
+				Some(g) => g.get_dns_propagation_check(),
+				None => crate::DEFAULT_DNS_PROPAGATION_CHECK,
+			},
+		}
+	}
+
+	pub fn get_dns_propagation_timeout(&self, cnf: &Config) -> Result<Duration, Error> {
+		match &self.dns_propagation_timeout {
+			Some(d) => parse_duration(d),
+			None => match &cnf.global {
+				Some(g) => g.get_dns_propagation_timeout(),
+				None => Ok(Duration::new(crate::DEFAULT_DNS_PROPAGATION_TIMEOUT, 0)),
+			},
+		}
+	}
+
+	pub fn get_expiration_warning(&self, cnf: &Config) -> Result<Duration, Error> {
+		match &self.expiration_warning {
+			Some(d) => parse_duration(d),
+			None => match &cnf.global {
+				Some(g) => g.get_expiration_warning(),
+				None => Ok(Duration::new(crate::DEFAULT_CERT_EXPIRATION_WARNING, 0)),
+			},
+		}
+	}
+
+	pub fn get_not_before(&self) -> Result<Option<TimeReference>, Error> {
+		self.not_before
+			.as_deref()
+			.map(TimeReference::from_str)
+			.transpose()
+	}
+
+	pub fn get_not_after(&self) -> Result<Option<TimeReference>, Error> {
+		self.not_after
+			.as_deref()
+			.map(TimeReference::from_str)
+			.transpose()
+	}
+
+	pub fn get_pre_authorization_delay(&self, cnf: &Config) -> Result<Duration, Error> {
+		match &self.pre_authorization_delay {
+			Some(d) => parse_duration(d),
+			None => match &cnf.global {
+				Some(g) => g.get_pre_authorization_delay(),
+				None => Ok(Duration::new(crate::DEFAULT_PRE_AUTHORIZATION_DELAY, 0)),
+			},
+		}
+	}
+
+	pub fn get_pre_operation_retry_delay(&self, cnf: &Config) -> Result<Duration, Error> {
+		match &self.pre_operation_retry_delay {
+			Some(d) => parse_duration(d),
+			None => match &cnf.global {
+				Some(g) => g.get_pre_operation_retry_delay(),
+				None => Ok(Duration::new(crate::DEFAULT_PRE_OPERATION_RETRY_DELAY, 0)),
+			},
+		}
+	}
+
+	pub fn get_dns_resolve_delegation(&self, cnf: &Config) -> bool {
+		match self.dns_resolve_delegation {
+			Some(r) => r,
+			None => match &cnf.global {
+				Some(g) => g.get_dns_resolve_delegation(),
+				None => crate::DEFAULT_DNS_RESOLVE_DELEGATION,
+			},
+		}
+	}
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum ChallengeList {
+	Single(String),
+	List(Vec<String>),
+}
+
+impl ChallengeList {
+	fn to_vec(&self) -> Vec<String> {
+		match self {
+			ChallengeList::Single(c) => vec![c.to_owned()],
+			ChallengeList::List(l) => l.to_owned(),
+		}
+	}
 }
 
 #[derive(Clone, Debug, Deserialize)]
 #[serde(remote = "Self")]
 #[serde(deny_unknown_fields)]
 pub struct Identifier {
-	pub challenge: String,
+	pub acme_dns: Option<String>,
+	pub challenge: ChallengeList,
 	pub dns: Option<String>,
+	pub dns_delegation: Option<String>,
 	#[serde(default)]
 	pub env: HashMap<String, String>,
 	pub ip: Option<String>,
@@ -622,6 +1115,11 @@ impl<'de> Deserialize<'de> for Identifier {
 				"one and only one of `dns` or `ip` must be specified",
 			));
 		}
+		if unchecked.acme_dns.is_some() && unchecked.dns_delegation.is_some() {
+			return Err(de::Error::custom(
+				"`acme_dns` and `dns_delegation` cannot be both specified",
+			));
+		}
 		Ok(unchecked)
 	}
 }
@@ -635,7 +1133,7 @@ impl fmt::Display for Identifier {
 }
 
 impl Identifier {
-	fn to_generic(&self) -> Result<crate::identifier::Identifier, Error> {
+	fn to_generic(&self, cnf: &Config) -> Result<crate::identifier::Identifier, Error> {
 		let (t, v) = match &self.dns {
 			Some(d) => (IdentifierType::Dns, d),
 			None => match &self.ip {
@@ -645,7 +1143,14 @@ impl Identifier {
 				}
 			},
 		};
-		crate::identifier::Identifier::new(t, v, &self.challenge, &self.env)
+		let mut id = crate::identifier::Identifier::new(t, v, &self.challenge.to_vec(), &self.env)?;
+		if let Some(name) = &self.acme_dns {
+			id.set_acme_dns(cnf.get_acme_dns(name)?)?;
+		}
+		if let Some(target) = &self.dns_delegation {
+			id.set_dns_delegation(target)?;
+		}
+		Ok(id)
 	}
 }
 
@@ -746,6 +1251,7 @@ fn read_cnf(path: &Path, loaded_files: &mut BTreeSet<PathBuf>) -> Result<Config,
 		for cnf_path in get_cnf_path(&path, cnf_name)? {
 			let mut add_cnf = read_cnf(&cnf_path, loaded_files)?;
 			config.endpoint.append(&mut add_cnf.endpoint);
+			config.acme_dns.append(&mut add_cnf.acme_dns);
 			config.rate_limit.append(&mut add_cnf.rate_limit);
 			config.hook.append(&mut add_cnf.hook);
 			config.group.append(&mut add_cnf.group);
@@ -766,6 +1272,28 @@ fn read_cnf(path: &Path, loaded_files: &mut BTreeSet<PathBuf>) -> Result<Config,
 				set_cfg_attr!(tmp_glob.pk_file_mode, new_glob.pk_file_mode);
 				set_cfg_attr!(tmp_glob.pk_file_user, new_glob.pk_file_user);
 				set_cfg_attr!(tmp_glob.pk_file_group, new_glob.pk_file_group);
+				set_cfg_attr!(
+					tmp_glob.dns_propagation_check,
+					new_glob.dns_propagation_check
+				);
+				set_cfg_attr!(
+					tmp_glob.dns_propagation_timeout,
+					new_glob.dns_propagation_timeout
+				);
+				set_cfg_attr!(
+					tmp_glob.dns_resolve_delegation,
+					new_glob.dns_resolve_delegation
+				);
+				set_cfg_attr!(tmp_glob.hook_timeout, new_glob.hook_timeout);
+				set_cfg_attr!(tmp_glob.expiration_warning, new_glob.expiration_warning);
+				set_cfg_attr!(
+					tmp_glob.pre_authorization_delay,
+					new_glob.pre_authorization_delay
+				);
+				set_cfg_attr!(
+					tmp_glob.pre_operation_retry_delay,
+					new_glob.pre_operation_retry_delay
+				);
 				config.global = Some(tmp_glob);
 			}
 		}
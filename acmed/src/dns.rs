@@ -0,0 +1,223 @@
+use crate::logs::HasLogger;
+use acme_common::error::Error;
+use hickory_resolver::config::{NameServerConfig, ResolverConfig, ResolverOpts};
+use hickory_resolver::name_server::TokioConnectionProvider;
+use hickory_resolver::proto::rr::{RData, RecordType};
+use hickory_resolver::proto::xfer::Protocol;
+use hickory_resolver::{ResolveError, TokioResolver};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+const DNS_PORT: u16 = 53;
+const MAX_CNAME_DEPTH: usize = 16;
+
+fn resolve_err(error: ResolveError) -> Error {
+	format!("DNS error: {error}").into()
+}
+
+fn get_system_resolver() -> Result<TokioResolver, Error> {
+	let resolver = TokioResolver::builder_tokio().map_err(resolve_err)?.build();
+	Ok(resolver)
+}
+
+fn get_authoritative_resolver(ip: IpAddr) -> TokioResolver {
+	let mut config = ResolverConfig::new();
+	config.add_name_server(NameServerConfig::new(
+		SocketAddr::new(ip, DNS_PORT),
+		Protocol::Udp,
+	));
+	let mut opts = ResolverOpts::default();
+	opts.recursion_desired = false;
+	opts.cache_size = 0;
+	TokioResolver::builder_with_config(config, TokioConnectionProvider::default())
+		.with_options(opts)
+		.build()
+}
+
+fn to_fqdn(name: &str) -> String {
+	if name.ends_with('.') {
+		name.to_string()
+	} else {
+		format!("{name}.")
+	}
+}
+
+fn get_zone_candidates(name: &str) -> Vec<String> {
+	let name = to_fqdn(name);
+	let labels: Vec<&str> = name.trim_end_matches('.').split('.').collect();
+	(0..labels.len())
+		.map(|i| to_fqdn(&labels[i..].join(".")))
+		.collect()
+}
+
+async fn do_resolve_cname<L: HasLogger>(
+	logger: &L,
+	resolver: &TokioResolver,
+	name: &str,
+) -> Result<String, Error> {
+	let mut current = to_fqdn(name);
+	for _ in 0..MAX_CNAME_DEPTH {
+		let target = match resolver.lookup(current.as_str(), RecordType::CNAME).await {
+			Ok(lookup) => lookup.record_iter().find_map(|r| match r.data() {
+				RData::CNAME(cname) => Some(cname.to_ascii()),
+				_ => None,
+			}),
+			Err(e) if e.is_no_records_found() || e.is_nx_domain() => None,
+			Err(e) => {
+				return Err(resolve_err(e));
+			}
+		};
+		match target {
+			Some(target) => {
+				logger.trace(&format!("{current}: CNAME to {target}"));
+				current = to_fqdn(&target);
+			}
+			None => {
+				return Ok(current);
+			}
+		}
+	}
+	Err(format!("{name}: too many CNAME redirections").into())
+}
+
+async fn get_authoritative_servers<L: HasLogger>(
+	logger: &L,
+	resolver: &TokioResolver,
+	name: &str,
+) -> Result<Vec<IpAddr>, Error> {
+	for zone in get_zone_candidates(name) {
+		let ns_names = match resolver.ns_lookup(zone.as_str()).await {
+			Ok(lookup) => lookup
+				.as_lookup()
+				.record_iter()
+				.filter(|r| r.name().to_ascii().eq_ignore_ascii_case(&zone))
+				.filter_map(|r| match r.data() {
+					RData::NS(ns) => Some(ns.to_ascii()),
+					_ => None,
+				})
+				.collect::<Vec<String>>(),
+			Err(e) if e.is_no_records_found() || e.is_nx_domain() => Vec::new(),
+			Err(e) => {
+				return Err(resolve_err(e));
+			}
+		};
+		if ns_names.is_empty() {
+			continue;
+		}
+		logger.trace(&format!(
+			"{name}: zone {zone} served by {}",
+			ns_names.join(", ")
+		));
+		let mut servers = vec![];
+		for ns in ns_names.iter() {
+			match resolver.lookup_ip(ns.as_str()).await {
+				Ok(ips) => servers.extend(ips.iter()),
+				Err(e) => logger.debug(&format!("{ns}: unable to resolve name server: {e}")),
+			}
+		}
+		if servers.is_empty() {
+			return Err(format!("{zone}: unable to resolve any authoritative name server").into());
+		}
+		return Ok(servers);
+	}
+	Err(format!("{name}: unable to find the authoritative zone").into())
+}
+
+async fn has_txt_value(server: IpAddr, name: &str, value: &str) -> Result<bool, Error> {
+	let resolver = get_authoritative_resolver(server);
+	match resolver.txt_lookup(name).await {
+		Ok(lookup) => Ok(lookup.iter().any(|txt| {
+			let data = txt
+				.txt_data()
+				.iter()
+				.flat_map(|d| d.iter().copied())
+				.collect::<Vec<u8>>();
+			data == value.as_bytes()
+		})),
+		Err(e) if e.is_no_records_found() || e.is_nx_domain() => Ok(false),
+		Err(e) => Err(resolve_err(e)),
+	}
+}
+
+/// Follow the CNAME records starting at `name` and return the final name,
+/// without the trailing dot.
+pub async fn resolve_cname<L: HasLogger>(logger: &L, name: &str) -> Result<String, Error> {
+	let resolver = get_system_resolver()?;
+	let target = do_resolve_cname(logger, &resolver, name).await?;
+	Ok(target.trim_end_matches('.').to_string())
+}
+
+/// Wait until the TXT record `name` containing `value` is served by every
+/// authoritative name server of its zone, or until `timeout` is reached.
+pub async fn wait_for_propagation<L: HasLogger>(
+	logger: &L,
+	name: &str,
+	value: &str,
+	timeout: Duration,
+) -> Result<(), Error> {
+	let start = Instant::now();
+	let resolver = get_system_resolver()?;
+	let name = do_resolve_cname(logger, &resolver, name).await?;
+	let servers = get_authoritative_servers(logger, &resolver, &name).await?;
+	let mut pending = servers;
+	loop {
+		let mut still_pending = vec![];
+		for server in pending.into_iter() {
+			match has_txt_value(server, &name, value).await {
+				Ok(true) => logger.trace(&format!("{name}: TXT record found on {server}")),
+				Ok(false) => still_pending.push(server),
+				Err(e) => {
+					logger.trace(&format!("{name}: {server}: {e}"));
+					still_pending.push(server);
+				}
+			}
+		}
+		if still_pending.is_empty() {
+			logger.debug(&format!(
+				"{name}: TXT record propagated in {}s",
+				start.elapsed().as_secs()
+			));
+			return Ok(());
+		}
+		if start.elapsed() >= timeout {
+			let servers = still_pending
+				.iter()
+				.map(|s| s.to_string())
+				.collect::<Vec<String>>()
+				.join(", ");
+			let msg = format!(
+				"{name}: TXT record not visible on the following name servers after {}s: {servers}",
+				timeout.as_secs()
+			);
+			return Err(msg.into());
+		}
+		pending = still_pending;
+		sleep(Duration::from_secs(crate::DEFAULT_DNS_PROPAGATION_WAIT_SEC)).await;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{get_zone_candidates, to_fqdn};
+
+	#[test]
+	fn test_to_fqdn() {
+		assert_eq!(to_fqdn("example.org"), "example.org.");
+		assert_eq!(to_fqdn("example.org."), "example.org.");
+	}
+
+	#[test]
+	fn test_zone_candidates() {
+		let c = get_zone_candidates("_acme-challenge.www.example.org");
+		assert_eq!(
+			c,
+			vec![
+				"_acme-challenge.www.example.org.",
+				"www.example.org.",
+				"example.org.",
+				"org.",
+			]
+		);
+	}
+}
@@ -50,3 +50,77 @@ pub fn parse_duration(input: &str) -> Result<Duration, Error> {
 		Err(_) => Err(format!("{input}: invalid duration").into()),
 	}
 }
+
+/// Period of time between the certificate renewal and its expiration date.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenewDelay {
+	Fixed(Duration),
+	/// Percentage of the certificate's lifetime.
+	Lifetime(u32),
+}
+
+impl Default for RenewDelay {
+	fn default() -> Self {
+		RenewDelay::Lifetime(crate::DEFAULT_CERT_RENEW_LIFETIME_PERCENT)
+	}
+}
+
+impl RenewDelay {
+	/// Return the delay for a certificate having the given lifetime. A fixed
+	/// delay that is not shorter than the lifetime would result in
+	/// continuous renewals and is therefore rejected.
+	pub fn get_delay(&self, lifetime: Duration) -> Result<Duration, Error> {
+		match self {
+			RenewDelay::Fixed(d) if *d < lifetime => Ok(*d),
+			RenewDelay::Fixed(d) => Err(format!(
+				"the renewal delay ({} days) is not shorter than the certificate's lifetime ({} days)",
+				d.as_secs() / 86400,
+				lifetime.as_secs() / 86400
+			)
+			.into()),
+			RenewDelay::Lifetime(p) => Ok(lifetime * *p / 100),
+		}
+	}
+}
+
+pub fn parse_renew_delay(input: &str) -> Result<RenewDelay, Error> {
+	match input.strip_suffix('%') {
+		Some(nb) => match nb.parse::<u32>() {
+			Ok(p) if (1..100).contains(&p) => Ok(RenewDelay::Lifetime(p)),
+			_ => Err(format!("{input}: invalid percentage").into()),
+		},
+		None => Ok(RenewDelay::Fixed(parse_duration(input)?)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_renew_delay, RenewDelay};
+	use std::time::Duration;
+
+	#[test]
+	fn test_renew_delay() {
+		let six_days = Duration::from_secs(6 * 86_400);
+		let d = parse_renew_delay("2d").unwrap();
+		assert_eq!(d, RenewDelay::Fixed(Duration::from_secs(2 * 86_400)));
+		assert_eq!(
+			d.get_delay(six_days).unwrap(),
+			Duration::from_secs(2 * 86_400)
+		);
+		let d = parse_renew_delay("50%").unwrap();
+		assert_eq!(d, RenewDelay::Lifetime(50));
+		assert_eq!(
+			d.get_delay(six_days).unwrap(),
+			Duration::from_secs(3 * 86_400)
+		);
+		let d = parse_renew_delay("30d").unwrap();
+		assert!(d.get_delay(six_days).is_err());
+		assert_eq!(
+			RenewDelay::default().get_delay(six_days).unwrap(),
+			six_days * 33 / 100
+		);
+		for invalid in ["0%", "100%", "-5%", "%", "30"] {
+			assert!(parse_renew_delay(invalid).is_err());
+		}
+	}
+}
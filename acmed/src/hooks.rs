@@ -1,17 +1,25 @@
 pub use crate::config::HookType;
+use crate::http::get_client_builder;
 use crate::logs::HasLogger;
 use crate::template::render_template;
 use acme_common::error::Error;
-use async_process::{Command, Stdio};
-use futures::AsyncWriteExt;
+use async_process::{Child, Command, ExitStatus, Stdio};
+use futures::future::{join_all, Either};
+use futures::{AsyncBufReadExt, AsyncRead, AsyncWriteExt, StreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::Client;
 use serde::Serialize;
 use std::collections::hash_map::Iter;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::BufReader;
+#[cfg(unix)]
+use std::os::unix::process::CommandExt;
 use std::path::PathBuf;
+use std::time::{Duration, Instant};
 use std::{env, fmt};
+use tokio::time::{sleep, timeout};
 
 pub trait HookEnvData {
 	fn set_env(&mut self, env: &HashMap<String, String>);
@@ -40,26 +48,93 @@ macro_rules! imple_hook_data_env {
 			}
 		}
 	};
+	($t: ty, $field: ident) => {
+		impl HookEnvData for $t {
+			fn set_env(&mut self, env: &HashMap<String, String>) {
+				self.$field.set_env(env);
+			}
+
+			fn get_env(&self) -> Iter<'_, String, String> {
+				self.$field.get_env()
+			}
+		}
+	};
 }
 
+/// Data available to every hook related to a certificate. The
+/// pre-operation and post-operation success hooks do not have any other.
 #[derive(Clone, Serialize)]
-pub struct PostOperationHookData {
+pub struct CertificateHookData {
 	pub identifiers: Vec<String>,
 	pub key_type: String,
-	pub status: String,
-	pub is_success: bool,
 	pub certificate_path: PathBuf,
 	pub private_key_path: PathBuf,
 	pub env: HashMap<String, String>,
 }
 
-imple_hook_data_env!(PostOperationHookData);
+imple_hook_data_env!(CertificateHookData);
+
+#[derive(Clone, Serialize)]
+pub struct PostOperationHookData {
+	#[serde(flatten)]
+	pub certificate: CertificateHookData,
+	pub status: String,
+	pub is_success: bool,
+}
+
+imple_hook_data_env!(PostOperationHookData, certificate);
+
+#[derive(Clone, Serialize)]
+pub struct PostOperationFailureHookData {
+	#[serde(flatten)]
+	pub certificate: CertificateHookData,
+	pub error: String,
+}
+
+imple_hook_data_env!(PostOperationFailureHookData, certificate);
+
+#[derive(Clone, Serialize)]
+pub struct ExpirationWarningHookData {
+	#[serde(flatten)]
+	pub certificate: CertificateHookData,
+	pub expires_in_days: u64,
+}
+
+imple_hook_data_env!(ExpirationWarningHookData, certificate);
+
+#[derive(Clone, Serialize)]
+pub struct AccountCreatedHookData {
+	pub account_name: String,
+	pub endpoint_name: String,
+	pub account_url: String,
+	pub contacts: Vec<String>,
+	pub key_type: String,
+	pub signature_algorithm: String,
+	pub env: HashMap<String, String>,
+}
+
+imple_hook_data_env!(AccountCreatedHookData);
+
+#[derive(Clone, Serialize)]
+pub struct AccountKeyRolledHookData {
+	pub account_name: String,
+	pub endpoint_name: String,
+	pub account_url: String,
+	pub old_key_type: String,
+	pub old_signature_algorithm: String,
+	pub new_key_type: String,
+	pub new_signature_algorithm: String,
+	pub env: HashMap<String, String>,
+}
+
+imple_hook_data_env!(AccountKeyRolledHookData);
 
 #[derive(Clone, Serialize)]
 pub struct ChallengeHookData {
 	pub identifier: String,
 	pub identifier_tls_alpn: String,
 	pub challenge: String,
+	pub dns_record_name: String,
 	pub file_name: String,
 	pub proof: String,
 	pub raw_proof: String,
@@ -71,10 +146,16 @@ imple_hook_data_env!(ChallengeHookData);
 
 #[derive(Clone, Serialize)]
 pub struct FileStorageHookData {
-	// TODO: add the current operation (create/edit)
+	pub operation: String,
+	pub file_type: String,
+	pub certificate_id: String,
 	pub file_name: String,
 	pub file_directory: String,
 	pub file_path: PathBuf,
+	pub old_fingerprint: String,
+	pub old_serial: String,
+	pub new_fingerprint: String,
+	pub new_serial: String,
 	pub env: HashMap<String, String>,
 }
 
@@ -88,15 +169,51 @@ pub enum HookStdin {
 }
 
 #[derive(Clone, Debug)]
-pub struct Hook {
-	pub name: String,
-	pub hook_type: HashSet<HookType>,
+pub struct CommandHook {
 	pub cmd: String,
 	pub args: Option<Vec<String>>,
 	pub stdin: HookStdin,
 	pub stdout: Option<String>,
 	pub stderr: Option<String>,
+	pub log_output: Option<log::Level>,
+}
+
+#[derive(Clone, Debug)]
+pub struct HttpHook {
+	pub method: reqwest::Method,
+	pub url: String,
+	pub headers: Vec<(String, String)>,
+	pub body: Option<String>,
+	pub tls_verify: bool,
+	pub root_certificates: Vec<String>,
+	pub retries: usize,
+	pub success_status: Vec<u16>,
+}
+
+impl HttpHook {
+	fn is_success(&self, status: reqwest::StatusCode) -> bool {
+		if self.success_status.is_empty() {
+			status.is_success()
+		} else {
+			self.success_status.contains(&status.as_u16())
+		}
+	}
+}
+
+#[derive(Clone, Debug)]
+pub enum HookKind {
+	Command(CommandHook),
+	Http(HttpHook),
+}
+
+#[derive(Clone, Debug)]
+pub struct Hook {
+	pub name: String,
+	pub hook_type: HashSet<HookType>,
+	pub kind: HookKind,
 	pub allow_failure: bool,
+	pub timeout: Duration,
+	pub parallel_group: Option<String>,
 }
 
 impl fmt::Display for Hook {
@@ -106,7 +223,7 @@ impl fmt::Display for Hook {
 }
 
 macro_rules! get_hook_output {
-	($logger: expr, $out: expr, $data: expr, $hook_name: expr, $out_name: expr) => {{
+	($logger: expr, $out: expr, $data: expr, $hook_name: expr, $out_name: expr, $capture: expr) => {{
 		match $out {
 			Some(path) => {
 				let path = render_template(path, $data)?;
@@ -114,19 +231,184 @@ macro_rules! get_hook_output {
 				let file = File::create(&path)?;
 				Stdio::from(file)
 			}
+			None if $capture => Stdio::piped(),
 			None => Stdio::null(),
 		}
 	}};
 }
 
+/// Read a hook's output line by line, forwarding each line to the logger if
+/// a level is set and keeping the last lines in `tail`.
+async fn read_output<L, R>(
+	logger: &L,
+	hook: &Hook,
+	level: Option<log::Level>,
+	out_name: &str,
+	output: Option<R>,
+	tail: &mut VecDeque<String>,
+) where
+	L: HasLogger,
+	R: AsyncRead + Unpin,
+{
+	let output = match output {
+		Some(output) => output,
+		None => return,
+	};
+	let mut lines = futures::io::BufReader::new(output).lines();
+	while let Some(line) = lines.next().await {
+		let line = match line {
+			Ok(l) => l,
+			Err(e) => {
+				logger.debug(&format!("hook \"{}\": {out_name}: {e}", hook.name));
+				break;
+			}
+		};
+		if let Some(level) = level {
+			logger.log(
+				level,
+				&format!("hook \"{}\": {out_name}: {line}", hook.name),
+			);
+		}
+		if tail.len() == crate::DEFAULT_HOOK_OUTPUT_TAIL_LINES {
+			tail.pop_front();
+		}
+		tail.push_back(line);
+	}
+}
+
 async fn call_single<L, T>(logger: &L, data: &T, hook: &Hook) -> Result<(), Error>
 where
 	L: HasLogger,
 	T: Clone + HookEnvData + Serialize,
 {
 	logger.debug(&format!("calling hook \"{}\"", hook.name));
+	match &hook.kind {
+		HookKind::Command(cmd) => call_command(logger, data, hook, cmd).await,
+		HookKind::Http(http) => call_http(logger, data, hook, http).await,
+	}
+}
+
+async fn call_http<L, T>(logger: &L, data: &T, hook: &Hook, http: &HttpHook) -> Result<(), Error>
+where
+	L: HasLogger,
+	T: Clone + HookEnvData + Serialize,
+{
+	let url = render_template(&http.url, &data)?;
+	// Invalid headers would make every attempt fail, hence they are
+	// rejected before the first one.
+	let mut headers = HeaderMap::new();
+	for (name, value) in http.headers.iter() {
+		let value = render_template(value, &data)?;
+		let invalid_header = || Error::from("invalid HTTP header").prefix(name);
+		headers.append(
+			HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid_header())?,
+			HeaderValue::from_str(&value).map_err(|_| invalid_header())?,
+		);
+	}
+	let body = match &http.body {
+		Some(b) => Some(render_template(b, &data)?),
+		None => None,
+	};
+	logger.trace(&format!("hook \"{}\": {} {url}", hook.name, http.method));
+	if let Some(b) = &body {
+		logger.trace(&format!("hook \"{}\": body: {b}", hook.name));
+	}
+	let client = get_client_builder(&http.root_certificates)?
+		.danger_accept_invalid_certs(!http.tls_verify)
+		.build()?;
+	let start = Instant::now();
+	let run = send_http_request(logger, hook, http, &client, &url, &headers, &body);
+	let res = if hook.timeout.is_zero() {
+		run.await
+	} else {
+		match timeout(hook.timeout, run).await {
+			Ok(r) => r,
+			Err(_) => Err("timed out".into()),
+		}
+	};
+	let elapsed = start.elapsed().as_secs_f32();
+	match res {
+		Ok(status) => {
+			logger.debug(&format!(
+				"hook \"{}\": HTTP status {status} (elapsed: {elapsed}s)",
+				hook.name
+			));
+			Ok(())
+		}
+		Err(e) if hook.allow_failure => {
+			logger.debug(&format!(
+				"hook \"{}\": failed: {} (elapsed: {elapsed}s)",
+				hook.name, e.message
+			));
+			Ok(())
+		}
+		Err(e) => Err(e.prefix(&format!("unrecoverable failure (elapsed: {elapsed}s)"))),
+	}
+}
+
+/// Send the request of an HTTP hook, retrying if it fails or if the
+/// response's status is not considered as a success. Return the status of
+/// the successful response.
+async fn send_http_request<L>(
+	logger: &L,
+	hook: &Hook,
+	http: &HttpHook,
+	client: &Client,
+	url: &str,
+	headers: &HeaderMap,
+	body: &Option<String>,
+) -> Result<u16, Error>
+where
+	L: HasLogger,
+{
+	let mut last_error = Error::from("no request sent");
+	for attempt in 0..=http.retries {
+		if attempt > 0 {
+			sleep(Duration::from_secs(crate::DEFAULT_HOOK_HTTP_RETRY_WAIT_SEC)).await;
+			logger.debug(&format!(
+				"hook \"{}\": retry {attempt}/{}",
+				hook.name, http.retries
+			));
+		}
+		let mut request = client
+			.request(http.method.clone(), url)
+			.headers(headers.clone());
+		if let Some(b) = body {
+			request = request.body(b.to_owned());
+		}
+		match request.send().await {
+			Ok(response) => {
+				let status = response.status();
+				if http.is_success(status) {
+					return Ok(status.as_u16());
+				}
+				last_error = format!("unexpected HTTP status: {}", status.as_u16()).into();
+			}
+			// The request could not be built, which will not change
+			Err(e) if e.is_builder() => {
+				return Err(e.into());
+			}
+			Err(e) => {
+				last_error = e.into();
+			}
+		};
+		logger.debug(&format!("hook \"{}\": {}", hook.name, last_error.message));
+	}
+	Err(last_error)
+}
+
+async fn call_command<L, T>(
+	logger: &L,
+	data: &T,
+	hook: &Hook,
+	command: &CommandHook,
+) -> Result<(), Error>
+where
+	L: HasLogger,
+	T: Clone + HookEnvData + Serialize,
+{
 	let mut v = vec![];
-	let args = match &hook.args {
+	let args = match &command.args {
 		Some(lst) => {
 			for fmt in lst.iter() {
 				let s = render_template(fmt, &data)?;
@@ -136,31 +418,140 @@ where
 		}
 		None => &[],
 	};
-	logger.trace(&format!("hook \"{}\": cmd: {}", hook.name, hook.cmd));
+	logger.trace(&format!("hook \"{}\": cmd: {}", hook.name, command.cmd));
 	logger.trace(&format!("hook \"{}\": args: {args:?}", hook.name));
-	let mut cmd = Command::new(&hook.cmd)
+	let mut std_cmd = std::process::Command::new(&command.cmd);
+	// The hook is run in its own process group so that every process it
+	// spawns can be terminated at once.
+	#[cfg(unix)]
+	std_cmd.process_group(0);
+	let mut cmd = Command::from(std_cmd)
 		.envs(data.get_env())
 		.args(args)
 		.stdout(get_hook_output!(
 			logger,
-			&hook.stdout,
+			&command.stdout,
 			&data,
 			&hook.name,
-			"stdout"
+			"stdout",
+			command.log_output.is_some()
 		))
 		.stderr(get_hook_output!(
 			logger,
-			&hook.stderr,
+			&command.stderr,
 			&data,
 			&hook.name,
-			"stderr"
+			"stderr",
+			true
 		))
-		.stdin(match &hook.stdin {
+		.stdin(match &command.stdin {
 			HookStdin::Str(_) | HookStdin::File(_) => Stdio::piped(),
 			HookStdin::None => Stdio::null(),
 		})
 		.spawn()?;
-	match &hook.stdin {
+	let start = Instant::now();
+	let stdout = cmd.stdout.take();
+	let stderr = cmd.stderr.take();
+	let mut stdout_tail = VecDeque::new();
+	let mut stderr_tail = VecDeque::new();
+	let status = {
+		let output = async {
+			futures::join!(
+				read_output(
+					logger,
+					hook,
+					command.log_output,
+					"stdout",
+					stdout,
+					&mut stdout_tail
+				),
+				read_output(
+					logger,
+					hook,
+					command.log_output,
+					"stderr",
+					stderr,
+					&mut stderr_tail
+				),
+			)
+		};
+		let run = async {
+			let wait = write_stdin_and_wait(logger, data, hook, command, &mut cmd);
+			futures::pin_mut!(output);
+			futures::pin_mut!(wait);
+			match futures::future::select(wait, output).await {
+				// A process spawned in the background by the hook may still
+				// hold the pipes, hence the remaining output is only read for
+				// a limited time once the hook has exited.
+				Either::Left((status, output)) => {
+					let drain_delay = Duration::from_secs(crate::DEFAULT_HOOK_OUTPUT_DRAIN_SEC);
+					if timeout(drain_delay, output).await.is_err() {
+						logger.debug(&format!(
+							"hook \"{}\": output still open after exit, no longer read",
+							hook.name
+						));
+					}
+					status
+				}
+				Either::Right((_, wait)) => wait.await,
+			}
+		};
+		if hook.timeout.is_zero() {
+			run.await
+		} else {
+			match timeout(hook.timeout, run).await {
+				Ok(r) => r,
+				Err(_) => {
+					terminate(logger, hook, &mut cmd).await;
+					let msg = format!("timed out (elapsed: {}s)", start.elapsed().as_secs_f32());
+					if hook.allow_failure {
+						logger.debug(&format!("hook \"{}\": {msg}", hook.name));
+						return Ok(());
+					}
+					return Err(format!("unrecoverable failure: {msg}").into());
+				}
+			}
+		}
+	}?;
+	let elapsed = start.elapsed().as_secs_f32();
+	if !status.success() && !hook.allow_failure {
+		let msg = match status.code() {
+			Some(code) => format!("unrecoverable failure: code {code} (elapsed: {elapsed}s)"),
+			None => format!("unrecoverable failure (elapsed: {elapsed}s)"),
+		};
+		let msg = if stderr_tail.is_empty() {
+			msg
+		} else {
+			let stderr_tail: Vec<String> = stderr_tail.into();
+			format!("{msg}: {}", stderr_tail.join("\n"))
+		};
+		return Err(msg.into());
+	}
+	match status.code() {
+		Some(code) => logger.debug(&format!(
+			"hook \"{}\": exited: code {code} (elapsed: {elapsed}s)",
+			hook.name
+		)),
+		None => logger.debug(&format!(
+			"hook \"{}\": exited (elapsed: {elapsed}s)",
+			hook.name
+		)),
+	};
+	Ok(())
+}
+
+async fn write_stdin_and_wait<L, T>(
+	logger: &L,
+	data: &T,
+	hook: &Hook,
+	command: &CommandHook,
+	cmd: &mut Child,
+) -> Result<ExitStatus, Error>
+where
+	L: HasLogger,
+	T: Clone + HookEnvData + Serialize,
+{
+	match &command.stdin {
 		HookStdin::Str(s) => {
 			let data_in = render_template(s, &data)?;
 			logger.trace(&format!("hook \"{}\": string stdin: {data_in}", hook.name));
@@ -180,22 +571,50 @@ where
 		}
 		HookStdin::None => {}
 	}
-	// TODO: add a timeout
-	let status = cmd.status().await?;
-	if !status.success() && !hook.allow_failure {
-		let msg = match status.code() {
-			Some(code) => format!("unrecoverable failure: code {code}").into(),
-			None => "unrecoverable failure".into(),
-		};
-		return Err(msg);
-	}
-	match status.code() {
-		Some(code) => logger.debug(&format!("hook \"{}\": exited: code {code}", hook.name)),
-		None => logger.debug(&format!("hook \"{}\": exited", hook.name)),
-	};
+	Ok(cmd.status().await?)
+}
+
+#[cfg(unix)]
+fn send_signal(cmd: &Child, signal: nix::sys::signal::Signal) -> Result<(), Error> {
+	let pgid = nix::unistd::Pid::from_raw(cmd.id() as i32);
+	nix::sys::signal::killpg(pgid, signal)?;
 	Ok(())
 }
 
+/// Terminate a hook that exceeded its timeout: the whole process group is
+/// first asked to terminate and is killed if it is still running after a
+/// short delay.
+async fn terminate<L: HasLogger>(logger: &L, hook: &Hook, cmd: &mut Child) {
+	#[cfg(unix)]
+	{
+		use nix::sys::signal::Signal;
+
+		logger.debug(&format!("hook \"{}\": timeout, sending SIGTERM", hook.name));
+		if let Err(e) = send_signal(cmd, Signal::SIGTERM) {
+			logger.debug(&format!("hook \"{}\": {}", hook.name, e.message));
+		}
+		let delay = Duration::from_secs(crate::DEFAULT_HOOK_KILL_DELAY_SEC);
+		if timeout(delay, cmd.status()).await.is_ok() {
+			return;
+		}
+		logger.debug(&format!("hook \"{}\": timeout, sending SIGKILL", hook.name));
+		if let Err(e) = send_signal(cmd, Signal::SIGKILL) {
+			logger.debug(&format!("hook \"{}\": {}", hook.name, e.message));
+		}
+	}
+	#[cfg(not(unix))]
+	{
+		logger.debug(&format!(
+			"hook \"{}\": timeout, killing the process",
+			hook.name
+		));
+		if let Err(e) = cmd.kill() {
+			logger.debug(&format!("hook \"{}\": {e}", hook.name));
+		}
+	}
+	let _ = cmd.status().await;
+}
+
 pub async fn call<L, T>(
 	logger: &L,
 	hooks: &[Hook],
@@ -206,10 +625,267 @@ where
 	L: HasLogger,
 	T: Clone + HookEnvData + Serialize,
 {
+	// Consecutive hooks from the same parallel group are batched together.
+	let mut batches: Vec<Vec<&Hook>> = vec![];
 	for hook in hooks.iter().filter(|h| h.hook_type.contains(&hook_type)) {
-		call_single(logger, data, hook)
-			.await
-			.map_err(|e| e.prefix(&hook.name))?;
+		match batches.last_mut() {
+			Some(batch)
+				if hook.parallel_group.is_some()
+					&& batch[0].parallel_group == hook.parallel_group =>
+			{
+				batch.push(hook);
+			}
+			_ => batches.push(vec![hook]),
+		}
+	}
+	for batch in batches {
+		if batch.len() == 1 {
+			let hook = batch[0];
+			call_single(logger, data, hook)
+				.await
+				.map_err(|e| e.prefix(&hook.name))?;
+			continue;
+		}
+		// Hooks from a parallel group are all run to completion, the first
+		// error being reported afterward.
+		let results = join_all(batch.iter().map(|hook| async move {
+			call_single(logger, data, hook)
+				.await
+				.map_err(|e| e.prefix(&hook.name))
+		}))
+		.await;
+		for res in results {
+			res?;
+		}
 	}
 	Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{
+		call, call_single, CertificateHookData, CommandHook, FileStorageHookData, Hook,
+		HookEnvData, HookKind, HookStdin, HookType, HttpHook, PostOperationFailureHookData,
+	};
+	use crate::logs::HasLogger;
+	use crate::template::render_template;
+	use reqwest::{Method, StatusCode};
+	use std::collections::{HashMap, HashSet};
+	use std::path::PathBuf;
+	use std::time::{Duration, Instant};
+
+	struct TestLogger;
+
+	impl HasLogger for TestLogger {
+		fn warn(&self, _msg: &str) {}
+		fn info(&self, _msg: &str) {}
+		fn debug(&self, _msg: &str) {}
+		fn trace(&self, _msg: &str) {}
+	}
+
+	fn get_cmd_hook(args: &[&str], timeout: u64, allow_failure: bool) -> Hook {
+		Hook {
+			name: "test".to_string(),
+			hook_type: HashSet::new(),
+			kind: HookKind::Command(CommandHook {
+				cmd: "sh".to_string(),
+				args: Some(args.iter().map(|a| a.to_string()).collect()),
+				stdin: HookStdin::None,
+				stdout: None,
+				stderr: None,
+				log_output: None,
+			}),
+			allow_failure,
+			timeout: Duration::from_secs(timeout),
+			parallel_group: None,
+		}
+	}
+
+	fn get_hook_data() -> FileStorageHookData {
+		FileStorageHookData {
+			operation: "create".to_string(),
+			file_type: "crt".to_string(),
+			certificate_id: String::new(),
+			file_name: String::new(),
+			file_directory: String::new(),
+			file_path: PathBuf::new(),
+			old_fingerprint: String::new(),
+			old_serial: String::new(),
+			new_fingerprint: String::new(),
+			new_serial: String::new(),
+			env: HashMap::new(),
+		}
+	}
+
+	fn get_hook(success_status: &[u16]) -> HttpHook {
+		HttpHook {
+			method: Method::POST,
+			url: "https://example.org/".to_string(),
+			headers: vec![],
+			body: None,
+			tls_verify: true,
+			root_certificates: vec![],
+			retries: 0,
+			success_status: success_status.to_vec(),
+		}
+	}
+
+	#[test]
+	fn test_http_default_success_status() {
+		let hook = get_hook(&[]);
+		assert!(hook.is_success(StatusCode::OK));
+		assert!(hook.is_success(StatusCode::NO_CONTENT));
+		assert!(!hook.is_success(StatusCode::FOUND));
+		assert!(!hook.is_success(StatusCode::NOT_FOUND));
+	}
+
+	#[test]
+	fn test_http_custom_success_status() {
+		let hook = get_hook(&[200, 409]);
+		assert!(hook.is_success(StatusCode::OK));
+		assert!(hook.is_success(StatusCode::CONFLICT));
+		assert!(!hook.is_success(StatusCode::CREATED));
+	}
+
+	fn get_http_hook(http: HttpHook, timeout: u64) -> Hook {
+		Hook {
+			name: "test".to_string(),
+			hook_type: HashSet::new(),
+			kind: HookKind::Http(http),
+			allow_failure: false,
+			timeout: Duration::from_secs(timeout),
+			parallel_group: None,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_http_invalid_header() {
+		let mut http = get_hook(&[]);
+		http.headers = vec![("X-Test".to_string(), "a\nb".to_string())];
+		http.retries = 3;
+		let hook = get_http_hook(http, 0);
+		let start = Instant::now();
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.is_err());
+		assert!(start.elapsed() < Duration::from_secs(crate::DEFAULT_HOOK_HTTP_RETRY_WAIT_SEC));
+	}
+
+	#[tokio::test]
+	async fn test_http_timeout() {
+		// The connection is accepted but no response is ever sent
+		let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let mut http = get_hook(&[]);
+		http.url = format!("http://{}/", listener.local_addr().unwrap());
+		http.retries = 5;
+		let hook = get_http_hook(http, 1);
+		let start = Instant::now();
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.is_err());
+		assert!(start.elapsed() < Duration::from_secs(3));
+	}
+
+	#[tokio::test]
+	async fn test_cmd_no_timeout() {
+		let hook = get_cmd_hook(&["-c", "exit 0"], 10, false);
+		assert!(call_single(&TestLogger, &get_hook_data(), &hook)
+			.await
+			.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_cmd_timeout() {
+		let start = Instant::now();
+		let hook = get_cmd_hook(&["-c", "sleep 30 & sleep 30"], 1, false);
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.is_err());
+		assert!(start.elapsed() < Duration::from_secs(20));
+		let hook = get_cmd_hook(&["-c", "sleep 30"], 1, true);
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.is_ok());
+	}
+
+	#[tokio::test]
+	async fn test_cmd_stderr_tail() {
+		let mut hook = get_cmd_hook(
+			&["-c", "echo out; echo err1 >&2; echo err2 >&2; exit 1"],
+			10,
+			false,
+		);
+		if let HookKind::Command(cmd) = &mut hook.kind {
+			cmd.log_output = Some(log::Level::Debug);
+		}
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		let msg = res.unwrap_err().message;
+		assert!(msg.ends_with("err1\nerr2"));
+		assert!(!msg.contains("out"));
+	}
+
+	#[tokio::test]
+	async fn test_cmd_stderr_tail_without_log() {
+		let hook = get_cmd_hook(&["-c", "echo err >&2; exit 1"], 10, false);
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.unwrap_err().message.ends_with(": err"));
+	}
+
+	#[tokio::test]
+	async fn test_cmd_background_child() {
+		let start = Instant::now();
+		let hook = get_cmd_hook(&["-c", "sleep 10 & echo done"], 0, false);
+		let res = call_single(&TestLogger, &get_hook_data(), &hook).await;
+		assert!(res.is_ok());
+		assert!(start.elapsed() < Duration::from_secs(8));
+	}
+
+	fn get_group(args: &[&[&str]], parallel_group: Option<&str>) -> Vec<Hook> {
+		args.iter()
+			.map(|a| {
+				let mut hook = get_cmd_hook(a, 10, false);
+				hook.hook_type.insert(HookType::FilePreCreate);
+				hook.parallel_group = parallel_group.map(|g| g.to_string());
+				hook
+			})
+			.collect()
+	}
+
+	#[tokio::test]
+	async fn test_parallel_group() {
+		let data = get_hook_data();
+		let hooks = get_group(&[&["-c", "sleep 2"], &["-c", "sleep 2"]], Some("grp"));
+		let start = Instant::now();
+		let res = call(&TestLogger, &hooks, &data, HookType::FilePreCreate).await;
+		assert!(res.is_ok());
+		assert!(start.elapsed() < Duration::from_secs(4));
+		let hooks = get_group(&[&["-c", "exit 1"], &["-c", "sleep 1"]], Some("grp"));
+		let start = Instant::now();
+		let res = call(&TestLogger, &hooks, &data, HookType::FilePreCreate).await;
+		assert!(res.is_err());
+		assert!(start.elapsed() >= Duration::from_secs(1));
+		let hooks = get_group(&[&["-c", "exit 1"], &["-c", "sleep 1"]], None);
+		let start = Instant::now();
+		let res = call(&TestLogger, &hooks, &data, HookType::FilePreCreate).await;
+		assert!(res.is_err());
+		assert!(start.elapsed() < Duration::from_secs(1));
+	}
+
+	#[test]
+	fn test_certificate_hook_data() {
+		let mut data = PostOperationFailureHookData {
+			certificate: CertificateHookData {
+				identifiers: vec!["example.org".to_string()],
+				key_type: "ecdsa_p256".to_string(),
+				certificate_path: PathBuf::from("/tmp/example.org.crt.pem"),
+				private_key_path: PathBuf::from("/tmp/example.org.pk.pem"),
+				env: HashMap::new(),
+			},
+			error: "invalid order".to_string(),
+		};
+		let env = HashMap::from([("ACMED_TEST".to_string(), "test".to_string())]);
+		data.set_env(&env);
+		assert_eq!(data.certificate.env.get("ACMED_TEST").unwrap(), "test");
+		let tpl = "{{ identifiers[0] }} {{ key_type }} {{ certificate_path }} {{ error }} {{ env.ACMED_TEST }}";
+		assert_eq!(
+			render_template(tpl, &data).unwrap(),
+			"example.org ecdsa_p256 /tmp/example.org.crt.pem invalid order test"
+		);
+	}
+}
@@ -1,11 +1,9 @@
 use crate::acme_proto::structs::{AcmeError, HttpApiError};
 use crate::endpoint::Endpoint;
-#[cfg(feature = "crypto_openssl")]
 use acme_common::error::Error;
 use reqwest::header::{HeaderMap, HeaderValue};
 use reqwest::{header, Client, ClientBuilder, Response};
 use std::fs::File;
-#[cfg(feature = "crypto_openssl")]
 use std::io::prelude::*;
 use std::{thread, time};
 
@@ -142,7 +140,11 @@ fn header_to_string(header_value: &HeaderValue) -> Result<String, Error> {
 	Ok(s.to_string())
 }
 
-fn get_client(root_certs: &[String]) -> Result<Client, Error> {
+pub fn get_client(root_certs: &[String]) -> Result<Client, Error> {
+	Ok(get_client_builder(root_certs)?.build()?)
+}
+
+pub fn get_client_builder(root_certs: &[String]) -> Result<ClientBuilder, Error> {
 	let useragent = format!(
 		"{}/{} ({}) {}",
 		crate::APP_NAME,
@@ -157,17 +159,14 @@ fn get_client(root_certs: &[String]) -> Result<Client, Error> {
 	default_headers.append(header::USER_AGENT, useragent.parse().unwrap());
 	client_builder = client_builder.default_headers(default_headers);
 	for crt_file in root_certs.iter() {
-		#[cfg(feature = "crypto_openssl")]
-		{
-			let mut buff = Vec::new();
-			File::open(crt_file)
-				.map_err(|e| Error::from(e).prefix(crt_file))?
-				.read_to_end(&mut buff)?;
-			let crt = reqwest::Certificate::from_pem(&buff)?;
-			client_builder = client_builder.add_root_certificate(crt);
-		}
+		let mut buff = Vec::new();
+		File::open(crt_file)
+			.map_err(|e| Error::from(e).prefix(crt_file))?
+			.read_to_end(&mut buff)?;
+		let crt = reqwest::Certificate::from_pem(&buff)?;
+		client_builder = client_builder.add_root_certificate(crt);
 	}
-	Ok(client_builder.build()?)
+	Ok(client_builder)
 }
 
 pub async fn get(endpoint: &mut Endpoint, url: &str) -> Result<ValidHttpResponse, HttpError> {
@@ -1,6 +1,7 @@
+use crate::acme_dns::AcmeDnsServer;
 use crate::acme_proto::Challenge;
 use acme_common::error::Error;
-use acme_common::to_idna;
+use acme_common::{dns_name_to_idna, from_idna, to_idna};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
@@ -15,6 +16,16 @@ fn u8_to_nibbles_string(value: &u8) -> String {
 	format!("{first:x}.{second:x}")
 }
 
+/// Return the type and the canonical value of a subject alternative name:
+/// IP addresses are normalized and domain names are converted to their
+/// lowercase ASCII form, as identifiers are.
+pub fn parse_subject_alt_name(name: &str) -> Result<(IdentifierType, String), Error> {
+	match IpAddr::from_str(name) {
+		Ok(ip) => Ok((IdentifierType::Ip, ip.to_string())),
+		Err(_) => Ok((IdentifierType::Dns, to_idna(name)?)),
+	}
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize, Eq, PartialEq)]
 pub enum IdentifierType {
 	#[serde(rename = "dns")]
@@ -46,35 +57,108 @@ impl fmt::Display for IdentifierType {
 pub struct Identifier {
 	pub id_type: IdentifierType,
 	pub value: String,
-	pub challenge: Challenge,
+	pub challenges: Vec<Challenge>,
 	pub env: HashMap<String, String>,
+	pub dns_delegation: Option<String>,
+	pub acme_dns: Option<AcmeDnsServer>,
 }
 
 impl Identifier {
-	pub fn new(
+	pub fn new<S: AsRef<str>>(
 		id_type: IdentifierType,
 		value: &str,
-		challenge: &str,
+		challenges: &[S],
 		env: &HashMap<String, String>,
 	) -> Result<Self, Error> {
 		let value = match id_type {
 			IdentifierType::Dns => to_idna(value)?,
 			IdentifierType::Ip => IpAddr::from_str(value)?.to_string(),
 		};
-		let challenge = Challenge::from_str(challenge)?;
-		if !id_type.supported_challenges().contains(&challenge) {
-			let msg =
-				format!("challenge {challenge} cannot be used with identifier of type {id_type}");
-			return Err(msg.into());
+		if challenges.is_empty() {
+			return Err(format!("{value}: at least one challenge must be specified").into());
+		}
+		let mut challenge_lst = Vec::with_capacity(challenges.len());
+		for challenge in challenges.iter() {
+			let challenge = Challenge::from_str(challenge.as_ref())?;
+			if !id_type.supported_challenges().contains(&challenge) {
+				let msg = format!(
+					"challenge {challenge} cannot be used with identifier of type {id_type}"
+				);
+				return Err(msg.into());
+			}
+			if challenge_lst.contains(&challenge) {
+				let msg = format!("{value}: challenge {challenge} is specified more than once");
+				return Err(msg.into());
+			}
+			challenge_lst.push(challenge);
 		}
 		Ok(Identifier {
 			id_type,
 			value,
-			challenge,
+			challenges: challenge_lst,
 			env: env.clone(),
+			dns_delegation: None,
+			acme_dns: None,
 		})
 	}
 
+	/// Return the first challenge, in order of preference, that has not
+	/// been excluded and is offered by the server.
+	pub fn select_challenge<F>(&self, excluded: &[Challenge], is_offered: F) -> Option<Challenge>
+	where
+		F: Fn(&Challenge) -> bool,
+	{
+		self.challenges
+			.iter()
+			.find(|c| !excluded.contains(c) && is_offered(c))
+			.copied()
+	}
+
+	/// Return whether or not there is at least one challenge left once the
+	/// given ones are excluded.
+	pub fn has_fallback_challenge(&self, excluded: &[Challenge]) -> bool {
+		self.challenges.iter().any(|c| !excluded.contains(c))
+	}
+
+	fn check_dns_01(&self, option: &str) -> Result<(), Error> {
+		if self.id_type != IdentifierType::Dns || !self.challenges.contains(&Challenge::Dns01) {
+			let msg = format!(
+				"{}: {option} can only be used with the {} challenge",
+				self.value,
+				Challenge::Dns01
+			);
+			return Err(msg.into());
+		}
+		Ok(())
+	}
+
+	pub fn set_dns_delegation(&mut self, target: &str) -> Result<(), Error> {
+		self.check_dns_01("dns_delegation")?;
+		self.dns_delegation = Some(dns_name_to_idna(target.trim_end_matches('.'))?);
+		Ok(())
+	}
+
+	/// Return the value with internationalized domain names in their Unicode
+	/// form, for display purposes only.
+	pub fn unicode_value(&self) -> String {
+		match self.id_type {
+			IdentifierType::Dns => from_idna(&self.value).unwrap_or_else(|_| self.value.to_owned()),
+			IdentifierType::Ip => self.value.to_owned(),
+		}
+	}
+
+	pub fn set_acme_dns(&mut self, server: AcmeDnsServer) -> Result<(), Error> {
+		self.check_dns_01("acme_dns")?;
+		self.acme_dns = Some(server);
+		Ok(())
+	}
+
+	/// Name of the TXT record used by the dns-01 challenge, before any
+	/// delegation is taken into account.
+	pub fn get_dns_record_name(&self) -> String {
+		format!("_acme-challenge.{}", self.value.trim_start_matches("*."))
+	}
+
 	pub fn get_tls_alpn_name(&self) -> Result<String, Error> {
 		match &self.id_type {
 			IdentifierType::Dns => Ok(self.value.to_owned()),
@@ -108,7 +192,13 @@ impl Identifier {
 
 impl fmt::Display for Identifier {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-		write!(f, "{}: {} ({})", self.id_type, self.value, self.challenge)
+		let challenges = self
+			.challenges
+			.iter()
+			.map(|c| c.to_string())
+			.collect::<Vec<String>>()
+			.join(", ");
+		write!(f, "{}: {} ({challenges})", self.id_type, self.value)
 	}
 }
 
@@ -117,17 +207,42 @@ mod tests {
 	use super::*;
 	use std::collections::HashMap;
 
+	#[test]
+	fn test_dns_record_name() {
+		let env = HashMap::new();
+		let id = Identifier::new(IdentifierType::Dns, "example.org", &["dns-01"], &env).unwrap();
+		assert_eq!(&id.get_dns_record_name(), "_acme-challenge.example.org");
+		let id = Identifier::new(IdentifierType::Dns, "*.example.org", &["dns-01"], &env).unwrap();
+		assert_eq!(&id.get_dns_record_name(), "_acme-challenge.example.org");
+	}
+
+	#[test]
+	fn test_dns_delegation() {
+		let env = HashMap::new();
+		let mut id =
+			Identifier::new(IdentifierType::Dns, "example.org", &["dns-01"], &env).unwrap();
+		id.set_dns_delegation("example.org.acme.example.net.")
+			.unwrap();
+		assert_eq!(
+			id.dns_delegation,
+			Some("example.org.acme.example.net".to_string())
+		);
+		let mut id =
+			Identifier::new(IdentifierType::Dns, "example.org", &["http-01"], &env).unwrap();
+		assert!(id.set_dns_delegation("acme.example.net").is_err());
+	}
+
 	#[test]
 	fn test_ipv4_tls_alpn_name() {
 		let env = HashMap::new();
-		let id = Identifier::new(IdentifierType::Ip, "203.0.113.1", "http-01", &env).unwrap();
+		let id = Identifier::new(IdentifierType::Ip, "203.0.113.1", &["http-01"], &env).unwrap();
 		assert_eq!(&id.get_tls_alpn_name().unwrap(), "1.113.0.203.in-addr.arpa");
 	}
 
 	#[test]
 	fn test_ipv6_tls_alpn_name() {
 		let env = HashMap::new();
-		let id = Identifier::new(IdentifierType::Ip, "2001:db8::1", "http-01", &env).unwrap();
+		let id = Identifier::new(IdentifierType::Ip, "2001:db8::1", &["http-01"], &env).unwrap();
 		assert_eq!(
 			&id.get_tls_alpn_name().unwrap(),
 			"1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
@@ -135,7 +250,7 @@ mod tests {
 		let id = Identifier::new(
 			IdentifierType::Ip,
 			"4321:0:1:2:3:4:567:89ab",
-			"http-01",
+			&["http-01"],
 			&env,
 		)
 		.unwrap();
@@ -144,4 +259,68 @@ mod tests {
 			"b.a.9.8.7.6.5.0.4.0.0.0.3.0.0.0.2.0.0.0.1.0.0.0.0.0.0.0.1.2.3.4.ip6.arpa"
 		);
 	}
+
+	#[test]
+	fn test_parse_subject_alt_name() {
+		assert_eq!(
+			parse_subject_alt_name("Example.ORG").unwrap(),
+			(IdentifierType::Dns, "example.org".to_string())
+		);
+		assert_eq!(
+			parse_subject_alt_name("*.Exämple.org").unwrap(),
+			(IdentifierType::Dns, "*.xn--exmple-cua.org".to_string())
+		);
+		assert_eq!(
+			parse_subject_alt_name("203.0.113.1").unwrap(),
+			(IdentifierType::Ip, "203.0.113.1".to_string())
+		);
+		assert_eq!(
+			parse_subject_alt_name("2001:DB8:0::1").unwrap(),
+			(IdentifierType::Ip, "2001:db8::1".to_string())
+		);
+		assert!(parse_subject_alt_name("invalid_name.example.org").is_err());
+	}
+
+	#[test]
+	fn test_challenge_fallback() {
+		let env = HashMap::new();
+		let id = Identifier::new(
+			IdentifierType::Dns,
+			"example.org",
+			&["http-01", "dns-01"],
+			&env,
+		)
+		.unwrap();
+		assert_eq!(id.select_challenge(&[], |_| true), Some(Challenge::Http01));
+		assert_eq!(
+			id.select_challenge(&[], |c| *c == Challenge::Dns01),
+			Some(Challenge::Dns01)
+		);
+		assert_eq!(
+			id.select_challenge(&[Challenge::Http01], |_| true),
+			Some(Challenge::Dns01)
+		);
+		assert_eq!(
+			id.select_challenge(&[], |c| *c == Challenge::TlsAlpn01),
+			None
+		);
+		assert!(id.has_fallback_challenge(&[Challenge::Http01]));
+		assert!(!id.has_fallback_challenge(&[Challenge::Http01, Challenge::Dns01]));
+		let empty: &[&str] = &[];
+		assert!(Identifier::new(IdentifierType::Dns, "example.org", empty, &env).is_err());
+		assert!(Identifier::new(
+			IdentifierType::Dns,
+			"example.org",
+			&["dns-01", "dns-01"],
+			&env
+		)
+		.is_err());
+		assert!(Identifier::new(
+			IdentifierType::Ip,
+			"203.0.113.1",
+			&["http-01", "dns-01"],
+			&env
+		)
+		.is_err());
+	}
 }
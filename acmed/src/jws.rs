@@ -1,5 +1,5 @@
 use acme_common::b64_encode;
-use acme_common::crypto::{HashFunction, JwsSignatureAlgorithm, KeyPair};
+use acme_common::crypto::{HashFunction, JwsSignatureAlgorithm, Signer};
 use acme_common::error::Error;
 use serde::Serialize;
 use serde_json::value::Value;
@@ -24,7 +24,7 @@ struct JwsProtectedHeader {
 }
 
 fn get_jws_data(
-	key_pair: &KeyPair,
+	key_pair: &impl Signer,
 	sign_alg: &JwsSignatureAlgorithm,
 	protected: &str,
 	payload: &[u8],
@@ -44,7 +44,7 @@ fn get_jws_data(
 }
 
 pub fn encode_jwk(
-	key_pair: &KeyPair,
+	key_pair: &impl Signer,
 	sign_alg: &JwsSignatureAlgorithm,
 	payload: &[u8],
 	url: &str,
@@ -62,7 +62,7 @@ pub fn encode_jwk(
 }
 
 pub fn encode_kid(
-	key_pair: &KeyPair,
+	key_pair: &impl Signer,
 	sign_alg: &JwsSignatureAlgorithm,
 	key_id: &str,
 	payload: &[u8],
@@ -120,7 +120,34 @@ pub fn encode_kid_mac(
 #[cfg(test)]
 mod tests {
 	use super::{encode_jwk, encode_kid};
-	use acme_common::crypto::{gen_keypair, KeyType};
+	use acme_common::crypto::{gen_keypair, JwsSignatureAlgorithm, KeyType, Signer};
+	use acme_common::error::Error;
+	use serde_json::json;
+	use serde_json::value::Value;
+
+	struct DummySigner;
+
+	impl Signer for DummySigner {
+		fn key_type(&self) -> KeyType {
+			KeyType::EcdsaP256
+		}
+
+		fn sign(&self, _alg: &JwsSignatureAlgorithm, _data: &[u8]) -> Result<Vec<u8>, Error> {
+			Ok(b"dummy signature".to_vec())
+		}
+
+		fn jwk_public_key(&self) -> Result<Value, Error> {
+			Ok(json!({"kty": "dummy"}))
+		}
+
+		fn jwk_public_key_thumbprint(&self) -> Result<Value, Error> {
+			self.jwk_public_key()
+		}
+
+		fn public_key_to_der(&self) -> Result<Vec<u8>, Error> {
+			Ok(Vec::new())
+		}
+	}
 
 	#[test]
 	fn test_default_jwk() {
@@ -188,4 +215,23 @@ mod tests {
 		assert!(s.contains("\"signature\""));
 		assert!(s.contains(payload_b64));
 	}
+
+	#[test]
+	fn test_dummy_signer_jwk() {
+		let s = encode_jwk(
+			&DummySigner,
+			&JwsSignatureAlgorithm::Es256,
+			b"Dummy payload 1",
+			"https://example.org/",
+			None,
+		)
+		.unwrap();
+		let s: Value = serde_json::from_str(&s).unwrap();
+		assert_eq!(s["payload"], "RHVtbXkgcGF5bG9hZCAx");
+		assert_eq!(s["signature"], "ZHVtbXkgc2lnbmF0dXJl");
+		assert_eq!(
+			s["protected"],
+			"eyJhbGciOiJFUzI1NiIsImp3ayI6eyJrdHkiOiJkdW1teSJ9LCJ1cmwiOiJodHRwczovL2V4YW1wbGUub3JnLyJ9"
+		);
+	}
 }
@@ -1,6 +1,17 @@
+use log::Level;
+
 pub trait HasLogger {
 	fn warn(&self, msg: &str);
 	fn info(&self, msg: &str);
 	fn debug(&self, msg: &str);
 	fn trace(&self, msg: &str);
+
+	fn log(&self, level: Level, msg: &str) {
+		match level {
+			Level::Error | Level::Warn => self.warn(msg),
+			Level::Info => self.info(msg),
+			Level::Debug => self.debug(msg),
+			Level::Trace => self.trace(msg),
+		}
+	}
 }
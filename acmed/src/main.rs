@@ -11,9 +11,11 @@ use std::sync::Arc;
 use tokio::runtime::Builder;
 
 mod account;
+mod acme_dns;
 mod acme_proto;
 mod certificate;
 mod config;
+mod dns;
 mod duration;
 mod endpoint;
 mod hooks;
@@ -24,6 +26,7 @@ mod logs;
 mod main_event_loop;
 mod storage;
 mod template;
+mod timestamp;
 
 pub const APP_NAME: &str = "ACMEd";
 pub const APP_THREAD_NAME: &str = "acmed-runtime";
@@ -39,6 +42,11 @@ pub const DEFAULT_CERT_KEY_TYPE: KeyType = KeyType::Rsa2048;
 pub const DEFAULT_CERT_FILE_MODE: u32 = 0o644;
 pub const DEFAULT_CERT_RANDOM_EARLY_RENEW: u64 = 0; // default to not renewing early
 pub const DEFAULT_CERT_RENEW_DELAY: u64 = 30 * 24 * 60 * 60; // 30 days
+pub const DEFAULT_CERT_RENEW_LIFETIME_PERCENT: u32 = 33;
+pub const DEFAULT_CERT_EXPIRATION_WARNING: u64 = 7 * 24 * 60 * 60; // 7 days
+pub const DEFAULT_PRE_AUTHORIZATION_DELAY: u64 = 0;
+pub const DEFAULT_PRE_OPERATION_RETRY_DELAY: u64 = 60 * 60; // 1 hour
+pub const DEFAULT_EXPIRATION_CHECK_INTERVAL: u64 = 24 * 60 * 60; // 1 day
 pub const DEFAULT_PK_FILE_MODE: u32 = 0o600;
 pub const DEFAULT_ACCOUNT_FILE_MODE: u32 = 0o600;
 pub const DEFAULT_KP_REUSE: bool = false;
@@ -48,7 +56,20 @@ pub const DEFAULT_POOL_NB_TRIES: usize = 20;
 pub const DEFAULT_POOL_WAIT_SEC: u64 = 5;
 pub const DEFAULT_HTTP_FAIL_NB_RETRY: usize = 10;
 pub const DEFAULT_HTTP_FAIL_WAIT_SEC: u64 = 1;
+pub const DEFAULT_GROUP_PARALLEL: bool = false;
 pub const DEFAULT_HOOK_ALLOW_FAILURE: bool = false;
+pub const DEFAULT_HOOK_HTTP_METHOD: &str = "POST";
+pub const DEFAULT_HOOK_HTTP_RETRIES: usize = 0;
+pub const DEFAULT_HOOK_HTTP_RETRY_WAIT_SEC: u64 = 5;
+pub const DEFAULT_HOOK_HTTP_TLS_VERIFY: bool = true;
+pub const DEFAULT_HOOK_TIMEOUT: u64 = 0; // no timeout
+pub const DEFAULT_HOOK_KILL_DELAY_SEC: u64 = 5;
+pub const DEFAULT_HOOK_OUTPUT_TAIL_LINES: usize = 5;
+pub const DEFAULT_HOOK_OUTPUT_DRAIN_SEC: u64 = 2;
+pub const DEFAULT_DNS_PROPAGATION_CHECK: bool = false;
+pub const DEFAULT_DNS_PROPAGATION_TIMEOUT: u64 = 5 * 60; // 5 minutes
+pub const DEFAULT_DNS_PROPAGATION_WAIT_SEC: u64 = 5;
+pub const DEFAULT_DNS_RESOLVE_DELEGATION: bool = false;
 pub const MAX_RATE_LIMIT_SLEEP_MILISEC: u64 = 3_600_000;
 pub const MIN_RATE_LIMIT_SLEEP_MILISEC: u64 = 100;
 
@@ -141,6 +162,14 @@ async fn inner_main() {
 				.action(ArgAction::Append)
 				.value_name("FILE"),
 		)
+		.arg(
+			Arg::new("deactivate-authorizations")
+				.long("deactivate-authorizations")
+				.help("Deactivate the valid authorizations of the given identifiers and exit")
+				.num_args(1..)
+				.action(ArgAction::Append)
+				.value_name("IDENTIFIER"),
+		)
 		.get_matches();
 
 	match set_log_system(
@@ -166,6 +195,19 @@ async fn inner_main() {
 		.unwrap_or(DEFAULT_CONFIG_FILE);
 	let pid_file = matches.get_one::<String>("pid-file").map(|e| e.as_str());
 
+	if let Some(identifiers) = matches.get_many::<String>("deactivate-authorizations") {
+		let identifiers: Vec<String> = identifiers.map(|e| e.to_owned()).collect();
+		let res = match MainEventLoop::new(config_file, &root_certs).await {
+			Ok(srv) => srv.deactivate_authorizations(&identifiers).await,
+			Err(e) => Err(e),
+		};
+		if let Err(e) = res {
+			error!("{e}");
+			std::process::exit(1);
+		}
+		return;
+	}
+
 	init_server(matches.get_flag("foreground"), pid_file);
 
 	let mut srv = match MainEventLoop::new(config_file, &root_certs).await {
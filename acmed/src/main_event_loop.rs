@@ -1,20 +1,23 @@
-use crate::account::Account;
-use crate::acme_proto::request_certificate;
+use crate::account::{Account, AccountAuthorization};
+use crate::acme_proto::{authorization, refresh_directory, request_certificate};
 use crate::certificate::Certificate;
 use crate::config;
 use crate::endpoint::Endpoint;
 use crate::hooks::HookType;
 use crate::logs::HasLogger;
-use crate::storage::FileManager;
+use crate::storage::{get_certificate_metadata, set_pre_authorized, FileManager};
 use crate::{AccountSync, EndpointSync};
 use acme_common::error::Error;
+use acme_common::to_idna;
 use async_lock::RwLock;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::time::{Duration, SystemTime};
+use tokio::time::{sleep, sleep_until, Instant};
 
 pub struct MainEventLoop {
 	certificates: HashMap<String, Certificate>,
@@ -40,11 +43,19 @@ impl MainEventLoop {
 			HookType::ChallengeDns01Clean,
 			HookType::ChallengeTlsAlpn01,
 			HookType::ChallengeTlsAlpn01Clean,
+			HookType::PreOperation,
 			HookType::PostOperation,
+			HookType::PostOperationSuccess,
+			HookType::PostOperationFailure,
+			HookType::ExpirationWarning,
 		]
 		.into_iter()
 		.collect();
+		let account_hooks = vec![HookType::AccountCreated, HookType::AccountKeyRolled]
+			.into_iter()
+			.collect();
 
+		let key_passphrase = cnf.get_key_passphrase()?;
 		let mut accounts: HashMap<String, Account> = HashMap::new();
 		for acc in &cnf.account {
 			let fm = FileManager {
@@ -62,6 +73,7 @@ impl MainEventLoop {
 				pk_file_owner: cnf.get_pk_file_user(),
 				pk_file_group: cnf.get_pk_file_group(),
 				pk_file_ext: cnf.get_pk_file_ext(),
+				key_passphrase: key_passphrase.clone(),
 				hooks: acc
 					.get_hooks(&cnf)?
 					.iter()
@@ -70,7 +82,14 @@ impl MainEventLoop {
 					.collect(),
 				env: acc.env.clone(),
 			};
-			let account = acc.to_generic(&fm).await?;
+			let mut account = acc.to_generic(&fm).await?;
+			account.hooks = acc
+				.get_hooks(&cnf)?
+				.iter()
+				.filter(|h| !h.hook_type.is_disjoint(&account_hooks))
+				.map(|e| e.to_owned())
+				.collect();
+			account.env = acc.env.clone();
 			let name = acc.name.clone();
 			accounts.insert(name, account);
 		}
@@ -80,8 +99,11 @@ impl MainEventLoop {
 		for crt in cnf.certificate.iter() {
 			let endpoint = crt.get_endpoint(&cnf, root_certs)?;
 			let endpoint_name = endpoint.name.clone();
-			let crt_name = crt.get_crt_name()?;
-			let key_type = crt.get_key_type()?;
+			let identifiers = crt.get_identifiers(&cnf)?;
+			let crt_name = crt.get_crt_name(&identifiers)?;
+			let key_pair = crt.get_key_pair()?;
+			let external_key_type = crt.get_external_key_type(&key_pair, &key_passphrase)?;
+			let key_type = crt.get_key_type(external_key_type)?;
 			let hooks = crt.get_hooks(&cnf)?;
 			let fm = FileManager {
 				account_directory: cnf.get_account_dir(),
@@ -98,6 +120,7 @@ impl MainEventLoop {
 				pk_file_owner: cnf.get_pk_file_user(),
 				pk_file_group: cnf.get_pk_file_group(),
 				pk_file_ext: cnf.get_pk_file_ext(),
+				key_passphrase: key_passphrase.clone(),
 				hooks: hooks
 					.iter()
 					.filter(|h| !h.hook_type.is_disjoint(&file_hooks))
@@ -107,9 +130,12 @@ impl MainEventLoop {
 			};
 			let cert = Certificate {
 				account_name: crt.account.clone(),
-				identifiers: crt.get_identifiers()?,
+				identifiers,
 				subject_attributes: crt.subject_attributes.to_generic(),
 				key_type,
+				key_pair,
+				key_file: crt.key_file.clone(),
+				csr_file: crt.csr_file.clone(),
 				csr_digest: crt.get_csr_digest()?,
 				kp_reuse: crt.get_kp_reuse(),
 				endpoint_name: endpoint_name.clone(),
@@ -122,9 +148,19 @@ impl MainEventLoop {
 				env: crt.env.to_owned(),
 				random_early_renew: crt.get_random_early_renew(&cnf)?,
 				renew_delay: crt.get_renew_delay(&cnf)?,
+				expiration_warning: crt.get_expiration_warning(&cnf)?,
+				pre_authorization_delay: crt.get_pre_authorization_delay(&cnf)?,
+				pre_operation_retry_delay: crt.get_pre_operation_retry_delay(&cnf)?,
+				not_before: crt.get_not_before()?,
+				not_after: crt.get_not_after()?,
+				profile: crt.profile.clone(),
+				dns_propagation_check: crt.get_dns_propagation_check(&cnf),
+				dns_propagation_timeout: crt.get_dns_propagation_timeout(&cnf)?,
+				dns_resolve_delegation: crt.get_dns_resolve_delegation(&cnf),
 				file_manager: fm,
 			};
 			let crt_id = cert.get_id();
+			cert.get_csr().await.map_err(|e| e.prefix(&crt_id))?;
 			if certificates.contains_key(&crt_id) {
 				let msg = format!("{crt_id}: duplicate certificate id");
 				return Err(msg.into());
@@ -142,7 +178,7 @@ impl MainEventLoop {
 			certificates.insert(crt_id, cert);
 		}
 
-		Ok(MainEventLoop {
+		let srv = MainEventLoop {
 			certificates,
 			accounts: accounts
 				.iter()
@@ -152,7 +188,167 @@ impl MainEventLoop {
 				.iter()
 				.map(|(k, v)| (k.to_owned(), Arc::new(RwLock::new(v.to_owned()))))
 				.collect(),
-		})
+		};
+		srv.check_profiles().await?;
+		Ok(srv)
+	}
+
+	/// Check that the endpoints advertise the profiles requested by the
+	/// certificates. An endpoint which cannot be reached is not checked
+	/// since the profile is checked again before each order.
+	async fn check_profiles(&self) -> Result<(), Error> {
+		let mut refreshed = HashSet::new();
+		let mut unreachable = HashSet::new();
+		for crt in self.certificates.values() {
+			let profile = match &crt.profile {
+				Some(p) => p,
+				None => continue,
+			};
+			let endpoint_s = match self.endpoints.get(&crt.endpoint_name) {
+				Some(e) => e,
+				None => continue,
+			};
+			if unreachable.contains(&crt.endpoint_name) {
+				continue;
+			}
+			if !refreshed.contains(&crt.endpoint_name) {
+				if let Err(e) = refresh_directory(endpoint_s).await {
+					let msg = format!(
+						"endpoint \"{}\": unable to check the profiles",
+						crt.endpoint_name
+					);
+					log::warn!("{}", e.prefix(&msg).message);
+					unreachable.insert(crt.endpoint_name.clone());
+					continue;
+				}
+				refreshed.insert(crt.endpoint_name.clone());
+			}
+			endpoint_s
+				.read()
+				.await
+				.dir
+				.check_profile(profile)
+				.map_err(|e| e.prefix(&format!("certificate \"{crt}\"")))?;
+		}
+		Ok(())
+	}
+
+	/// Deactivate every tracked authorization that is still valid for one
+	/// of the given identifiers, as well as the authorizations of the orders
+	/// in progress related to those identifiers.
+	pub async fn deactivate_authorizations(&self, identifiers: &[String]) -> Result<(), Error> {
+		let identifiers = identifiers
+			.iter()
+			.map(|id| match IpAddr::from_str(id) {
+				Ok(ip) => Ok(ip.to_string()),
+				Err(_) => to_idna(id),
+			})
+			.collect::<Result<Vec<String>, Error>>()?;
+		let now = SystemTime::now();
+		let mut nb_deactivated = 0;
+		let mut nb_errors = 0;
+		let mut processed_urls = HashSet::new();
+		for account_s in self.accounts.values() {
+			let authorizations: Vec<AccountAuthorization> = account_s
+				.read()
+				.await
+				.authorizations
+				.iter()
+				.filter(|a| a.expires > now && identifiers.contains(&a.identifier))
+				.cloned()
+				.collect();
+			if authorizations.is_empty() {
+				continue;
+			}
+			for auth in authorizations.iter() {
+				processed_urls.insert(auth.url.to_owned());
+				let res = match self.endpoints.get(&auth.endpoint) {
+					Some(endpoint_s) => {
+						authorization::deactivate_authorization(account_s, endpoint_s, &auth.url)
+							.await
+					}
+					None => Err(format!("\"{}\": unknown endpoint", auth.endpoint).into()),
+				};
+				let account = account_s.read().await;
+				match res {
+					Ok(_) => {
+						account.info(&format!(
+							"{}: authorization deactivated on endpoint \"{}\"",
+							auth.identifier, auth.endpoint
+						));
+						nb_deactivated += 1;
+					}
+					Err(e) => {
+						account.warn(&e.prefix(&auth.identifier).message);
+						nb_errors += 1;
+					}
+				};
+			}
+			account_s.read().await.save().await?;
+		}
+		// Authorizations are tracked once valid, hence those of the orders
+		// in progress are read from the orders themselves.
+		for crt in self.certificates.values() {
+			if !crt
+				.identifiers
+				.iter()
+				.any(|i| identifiers.contains(&i.value))
+			{
+				continue;
+			}
+			let pending_order = match get_certificate_metadata(&crt.file_manager).await {
+				Ok(metadata) => match metadata.and_then(|m| m.pending_order) {
+					Some(o) => o,
+					None => continue,
+				},
+				Err(e) => {
+					crt.warn(&e.message);
+					nb_errors += 1;
+					continue;
+				}
+			};
+			let account_s = match self.accounts.get(&crt.account_name) {
+				Some(a) => a,
+				None => continue,
+			};
+			let endpoint_s = match self.endpoints.get(&pending_order.endpoint) {
+				Some(e) => e,
+				None => continue,
+			};
+			for url in pending_order.authorizations.iter() {
+				if !processed_urls.insert(url.to_owned()) {
+					continue;
+				}
+				match authorization::deactivate_order_authorization(
+					account_s,
+					endpoint_s,
+					url,
+					&identifiers,
+				)
+				.await
+				{
+					Ok(Some(id)) => {
+						crt.info(&format!(
+							"{id}: authorization deactivated on endpoint \"{}\"",
+							pending_order.endpoint
+						));
+						nb_deactivated += 1;
+					}
+					Ok(None) => {}
+					Err(e) => {
+						crt.warn(&e.prefix(url).message);
+						nb_errors += 1;
+					}
+				};
+			}
+			account_s.read().await.save().await?;
+		}
+		if nb_errors > 0 {
+			let msg = format!("{nb_errors} authorization(s) could not be deactivated");
+			return Err(msg.into());
+		}
+		log::info!("{nb_deactivated} authorization(s) deactivated");
+		Ok(())
 	}
 
 	pub async fn run(&mut self) {
@@ -187,7 +383,7 @@ async fn renew_certificate(
 	loop {
 		match certificate.schedule_renewal().await {
 			Ok(duration) => {
-				sleep(duration).await;
+				wait_for_renewal(certificate, &account_s, &endpoint_s, duration).await;
 				break;
 			}
 			Err(e) => {
@@ -200,6 +396,13 @@ async fn renew_certificate(
 			}
 		}
 	}
+	if let Err(e) = certificate.call_pre_operation_hooks().await {
+		let e = e.prefix("renewal vetoed by a pre-operation hook");
+		certificate.warn(&e.message);
+		check_expiration(certificate).await;
+		sleep(certificate.pre_operation_retry_delay).await;
+		return (certificate, account_s, endpoint_s);
+	}
 	let (status, is_success) =
 		match request_certificate(certificate, account_s.clone(), endpoint_s.clone()).await {
 			Ok(_) => ("success".to_string(), true),
@@ -219,5 +422,91 @@ async fn renew_certificate(
 			certificate.warn(&e.message);
 		}
 	};
+	let res = if is_success {
+		certificate.call_post_operation_success_hooks().await
+	} else {
+		certificate.call_post_operation_failure_hooks(&status).await
+	};
+	if let Err(e) = res {
+		let e = e.prefix("post-operation hook error");
+		certificate.warn(&e.message);
+	}
+	if !is_success {
+		check_expiration(certificate).await;
+	}
 	(certificate, account_s.clone(), endpoint_s.clone())
 }
+
+/// Wait until the renewal time, pre-authorizing the identifiers when the
+/// pre-authorization delay is reached and checking the certificate's
+/// expiration at regular intervals. Since whether or not the identifiers
+/// have been pre-authorized is stored in the certificate's metadata, they
+/// are pre-authorized right away if the delay has been reached while ACMEd
+/// was not running.
+async fn wait_for_renewal(
+	certificate: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	duration: Duration,
+) {
+	let renewal_time = Instant::now() + duration;
+	let delay = certificate.pre_authorization_delay;
+	let mut pre_authorization_time = if !delay.is_zero() && !is_pre_authorized(certificate).await {
+		Some(renewal_time - duration.min(delay))
+	} else {
+		None
+	};
+	let check_interval = Duration::from_secs(crate::DEFAULT_EXPIRATION_CHECK_INTERVAL);
+	loop {
+		let now = Instant::now();
+		if now >= renewal_time {
+			return;
+		}
+		check_expiration(certificate).await;
+		let mut wake_up = renewal_time.min(now + check_interval);
+		if let Some(t) = pre_authorization_time {
+			wake_up = wake_up.min(t);
+		}
+		sleep_until(wake_up).await;
+		if let Some(t) = pre_authorization_time {
+			if Instant::now() >= t {
+				pre_authorize(certificate, account_s, endpoint_s, delay).await;
+				pre_authorization_time = None;
+			}
+		}
+	}
+}
+
+async fn is_pre_authorized(certificate: &Certificate) -> bool {
+	match get_certificate_metadata(&certificate.file_manager).await {
+		Ok(metadata) => metadata.is_some_and(|m| m.pre_authorized),
+		Err(e) => {
+			certificate.warn(&e.message);
+			false
+		}
+	}
+}
+
+async fn pre_authorize(
+	certificate: &Certificate,
+	account_s: &AccountSync,
+	endpoint_s: &EndpointSync,
+	delay: Duration,
+) {
+	let valid_until = SystemTime::now() + delay;
+	let res =
+		match authorization::pre_authorize(certificate, account_s, endpoint_s, valid_until).await {
+			Ok(_) => set_pre_authorized(&certificate.file_manager).await,
+			Err(e) => Err(e.prefix("unable to pre-authorize the identifiers")),
+		};
+	if let Err(e) = res {
+		certificate.warn(&e.message);
+	}
+}
+
+async fn check_expiration(certificate: &Certificate) {
+	if let Err(e) = certificate.check_expiration().await {
+		let e = e.prefix("expiration warning hook error");
+		certificate.warn(&e.message);
+	}
+}
@@ -2,9 +2,12 @@ use crate::hooks::{self, FileStorageHookData, Hook, HookEnvData, HookType};
 use crate::logs::HasLogger;
 use crate::template::render_template;
 use acme_common::b64_encode;
-use acme_common::crypto::{KeyPair, X509Certificate};
+use acme_common::crypto::{
+	decrypt_data, encrypt_data, is_encrypted_data, CertificateInspector, Csr, HashFunction,
+	KeyPair, Signer, X509Certificate,
+};
 use acme_common::error::Error;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::path::{Path, PathBuf};
@@ -27,10 +30,39 @@ pub struct FileManager {
 	pub pk_file_owner: Option<String>,
 	pub pk_file_group: Option<String>,
 	pub pk_file_ext: Option<String>,
+	pub key_passphrase: Option<Passphrase>,
 	pub hooks: Vec<Hook>,
 	pub env: HashMap<String, String>,
 }
 
+/// Secret used to encrypt the private keys and the account files. Its value
+/// is never displayed.
+#[derive(Clone, PartialEq)]
+pub struct Passphrase(Vec<u8>);
+
+impl Passphrase {
+	pub fn new(passphrase: &[u8]) -> Result<Self, Error> {
+		let passphrase = passphrase
+			.strip_suffix(b"\n")
+			.map(|p| p.strip_suffix(b"\r").unwrap_or(p))
+			.unwrap_or(passphrase);
+		if passphrase.is_empty() {
+			return Err("the key passphrase must not be empty".into());
+		}
+		Ok(Passphrase(passphrase.to_vec()))
+	}
+
+	pub fn as_bytes(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+impl fmt::Debug for Passphrase {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Passphrase(<redacted>)")
+	}
+}
+
 impl HasLogger for FileManager {
 	fn warn(&self, msg: &str) {
 		log::warn!("{self}: {msg}");
@@ -65,6 +97,7 @@ enum FileType {
 	Account,
 	PrivateKey,
 	Certificate,
+	Metadata,
 }
 
 impl fmt::Display for FileType {
@@ -73,11 +106,51 @@ impl fmt::Display for FileType {
 			FileType::Account => "account",
 			FileType::PrivateKey => "pk",
 			FileType::Certificate => "crt",
+			FileType::Metadata => "meta",
 		};
 		write!(f, "{s}")
 	}
 }
 
+/// Information about the current certificate that cannot be retrieved from
+/// the certificate itself, as well as the state of its renewal: whether or
+/// not the identifiers have been pre-authorized and the order in progress.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct CertificateMetadata {
+	pub profile: Option<String>,
+	#[serde(default)]
+	pub pre_authorized: bool,
+	pub pending_order: Option<PendingOrder>,
+}
+
+/// Order which has been created but for which the certificate has not been
+/// downloaded yet. The private key is only set when a new one has been
+/// generated for this order, in which case it replaces the current one once
+/// the certificate is issued.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PendingOrder {
+	pub endpoint: String,
+	pub url: String,
+	pub authorizations: Vec<String>,
+	pub private_key: Option<String>,
+	#[serde(default)]
+	pub settings: OrderSettings,
+}
+
+/// Settings the order depends on. The order in progress is only resumed if
+/// they did not change since it has been created.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct OrderSettings {
+	pub identifiers: Vec<String>,
+	pub not_before: Option<String>,
+	pub not_after: Option<String>,
+	pub profile: Option<String>,
+	pub key_type: String,
+	pub key_uri: Option<String>,
+	pub key_file: Option<String>,
+	pub csr_file: Option<String>,
+}
+
 #[derive(Clone, Serialize)]
 pub struct CertFileFormat {
 	pub ext: String,
@@ -86,6 +159,43 @@ pub struct CertFileFormat {
 	pub name: String,
 }
 
+impl FileManager {
+	fn get_crt_id(&self) -> String {
+		if self.crt_name.is_empty() {
+			String::new()
+		} else {
+			format!("{}_{}", self.crt_name, self.crt_key_type)
+		}
+	}
+}
+
+fn to_hex(data: &[u8]) -> String {
+	data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Return the fingerprint and, for certificates, the serial number of a
+/// file's content.
+fn get_file_summary(
+	fm: &FileManager,
+	file_type: &FileType,
+	data: &[u8],
+) -> Result<(String, String), Error> {
+	match file_type {
+		FileType::Account | FileType::Metadata => {
+			Ok((to_hex(&HashFunction::Sha256.hash(data)), String::new()))
+		}
+		FileType::PrivateKey => {
+			let key = private_key_from_pem(fm, data)?;
+			let fingerprint = HashFunction::Sha256.hash(&key.public_key_to_der()?);
+			Ok((to_hex(&fingerprint), String::new()))
+		}
+		FileType::Certificate => {
+			let crt = X509Certificate::from_pem(data)?;
+			Ok((to_hex(&crt.fingerprint()?), crt.serial_number()?))
+		}
+	}
+}
+
 fn get_file_full_path(
 	fm: &FileManager,
 	file_type: FileType,
@@ -94,11 +204,13 @@ fn get_file_full_path(
 		FileType::Account => &fm.account_directory,
 		FileType::PrivateKey => &fm.crt_directory,
 		FileType::Certificate => &fm.crt_directory,
+		FileType::Metadata => &fm.crt_directory,
 	};
 	let ext = match file_type {
 		FileType::Account => "bin".to_string(),
 		FileType::PrivateKey => fm.pk_file_ext.clone().unwrap_or("pem".to_string()),
 		FileType::Certificate => fm.cert_file_ext.clone().unwrap_or("pem".to_string()),
+		FileType::Metadata => "json".to_string(),
 	};
 	let file_name = match file_type {
 		FileType::Account => format!(
@@ -107,7 +219,7 @@ fn get_file_full_path(
 			file_type = file_type,
 			ext = ext
 		),
-		FileType::PrivateKey | FileType::Certificate => {
+		FileType::PrivateKey | FileType::Certificate | FileType::Metadata => {
 			let fmt_data = CertFileFormat {
 				key_type: fm.crt_key_type.to_string(),
 				ext,
@@ -142,8 +254,8 @@ fn set_owner(fm: &FileManager, path: &Path, file_type: FileType) -> Result<(), E
 	let (uid, gid) = match file_type {
 		FileType::Certificate => (fm.cert_file_owner.to_owned(), fm.cert_file_group.to_owned()),
 		FileType::PrivateKey => (fm.pk_file_owner.to_owned(), fm.pk_file_group.to_owned()),
-		FileType::Account => {
-			// The account file does not need to be accessible to users other different from the current one.
+		FileType::Account | FileType::Metadata => {
+			// The account and metadata files do not need to be accessible to users other different from the current one.
 			return Ok(());
 		}
 	};
@@ -191,21 +303,76 @@ fn set_owner(fm: &FileManager, path: &Path, file_type: FileType) -> Result<(), E
 	}
 }
 
-async fn write_file(fm: &FileManager, file_type: FileType, data: &[u8]) -> Result<(), Error> {
-	let (file_directory, file_name, path) = get_file_full_path(fm, file_type.clone())?;
+async fn get_file_hook_data(
+	fm: &FileManager,
+	file_type: &FileType,
+	file_directory: String,
+	file_name: String,
+	path: &Path,
+	is_new: bool,
+	data: &[u8],
+) -> FileStorageHookData {
+	let (old_fingerprint, old_serial) = if is_new {
+		(String::new(), String::new())
+	} else {
+		let old_summary = match read_file(fm, path).await {
+			Ok(old_data) => get_file_summary(fm, file_type, &old_data),
+			Err(e) => Err(e),
+		};
+		old_summary.unwrap_or_else(|e| {
+			fm.debug(&e.prefix("unable to inspect the previous file").message);
+			(String::new(), String::new())
+		})
+	};
+	let (new_fingerprint, new_serial) = get_file_summary(fm, file_type, data).unwrap_or_else(|e| {
+		fm.debug(&e.prefix("unable to inspect the new file").message);
+		(String::new(), String::new())
+	});
 	let mut hook_data = FileStorageHookData {
+		operation: if is_new { "create" } else { "edit" }.to_string(),
+		file_type: file_type.to_string(),
+		certificate_id: fm.get_crt_id(),
 		file_name,
 		file_directory,
 		file_path: path.to_owned(),
+		old_fingerprint,
+		old_serial,
+		new_fingerprint,
+		new_serial,
 		env: HashMap::new(),
 	};
 	hook_data.set_env(&fm.env);
+	hook_data
+}
+
+async fn write_file(fm: &FileManager, file_type: FileType, data: &[u8]) -> Result<(), Error> {
+	let (file_directory, file_name, path) = get_file_full_path(fm, file_type.clone())?;
 	let is_new = !path.is_file();
+	// The metadata file is internal to ACMEd and is written several times per
+	// order, hence it does not invoke the file hooks.
+	let hook_data = match file_type {
+		FileType::Metadata => None,
+		_ => Some(
+			get_file_hook_data(
+				fm,
+				&file_type,
+				file_directory,
+				file_name,
+				&path,
+				is_new,
+				data,
+			)
+			.await,
+		),
+	};
 
-	if is_new {
-		hooks::call(fm, &fm.hooks, &hook_data, HookType::FilePreCreate).await?;
-	} else {
-		hooks::call(fm, &fm.hooks, &hook_data, HookType::FilePreEdit).await?;
+	if let Some(hook_data) = &hook_data {
+		let hook_type = if is_new {
+			HookType::FilePreCreate
+		} else {
+			HookType::FilePreEdit
+		};
+		hooks::call(fm, &fm.hooks, hook_data, hook_type).await?;
 	}
 
 	fm.trace(&format!("writing file {path:?}"));
@@ -214,11 +381,12 @@ async fn write_file(fm: &FileManager, file_type: FileType, data: &[u8]) -> Resul
 		options.mode(match &file_type {
 			FileType::Certificate => fm.cert_file_mode,
 			FileType::PrivateKey => fm.pk_file_mode,
-			FileType::Account => crate::DEFAULT_ACCOUNT_FILE_MODE,
+			FileType::Account | FileType::Metadata => crate::DEFAULT_ACCOUNT_FILE_MODE,
 		});
 		options
 			.write(true)
 			.create(true)
+			.truncate(true)
 			.open(&path)
 			.await
 			.map_err(|e| Error::from(e).prefix(&path.display().to_string()))?
@@ -234,21 +402,57 @@ async fn write_file(fm: &FileManager, file_type: FileType, data: &[u8]) -> Resul
 		set_owner(fm, &path, file_type).map_err(|e| e.prefix(&path.display().to_string()))?;
 	}
 
-	if is_new {
-		hooks::call(fm, &fm.hooks, &hook_data, HookType::FilePostCreate).await?;
-	} else {
-		hooks::call(fm, &fm.hooks, &hook_data, HookType::FilePostEdit).await?;
+	if let Some(hook_data) = &hook_data {
+		let hook_type = if is_new {
+			HookType::FilePostCreate
+		} else {
+			HookType::FilePostEdit
+		};
+		hooks::call(fm, &fm.hooks, hook_data, hook_type).await?;
 	}
 	Ok(())
 }
 
+/// Serialize a private key as PEM. If a passphrase is set, the key is
+/// encrypted.
+pub fn private_key_to_pem(fm: &FileManager, key_pair: &KeyPair) -> Result<Vec<u8>, Error> {
+	match &fm.key_passphrase {
+		Some(passphrase) => key_pair.private_key_to_pem_passphrase(passphrase.as_bytes()),
+		None => key_pair.private_key_to_pem(),
+	}
+}
+
+/// Load a private key from a PEM which may be encrypted. Unencrypted keys
+/// are always accepted so existing files can still be read after a
+/// passphrase has been set.
+pub fn private_key_from_pem(fm: &FileManager, data: &[u8]) -> Result<KeyPair, Error> {
+	match &fm.key_passphrase {
+		Some(passphrase) => KeyPair::from_pem_passphrase(data, passphrase.as_bytes()),
+		None => KeyPair::from_pem(data),
+	}
+}
+
 pub async fn get_account_data(fm: &FileManager) -> Result<Vec<u8>, Error> {
 	let path = get_file_path(fm, FileType::Account)?;
-	read_file(fm, &path).await
+	let data = read_file(fm, &path).await?;
+	if !is_encrypted_data(&data) {
+		return Ok(data);
+	}
+	let passphrase = fm.key_passphrase.as_ref().ok_or_else(|| {
+		Error::from("the account file is encrypted but no key passphrase has been set")
+			.prefix(&path.display().to_string())
+	})?;
+	decrypt_data(passphrase.as_bytes(), &data).map_err(|e| e.prefix(&path.display().to_string()))
 }
 
 pub async fn set_account_data(fm: &FileManager, data: &[u8]) -> Result<(), Error> {
-	write_file(fm, FileType::Account, data).await
+	match &fm.key_passphrase {
+		Some(passphrase) => {
+			let data = encrypt_data(passphrase.as_bytes(), data)?;
+			write_file(fm, FileType::Account, &data).await
+		}
+		None => write_file(fm, FileType::Account, data).await,
+	}
 }
 
 pub async fn get_keypair_path(fm: &FileManager) -> Result<PathBuf, Error> {
@@ -258,15 +462,27 @@ pub async fn get_keypair_path(fm: &FileManager) -> Result<PathBuf, Error> {
 pub async fn get_keypair(fm: &FileManager) -> Result<KeyPair, Error> {
 	let path = get_keypair_path(fm).await?;
 	let raw_key = read_file(fm, &path).await?;
-	let key = KeyPair::from_pem(&raw_key)?;
+	let key = private_key_from_pem(fm, &raw_key)?;
 	Ok(key)
 }
 
 pub async fn set_keypair(fm: &FileManager, key_pair: &KeyPair) -> Result<(), Error> {
-	let data = key_pair.private_key_to_pem()?;
+	let data = private_key_to_pem(fm, key_pair)?;
 	write_file(fm, FileType::PrivateKey, &data).await
 }
 
+/// Read a private key which is not managed by ACMEd.
+pub async fn get_external_keypair(fm: &FileManager, path: &Path) -> Result<KeyPair, Error> {
+	let raw_key = read_file(fm, path).await?;
+	private_key_from_pem(fm, &raw_key).map_err(|e| e.prefix(&path.display().to_string()))
+}
+
+/// Read a certificate signing request which is not managed by ACMEd.
+pub async fn get_external_csr(fm: &FileManager, path: &Path) -> Result<Csr, Error> {
+	let raw_csr = read_file(fm, path).await?;
+	Csr::from_pem(&raw_csr).map_err(|e| e.prefix(&path.display().to_string()))
+}
+
 pub async fn get_certificate_path(fm: &FileManager) -> Result<PathBuf, Error> {
 	get_file_path(fm, FileType::Certificate)
 }
@@ -282,6 +498,51 @@ pub async fn write_certificate(fm: &FileManager, data: &[u8]) -> Result<(), Erro
 	write_file(fm, FileType::Certificate, data).await
 }
 
+/// Return the metadata of the current certificate, or `None` if no metadata
+/// file exists, which is the case if the certificate has been issued by a
+/// version of ACMEd that did not record them.
+pub async fn get_certificate_metadata(
+	fm: &FileManager,
+) -> Result<Option<CertificateMetadata>, Error> {
+	let path = get_file_path(fm, FileType::Metadata)?;
+	if !path.is_file() {
+		return Ok(None);
+	}
+	let data = read_file(fm, &path).await?;
+	serde_json::from_slice(&data)
+		.map(Some)
+		.map_err(|e| Error::from(e).prefix(&path.display().to_string()))
+}
+
+pub async fn set_certificate_metadata(
+	fm: &FileManager,
+	metadata: &CertificateMetadata,
+) -> Result<(), Error> {
+	let data = serde_json::to_vec(metadata)?;
+	write_file(fm, FileType::Metadata, &data).await
+}
+
+pub async fn set_pending_order(
+	fm: &FileManager,
+	pending_order: Option<&PendingOrder>,
+) -> Result<(), Error> {
+	let mut metadata = get_certificate_metadata(fm).await?.unwrap_or_default();
+	if metadata.pending_order.as_ref() == pending_order {
+		return Ok(());
+	}
+	metadata.pending_order = pending_order.cloned();
+	set_certificate_metadata(fm, &metadata).await
+}
+
+pub async fn set_pre_authorized(fm: &FileManager) -> Result<(), Error> {
+	let mut metadata = get_certificate_metadata(fm).await?.unwrap_or_default();
+	if metadata.pre_authorized {
+		return Ok(());
+	}
+	metadata.pre_authorized = true;
+	set_certificate_metadata(fm, &metadata).await
+}
+
 fn check_files(fm: &FileManager, file_types: &[FileType]) -> bool {
 	for t in file_types.iter().cloned() {
 		let path = match get_file_path(fm, t) {
@@ -306,7 +567,63 @@ pub fn account_files_exists(fm: &FileManager) -> bool {
 	check_files(fm, &file_types)
 }
 
-pub fn certificate_files_exists(fm: &FileManager) -> bool {
-	let file_types = vec![FileType::PrivateKey, FileType::Certificate];
+/// Check whether the certificate file and, if managed by ACMEd, the private
+/// key file exist.
+pub fn certificate_files_exists(fm: &FileManager, with_private_key: bool) -> bool {
+	let file_types = if with_private_key {
+		vec![FileType::PrivateKey, FileType::Certificate]
+	} else {
+		vec![FileType::Certificate]
+	};
 	check_files(fm, &file_types)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::{CertificateMetadata, OrderSettings, Passphrase, PendingOrder};
+
+	#[test]
+	fn test_certificate_metadata() {
+		let metadata: CertificateMetadata = serde_json::from_str("{}").unwrap();
+		assert_eq!(metadata, CertificateMetadata::default());
+		let metadata: CertificateMetadata =
+			serde_json::from_str("{\"profile\":\"shortlived\"}").unwrap();
+		assert_eq!(metadata.profile, Some("shortlived".to_string()));
+		assert!(!metadata.pre_authorized);
+		assert!(metadata.pending_order.is_none());
+		let metadata = CertificateMetadata {
+			profile: None,
+			pre_authorized: true,
+			pending_order: Some(PendingOrder {
+				endpoint: "test".to_string(),
+				url: "https://example.org/acme/order/1".to_string(),
+				authorizations: vec!["https://example.org/acme/authz/1".to_string()],
+				private_key: None,
+				settings: OrderSettings {
+					identifiers: vec!["dns:example.org".to_string()],
+					profile: Some("shortlived".to_string()),
+					key_type: "ecdsa_p256".to_string(),
+					..Default::default()
+				},
+			}),
+		};
+		let data = serde_json::to_string(&metadata).unwrap();
+		let parsed: CertificateMetadata = serde_json::from_str(&data).unwrap();
+		assert_eq!(parsed, metadata);
+	}
+
+	#[test]
+	fn test_passphrase() {
+		let p = Passphrase::new(b"secret").unwrap();
+		assert_eq!(p.as_bytes(), b"secret");
+		let p = Passphrase::new(b"secret\n").unwrap();
+		assert_eq!(p.as_bytes(), b"secret");
+		let p = Passphrase::new(b"secret\r\n").unwrap();
+		assert_eq!(p.as_bytes(), b"secret");
+		let p = Passphrase::new(b" secret \n\n").unwrap();
+		assert_eq!(p.as_bytes(), b" secret \n");
+		assert!(Passphrase::new(b"").is_err());
+		assert!(Passphrase::new(b"\n").is_err());
+		assert_eq!(format!("{p:?}"), "Passphrase(<redacted>)");
+	}
+}
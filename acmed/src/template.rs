@@ -1,4 +1,5 @@
 use acme_common::error::Error;
+use acme_common::from_idna;
 use minijinja::{value::Value, Environment};
 use serde::Serialize;
 
@@ -10,12 +11,20 @@ fn formatter_rev_labels(value: Value) -> Result<Value, minijinja::Error> {
 	}
 }
 
+fn formatter_unicode(value: Value) -> Result<Value, minijinja::Error> {
+	match value.as_str().map(from_idna) {
+		Some(Ok(name)) => Ok(name.into()),
+		_ => Ok(value),
+	}
+}
+
 pub fn render_template<T>(template: &str, data: &T) -> Result<String, Error>
 where
 	T: Serialize,
 {
 	let mut environment = Environment::new();
 	environment.add_filter("rev_labels", formatter_rev_labels);
+	environment.add_filter("unicode", formatter_unicode);
 	environment.add_template("template", template)?;
 	let template = environment.get_template("template")?;
 	Ok(template.render(data)?)
@@ -57,4 +66,20 @@ mod tests {
 		let rendered = rendered.unwrap();
 		assert_eq!(rendered, "mx1.example.org - org.example.mx1");
 	}
+
+	#[test]
+	fn test_formatter_unicode() {
+		let c = TplTest {
+			foo: String::from("*.xn--hlo-bma.example.org"),
+			bar: 42,
+		};
+		let tpl = "{{ foo }} - {{ foo | unicode }} - {{ bar | unicode }}";
+		let rendered = render_template(tpl, &c);
+		assert!(rendered.is_ok());
+		let rendered = rendered.unwrap();
+		assert_eq!(
+			rendered,
+			"*.xn--hlo-bma.example.org - *.hélo.example.org - 42"
+		);
+	}
 }
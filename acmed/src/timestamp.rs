@@ -0,0 +1,129 @@
+use crate::duration::parse_duration;
+use acme_common::error::Error;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Parse an RFC 3339 timestamp, as used by ACME (RFC 8555, section 7.1).
+/// Fractions of seconds are ignored.
+pub fn parse_rfc3339(input: &str) -> Result<SystemTime, Error> {
+	match OffsetDateTime::parse(input, &Rfc3339).map(|t| t.unix_timestamp()) {
+		Ok(ts) if ts >= 0 => Ok(UNIX_EPOCH + Duration::from_secs(ts as u64)),
+		_ => Err(format!("{input}: invalid timestamp").into()),
+	}
+}
+
+/// Format a time as an RFC 3339 timestamp in UTC.
+pub fn format_rfc3339(time: &SystemTime) -> String {
+	let time = OffsetDateTime::from(*time).replace_nanosecond(0);
+	time.ok()
+		.and_then(|t| t.format(&Rfc3339).ok())
+		.unwrap_or_default()
+}
+
+/// A point in time, either absolute or relative to the moment it is used.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimeReference {
+	Absolute(SystemTime),
+	Relative(Duration),
+}
+
+impl TimeReference {
+	pub fn resolve(&self, now: SystemTime) -> SystemTime {
+		match self {
+			TimeReference::Absolute(t) => *t,
+			TimeReference::Relative(d) => now + *d,
+		}
+	}
+}
+
+impl fmt::Display for TimeReference {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			TimeReference::Absolute(t) => write!(f, "{}", format_rfc3339(t)),
+			TimeReference::Relative(d) => write!(f, "+{}s", d.as_secs()),
+		}
+	}
+}
+
+impl FromStr for TimeReference {
+	type Err = Error;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if let Ok(t) = parse_rfc3339(s) {
+			return Ok(TimeReference::Absolute(t));
+		}
+		match parse_duration(s) {
+			Ok(d) => Ok(TimeReference::Relative(d)),
+			Err(_) => Err(format!("{s}: invalid timestamp or duration").into()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format_rfc3339, parse_rfc3339, TimeReference};
+	use std::str::FromStr;
+	use std::time::{Duration, UNIX_EPOCH};
+
+	#[test]
+	fn test_parse_rfc3339() {
+		let t = parse_rfc3339("1970-01-01T00:00:00Z").unwrap();
+		assert_eq!(t, UNIX_EPOCH);
+		let t = parse_rfc3339("2016-01-20T14:09:07.99Z").unwrap();
+		assert_eq!(t, UNIX_EPOCH + Duration::from_secs(1_453_298_947));
+		let t = parse_rfc3339("2016-01-20T16:09:07+02:00").unwrap();
+		assert_eq!(t, UNIX_EPOCH + Duration::from_secs(1_453_298_947));
+		let t = parse_rfc3339("2024-02-29t23:59:59z").unwrap();
+		assert_eq!(t, UNIX_EPOCH + Duration::from_secs(1_709_251_199));
+		let t = parse_rfc3339("2000-02-29T00:00:00Z").unwrap();
+		assert_eq!(t, UNIX_EPOCH + Duration::from_secs(951_782_400));
+	}
+
+	#[test]
+	fn test_parse_rfc3339_invalid() {
+		for ts in [
+			"",
+			"2016-01-20",
+			"2016-01-20T14:09:07",
+			"2016-13-20T14:09:07Z",
+			"2016-00-20T14:09:07Z",
+			"2016-01-00T14:09:07Z",
+			"2016-01-32T14:09:07Z",
+			"2025-02-29T00:00:00Z",
+			"2025-02-31T00:00:00Z",
+			"2025-04-31T00:00:00Z",
+			"1900-02-29T00:00:00Z",
+			"2016-01-20T24:09:07Z",
+			"2016-01-20T14:09:07Zx",
+			"1969-12-31T23:59:59Z",
+		] {
+			assert!(parse_rfc3339(ts).is_err(), "{}", ts);
+		}
+	}
+
+	#[test]
+	fn test_format_rfc3339() {
+		assert_eq!(format_rfc3339(&UNIX_EPOCH), "1970-01-01T00:00:00Z");
+		let t = UNIX_EPOCH + Duration::from_secs(1_709_251_199);
+		assert_eq!(format_rfc3339(&t), "2024-02-29T23:59:59Z");
+		let s = "2031-07-04T08:30:12Z";
+		assert_eq!(format_rfc3339(&parse_rfc3339(s).unwrap()), s);
+	}
+
+	#[test]
+	fn test_time_reference() {
+		let t = TimeReference::from_str("2016-01-20T14:09:07Z").unwrap();
+		let abs = UNIX_EPOCH + Duration::from_secs(1_453_298_947);
+		assert_eq!(t, TimeReference::Absolute(abs));
+		assert_eq!(t.resolve(UNIX_EPOCH), abs);
+		assert_eq!(t.to_string(), "2016-01-20T14:09:07Z");
+		let t = TimeReference::from_str("1d12h").unwrap();
+		assert_eq!(t, TimeReference::Relative(Duration::from_secs(129_600)));
+		assert_eq!(t.resolve(abs), abs + Duration::from_secs(129_600));
+		assert_eq!(t.to_string(), "+129600s");
+		assert!(TimeReference::from_str("tomorrow").is_err());
+	}
+}
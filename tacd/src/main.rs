@@ -1,8 +1,12 @@
 #[cfg(feature = "crypto_openssl")]
 mod openssl_server;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+mod rustls_server;
 
 #[cfg(feature = "crypto_openssl")]
 use crate::openssl_server::start as server_start;
+#[cfg(all(feature = "crypto_rustcrypto", not(feature = "crypto_openssl")))]
+use crate::rustls_server::start as server_start;
 use acme_common::crypto::{get_lib_name, get_lib_version, HashFunction, KeyType, X509Certificate};
 use acme_common::logs::{set_log_system, DEFAULT_LOG_LEVEL};
 use acme_common::{clean_pid_file, to_idna};
@@ -1,7 +1,9 @@
-use acme_common::crypto::{KeyPair, X509Certificate};
-use anyhow::{bail, Result};
+use acme_common::crypto::{CertificateInspector, KeyPair};
+use anyhow::{anyhow, bail, Result};
 use log::debug;
+use openssl::pkey::PKey;
 use openssl::ssl::{self, AlpnError, SslAcceptor, SslMethod};
+use openssl::x509::X509;
 use std::net::TcpListener;
 use std::sync::Arc;
 use std::thread;
@@ -26,14 +28,21 @@ macro_rules! listen_and_accept {
 	};
 }
 
-pub fn start(listen_addr: &str, certificate: &X509Certificate, key_pair: &KeyPair) -> Result<()> {
+pub fn start(
+	listen_addr: &str,
+	certificate: &impl CertificateInspector,
+	key_pair: &KeyPair,
+) -> Result<()> {
+	let private_key =
+		PKey::private_key_from_der(&key_pair.private_key_to_der().map_err(|e| anyhow!(e))?)?;
+	let certificate = X509::from_der(&certificate.to_der().map_err(|e| anyhow!(e))?)?;
 	let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
 	acceptor.set_alpn_select_callback(|_, client| {
 		debug!("ALPN negociation");
 		ssl::select_next_proto(crate::ALPN_ACME_PROTO_NAME, client).ok_or(ALPN_ERROR)
 	});
-	acceptor.set_private_key(&key_pair.inner_key)?;
-	acceptor.set_certificate(&certificate.inner_cert)?;
+	acceptor.set_private_key(&private_key)?;
+	acceptor.set_certificate(&certificate)?;
 	acceptor.check_private_key()?;
 	let acceptor = Arc::new(acceptor.build());
 	if cfg!(unix) && listen_addr.starts_with("unix:") {
@@ -0,0 +1,89 @@
+use acme_common::crypto::{CertificateInspector, KeyPair, KeyType};
+use anyhow::{anyhow, bail, Result};
+use log::debug;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+
+#[cfg(target_family = "unix")]
+use std::os::unix::net::UnixListener;
+
+macro_rules! listen_and_accept {
+	($lt: ident, $addr: ident, $config: ident) => {
+		let listener = $lt::bind($addr)?;
+		for stream in listener.incoming() {
+			if let Ok(stream) = stream {
+				let config = $config.clone();
+				thread::spawn(move || {
+					debug!("new client");
+					if let Ok(conn) = ServerConnection::new(config) {
+						let mut tls_stream = StreamOwned::new(conn, stream);
+						let _ = tls_stream.conn.complete_io(&mut tls_stream.sock);
+					}
+				});
+			};
+		}
+	};
+}
+
+// The certificate's acmeIdentifier extension is critical and therefore
+// rejected by the certificate parser used by `with_single_cert`, hence this
+// resolver which always returns the same certificate without any check.
+#[derive(Debug)]
+struct SingleCertResolver(Arc<CertifiedKey>);
+
+impl ResolvesServerCert for SingleCertResolver {
+	fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+		Some(self.0.clone())
+	}
+}
+
+pub fn start(
+	listen_addr: &str,
+	certificate: &impl CertificateInspector,
+	key_pair: &KeyPair,
+) -> Result<()> {
+	// The ring crypto provider implements neither ECDSA using the P-521 curve
+	// nor ML-DSA.
+	let is_supported = match key_pair.key_type {
+		KeyType::EcdsaP521 => false,
+		#[cfg(feature = "pqc")]
+		KeyType::MlDsa44 | KeyType::MlDsa65 | KeyType::MlDsa87 => false,
+		_ => true,
+	};
+	if !is_supported {
+		bail!(
+			"{}: key type not supported by the TLS server",
+			key_pair.key_type
+		);
+	}
+	let provider = Arc::new(rustls::crypto::ring::default_provider());
+	let cert_chain = vec![CertificateDer::from(
+		certificate.to_der().map_err(|e| anyhow!(e))?,
+	)];
+	let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+		key_pair.private_key_to_der().map_err(|e| anyhow!(e))?,
+	));
+	let signing_key = provider.key_provider.load_private_key(key)?;
+	let resolver = SingleCertResolver(Arc::new(CertifiedKey::new(cert_chain, signing_key)));
+	let mut config = ServerConfig::builder_with_provider(provider)
+		.with_safe_default_protocol_versions()?
+		.with_no_client_auth()
+		.with_cert_resolver(Arc::new(resolver));
+	// The ALPN protocol name is stored in its wire format, which is prefixed by its length.
+	config.alpn_protocols = vec![crate::ALPN_ACME_PROTO_NAME[1..].to_vec()];
+	let config = Arc::new(config);
+	if cfg!(unix) && listen_addr.starts_with("unix:") {
+		let listen_addr = &listen_addr[5..];
+		debug!("listening on unix socket {listen_addr}");
+		listen_and_accept!(UnixListener, listen_addr, config);
+	} else {
+		debug!("listening on {listen_addr}");
+		listen_and_accept!(TcpListener, listen_addr, config);
+	}
+	bail!("main thread loop unexpectedly exited")
+}